@@ -0,0 +1,112 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use gram_core::extract::entity::extract_entity;
+use gram_core::extract::username::extract_usernames;
+use gram_core::format::Message;
+use grammers_tl_types::enums::MessageEntity;
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::io::Write;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Kind {
+    /// 用户名和被提及的用户ID
+    Usernames,
+    /// 链接, 包括文本中的链接和TextUrl的目标
+    Links,
+    /// 话题标签
+    Hashtags,
+}
+
+/// 单条消息的提取结果, 为(类别, 值)
+fn extract(kind: Kind, msg: &Message) -> Result<Vec<(&'static str, String)>> {
+    let ret = match kind {
+        Kind::Usernames => {
+            let (usernames, user_ids) = extract_usernames(&msg.text, Some(msg.entities.clone()))?;
+            let usernames = usernames.into_iter().collect::<BTreeSet<_>>();
+            let user_ids = user_ids.into_iter().collect::<BTreeSet<_>>();
+            usernames
+                .into_iter()
+                .map(|x| ("username", x))
+                .chain(user_ids.into_iter().map(|x| ("user_id", x.to_string())))
+                .collect()
+        }
+        Kind::Links => {
+            let mut links = vec![];
+            for ent in &msg.entities {
+                match ent {
+                    MessageEntity::Url(_) => {
+                        links.extend(extract_entity(&msg.text, ent)?.map(String::from))
+                    }
+                    MessageEntity::TextUrl(x) => links.push(x.url.clone()),
+                    _ => {}
+                }
+            }
+            links.into_iter().map(|x| ("link", x)).collect()
+        }
+        Kind::Hashtags => {
+            let mut hashtags = vec![];
+            for ent in &msg.entities {
+                if let MessageEntity::Hashtag(_) = ent {
+                    hashtags.extend(extract_entity(&msg.text, ent)?.map(String::from));
+                }
+            }
+            hashtags.into_iter().map(|x| ("hashtag", x)).collect()
+        }
+    };
+    Ok(ret)
+}
+
+/// 每条消息输出一行, 如`{"id": 1, "usernames": [...], "user_ids": [...]}`
+pub fn write_jsonl(
+    kind: Kind,
+    messages: impl IntoIterator<Item = Result<Message>>,
+    mut writer: impl Write,
+) -> Result<()> {
+    for msg in messages {
+        let msg = &msg?;
+        let items = extract(kind, msg)?;
+        let values = |k: &str| {
+            items
+                .iter()
+                .filter(|x| x.0 == k)
+                .map(|x| x.1.clone())
+                .collect::<Vec<_>>()
+        };
+        let line = match kind {
+            Kind::Usernames => json!({
+                "id": msg.id,
+                "usernames": values("username"),
+                "user_ids": values("user_id")
+                    .iter()
+                    .filter_map(|x| x.parse::<i64>().ok())
+                    .collect::<Vec<_>>(),
+            }),
+            Kind::Links => json!({ "id": msg.id, "links": values("link") }),
+            Kind::Hashtags => json!({ "id": msg.id, "hashtags": values("hashtag") }),
+        };
+        serde_json::to_writer(&mut writer, &line)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// 每个提取结果输出一行, 列为`id,kind,value`
+pub fn write_csv(
+    kind: Kind,
+    messages: impl IntoIterator<Item = Result<Message>>,
+    writer: impl Write,
+) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["id", "kind", "value"])?;
+    for msg in messages {
+        let msg = &msg?;
+        let id = msg.id.map(|x| x.to_string()).unwrap_or_default();
+        for (k, v) in extract(kind, msg)? {
+            writer.write_record([id.as_str(), k, v.as_str()])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use anyhow::{Context, Result, anyhow};
+use gram_core::format::{Format, Message};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// 输入文件, `-`为标准输入
+    /// 支持JSON Lines, 以及Telegram Desktop导出的`result.json`
+    #[arg(short, long, default_value = "-")]
+    pub input: String,
+    /// 输出文件, `-`为标准输出
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+}
+
+/// 逐条读取的消息
+pub type Messages = Box<dyn Iterator<Item = Result<Message>>>;
+
+/// 逐条读取消息
+/// 按JSON Lines逐行读取, 不会将整个输入载入内存;
+/// 首行是包含`messages`列表的JSON对象, 或首行不是完整的JSON时(即桌面端导出), 读取全部内容后取该列表
+pub fn read_messages(input: &str, format: Format) -> Result<Messages> {
+    let reader: Box<dyn BufRead> = if input == "-" {
+        Box::new(std::io::stdin().lock())
+    } else {
+        let file = File::open(input).with_context(|| format!("failed to open {input}"))?;
+        Box::new(BufReader::new(file))
+    };
+
+    let mut lines = reader
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map_or(true, |x| !x.trim().is_empty()));
+    let Some((i, first)) = lines.next() else {
+        return Ok(Box::new(std::iter::empty()));
+    };
+    let first = first?;
+    let value = match serde_json::from_str::<Value>(&first) {
+        Ok(value) => match from_export(format, value) {
+            Ok(messages) => return Ok(messages),
+            Err(value) => value,
+        },
+        Err(e) => {
+            let mut content = first;
+            for (_, line) in lines {
+                content.push('\n');
+                content.push_str(&line?);
+            }
+            return serde_json::from_str(&content)
+                .ok()
+                .and_then(|value| from_export(format, value).ok())
+                .ok_or_else(|| anyhow!("line {}: {e}", i + 1));
+        }
+    };
+
+    let first = Message::from_value(format, &value).map_err(|e| anyhow!("line {}: {e}", i + 1));
+    let rest = lines.map(move |(i, line)| {
+        let value = serde_json::from_str(&line?).map_err(|e| anyhow!("line {}: {e}", i + 1))?;
+        Message::from_value(format, &value).map_err(|e| anyhow!("line {}: {e}", i + 1))
+    });
+    Ok(Box::new(std::iter::once(first).chain(rest)))
+}
+
+/// 读取桌面端导出中的`messages`列表, 不是桌面端导出时原样返回`value`
+fn from_export(format: Format, mut value: Value) -> Result<Messages, Value> {
+    let Some(messages) = value.get_mut("messages").and_then(Value::as_array_mut) else {
+        return Err(value);
+    };
+    let messages = std::mem::take(messages);
+    Ok(Box::new(
+        messages
+            .into_iter()
+            .map(move |x| Message::from_value(format, &x)),
+    ))
+}
+
+pub fn open_output(output: &str) -> Result<Box<dyn Write>> {
+    if output == "-" {
+        Ok(Box::new(BufWriter::new(std::io::stdout().lock())))
+    } else {
+        let file = File::create(output).with_context(|| format!("failed to create {output}"))?;
+        Ok(Box::new(BufWriter::new(file)))
+    }
+}
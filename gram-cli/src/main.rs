@@ -0,0 +1,174 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand, ValueEnum};
+use gram_core::format::Format;
+use gram_core::render::color::parse_color;
+use gram_core::render::layout::{Align, LayoutOptions};
+use image::{DynamicImage, Rgba};
+use std::io::{Cursor, Read, Write};
+
+mod extract;
+mod io;
+
+/// 离线处理Telegram消息导出的命令行工具
+#[derive(Parser)]
+#[command(name = "gram", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 从消息中提取用户名、链接或话题标签
+    Extract {
+        kind: extract::Kind,
+        /// 输入消息的格式: telethon, botapi, desktop, html
+        #[arg(long, default_value = "telethon")]
+        from: Format,
+        /// 输出格式
+        #[arg(long, value_enum, default_value_t = Output::Jsonl)]
+        output_format: Output,
+        #[command(flatten)]
+        io: io::Args,
+    },
+    /// 将文本渲染为PNG图片, 或SVG、PDF矢量文件
+    Render {
+        /// 待渲染文本, 未提供时从标准输入读取
+        text: Option<String>,
+        /// 字体尺寸
+        #[arg(long, default_value_t = 72.)]
+        scale: f32,
+        /// 最大行宽(像素), 超出时自动换行
+        #[arg(long)]
+        max_width: Option<f32>,
+        /// 行距倍数
+        #[arg(long, default_value_t = 1.)]
+        line_spacing: f32,
+        /// 对齐方式: left, center, right
+        #[arg(long, default_value = "left")]
+        align: Align,
+        /// 输出模式, `rgba`模式下彩色emoji保留字体自带的颜色; `svg`、`pdf`输出矢量路径
+        #[arg(long, value_enum, default_value_t = Mode::L)]
+        mode: Mode,
+        /// 前景色, 不用于`l`模式
+        #[arg(long, default_value = "black", value_parser = parse_color)]
+        foreground: Rgba<u8>,
+        /// 背景色, 可为`transparent`, 不用于`l`模式
+        #[arg(long, default_value = "white", value_parser = parse_color)]
+        background: Rgba<u8>,
+        /// 输出文件, `-`为标准输出
+        #[arg(short, long)]
+        output: String,
+    },
+    /// 在不同消息格式之间转换
+    Convert {
+        /// 输入格式: telethon, botapi, desktop, html
+        #[arg(long)]
+        from: Format,
+        /// 输出格式: telethon, botapi, desktop, html
+        #[arg(long)]
+        to: Format,
+        #[command(flatten)]
+        io: io::Args,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Mode {
+    /// 白底黑字的灰度图
+    L,
+    Rgba,
+    Svg,
+    Pdf,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Output {
+    Jsonl,
+    Csv,
+}
+
+fn main() -> Result<()> {
+    match Cli::parse().command {
+        Command::Extract {
+            kind,
+            from,
+            output_format,
+            io,
+        } => {
+            let messages = io::read_messages(&io.input, from)?;
+            let writer = io::open_output(&io.output)?;
+            match output_format {
+                Output::Jsonl => extract::write_jsonl(kind, messages, writer),
+                Output::Csv => extract::write_csv(kind, messages, writer),
+            }
+        }
+        Command::Render {
+            text,
+            scale,
+            max_width,
+            line_spacing,
+            align,
+            mode,
+            foreground,
+            background,
+            output,
+        } => {
+            let text = match text {
+                Some(text) => text,
+                None => {
+                    let mut text = String::new();
+                    std::io::stdin().read_to_string(&mut text)?;
+                    text.trim_end_matches(['\r', '\n']).to_string()
+                }
+            };
+            let options = LayoutOptions {
+                max_width,
+                line_spacing,
+                align,
+                ..Default::default()
+            };
+            let img: DynamicImage = match mode {
+                Mode::L => gram_core::render::render_text(&text, scale, &options).into(),
+                Mode::Rgba => gram_core::render::render_text_rgba(
+                    &text, scale, &options, foreground, background,
+                )
+                .into(),
+                Mode::Svg | Mode::Pdf => {
+                    let buf = match mode {
+                        Mode::Svg => gram_core::render::render_svg(
+                            &text, scale, &options, foreground, background,
+                        )
+                        .into_bytes(),
+                        _ => gram_core::render::render_pdf(
+                            &text, scale, &options, foreground, background,
+                        ),
+                    };
+                    match output.as_str() {
+                        "-" => std::io::stdout().write_all(&buf)?,
+                        path => std::fs::write(path, buf)?,
+                    }
+                    return Ok(());
+                }
+            };
+            if output == "-" {
+                let mut buf = Vec::new();
+                img.write_to(&mut Cursor::new(&mut buf), image::ImageFormat::Png)?;
+                std::io::stdout().write_all(&buf)?;
+            } else {
+                img.save(&output)?;
+            }
+            Ok(())
+        }
+        Command::Convert { from, to, io } => {
+            let messages = io::read_messages(&io.input, from)?;
+            let mut writer = io::open_output(&io.output)?;
+            for msg in messages {
+                serde_json::to_writer(&mut writer, &msg?.to_value(to)?)?;
+                writeln!(writer)?;
+            }
+            writer.flush()?;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use serde_json::{Value, json};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+/// Bot API格式的输入, 每行一条消息
+const MESSAGES: &str = concat!(
+    r#"{"id": 1, "text": "hi @alice see t.me/durov #news", "entities": ["#,
+    r#"{"type": "mention", "offset": 3, "length": 6}, "#,
+    r#"{"type": "text_link", "offset": 10, "length": 3, "url": "https://example.com"}, "#,
+    r#"{"type": "hashtag", "offset": 25, "length": 5}]}"#,
+    "\n",
+    r#"{"id": 2, "text": "bold", "entities": [{"type": "bold", "offset": 0, "length": 4}]}"#,
+    "\n",
+);
+
+/// 运行`gram`, `stdin`写入标准输入
+fn gram(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_gram"))
+        .args(args)
+        .env_remove("RUST_BACKTRACE")
+        .env_remove("RUST_LIB_BACKTRACE")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout_lines(output: &Output) -> Vec<Value> {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone())
+        .unwrap()
+        .lines()
+        .map(|x| serde_json::from_str(x).unwrap())
+        .collect()
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("gram-cli-{}-{name}", std::process::id()))
+}
+
+#[test]
+fn test_extract() {
+    let args = ["extract", "usernames", "--from", "botapi"];
+    assert_eq!(
+        stdout_lines(&gram(&args, MESSAGES)),
+        [
+            json!({"id": 1, "usernames": ["alice", "durov"], "user_ids": []}),
+            json!({"id": 2, "usernames": [], "user_ids": []}),
+        ]
+    );
+    let args = ["extract", "links", "--from", "botapi"];
+    assert_eq!(
+        stdout_lines(&gram(&args, MESSAGES))[0],
+        json!({"id": 1, "links": ["https://example.com"]})
+    );
+    let args = ["extract", "hashtags", "--from", "botapi"];
+    assert_eq!(
+        stdout_lines(&gram(&args, MESSAGES))[0],
+        json!({"id": 1, "hashtags": ["#news"]})
+    );
+
+    // 从文件读取, 输出CSV到文件
+    let (input, output) = (temp_path("in.jsonl"), temp_path("out.csv"));
+    std::fs::write(&input, MESSAGES).unwrap();
+    let args = [
+        "extract",
+        "usernames",
+        "--from",
+        "botapi",
+        "--output-format",
+        "csv",
+        "-i",
+        input.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+    ];
+    assert!(gram(&args, "").status.success());
+    assert_eq!(
+        std::fs::read_to_string(&output).unwrap(),
+        "id,kind,value\n1,username,alice\n1,username,durov\n"
+    );
+    std::fs::remove_file(input).unwrap();
+    std::fs::remove_file(output).unwrap();
+}
+
+#[test]
+fn test_extract_export() {
+    // 桌面端导出, 格式化为多行或压缩为一行
+    let export = json!({
+        "name": "chat",
+        "messages": [
+            {"id": 1, "text_entities": [
+                {"type": "plain", "text": "hi "},
+                {"type": "mention", "text": "@alice"},
+            ]},
+            {"id": 2, "text_entities": [{"type": "plain", "text": "bye"}]},
+        ],
+    });
+    let expected = [
+        json!({"id": 1, "usernames": ["alice"], "user_ids": []}),
+        json!({"id": 2, "usernames": [], "user_ids": []}),
+    ];
+    let args = ["extract", "usernames", "--from", "desktop"];
+    for input in [
+        serde_json::to_string_pretty(&export).unwrap(),
+        export.to_string(),
+    ] {
+        assert_eq!(stdout_lines(&gram(&args, &input)), expected);
+    }
+    assert!(stdout_lines(&gram(&args, "\n\n")).is_empty());
+}
+
+#[test]
+fn test_extract_errors() {
+    // 输入错误时退出码为1, 并指出行号
+    let output = gram(&["extract", "usernames", "--from", "botapi"], "{}\nnope\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("line 2"));
+    let output = gram(&["extract", "usernames", "--from", "botapi"], "\n{\n}\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("line 2"));
+
+    let output = gram(
+        &["extract", "usernames", "-i", "/nonexistent/gram.jsonl"],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("failed to open"));
+
+    // 参数错误时退出码为2
+    let output = gram(&["extract", "emails"], "");
+    assert_eq!(output.status.code(), Some(2));
+    let output = gram(&["extract", "usernames", "--from", "xml"], "");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_convert() {
+    let args = ["convert", "--from", "botapi", "--to", "html"];
+    assert_eq!(
+        stdout_lines(&gram(&args, MESSAGES)),
+        [
+            json!({"id": 1, "html": "hi @alice <a href=\"https://example.com\">see</a> t.me/durov #news"}),
+            json!({"id": 2, "html": "<b>bold</b>"}),
+        ]
+    );
+
+    // 转换为telethon再转换回Bot API, 结果不变
+    let args = ["convert", "--from", "botapi", "--to", "telethon"];
+    let output = gram(&args, MESSAGES);
+    assert_eq!(
+        stdout_lines(&output)[1],
+        json!({
+            "_": "Message",
+            "id": 2,
+            "message": "bold",
+            "entities": [{"_": "MessageEntityBold", "offset": 0, "length": 4}],
+        })
+    );
+    let telethon = String::from_utf8(output.stdout).unwrap();
+    let args = ["convert", "--from", "telethon", "--to", "botapi"];
+    let lines = stdout_lines(&gram(&args, &telethon));
+    assert_eq!(lines[1]["text"], "bold");
+    assert_eq!(lines[1]["entities"][0]["type"], "bold");
+
+    let output = gram(&["convert", "--from", "botapi"], MESSAGES);
+    assert_eq!(output.status.code(), Some(2));
+    let output = gram(&["convert", "--from", "html", "--to", "botapi"], "[1]\n");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_render() {
+    let path = temp_path("render.png");
+    let output = gram(
+        &[
+            "render",
+            "hi",
+            "--scale",
+            "32",
+            "-o",
+            path.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert!(output.status.success());
+    let img = image::open(&path).unwrap();
+    assert!(img.width() > 0 && img.height() > 0);
+    assert_eq!(img.color(), image::ColorType::L8);
+    std::fs::remove_file(path).unwrap();
+
+    // 未提供文本时从标准输入读取, 输出到标准输出
+    let args = [
+        "render",
+        "--mode",
+        "rgba",
+        "--background",
+        "transparent",
+        "-o",
+        "-",
+    ];
+    let output = gram(&args, "hi\n");
+    assert!(output.status.success());
+    let img = image::load_from_memory(&output.stdout).unwrap();
+    assert_eq!(img.color(), image::ColorType::Rgba8);
+
+    let output = gram(&["render", "hi", "--mode", "svg", "-o", "-"], "");
+    assert!(output.status.success());
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .starts_with("<svg")
+    );
+    let output = gram(&["render", "hi", "--mode", "pdf", "-o", "-"], "");
+    assert!(output.stdout.starts_with(b"%PDF"));
+
+    let output = gram(&["render", "hi", "--foreground", "red", "-o", "-"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid color"));
+    let output = gram(&["render", "hi", "--align", "middle", "-o", "-"], "");
+    assert_eq!(output.status.code(), Some(2));
+    let output = gram(&["render", "hi", "-o", "/nonexistent/gram.png"], "");
+    assert_eq!(output.status.code(), Some(1));
+}
@@ -0,0 +1,31 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use gram_core::render::layout::LayoutOptions;
+use gram_core::render::render_text;
+use std::hint::black_box;
+
+/// 渲染10k个类似昵称的短字符串
+fn render_names(c: &mut Criterion) {
+    let names = (0..10_000)
+        .map(|i| match i % 4 {
+            0 => format!("user_{i}"),
+            1 => format!("Алексей {i}"),
+            2 => format!("नमस्ते {i}"),
+            _ => format!("שלום {i} 😀"),
+        })
+        .collect::<Vec<_>>();
+    let options = LayoutOptions::default();
+
+    let mut group = c.benchmark_group("render");
+    group.sample_size(10);
+    group.bench_function("10k short strings", |b| {
+        b.iter(|| {
+            for name in &names {
+                black_box(render_text(name, 32., &options));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, render_names);
+criterion_main!(benches);
@@ -0,0 +1,191 @@
+use crate::extract::entity::utf16_range_to_utf8;
+use crate::unicode::{is_invisible, is_variation_selector};
+use grammers_tl_types as tl;
+use tl::enums::MessageEntity;
+use unicode_normalization::UnicodeNormalization;
+
+pub mod lsh;
+pub mod minhash;
+pub mod simhash;
+
+pub use lsh::LshIndex;
+pub use minhash::MinHash;
+pub use simhash::SimHash;
+
+/// 链接、提及等实体在归一化文本中的占位符
+/// 这类实体每次转发时常被替换, 不应影响相似度
+const PLACEHOLDER: char = '\u{FFFC}';
+
+/// 字符shingle的长度
+const SHINGLE: usize = 3;
+
+/// 输入消息文本和消息entities
+/// 输出用于相似度计算的归一化文本:
+/// - 链接、提及、邮箱、电话等实体替换为占位符, 自定义emoji删除
+/// - NFKC归一化并转小写, 消除全角等变体
+/// - 删除零宽字符、emoji变体选择符和肤色修饰符
+/// - 标点和空白折叠为单个空格
+pub fn normalize(message: &str, entities: &[MessageEntity]) -> String {
+    // 需要替换的实体区间, 以UTF-8字节计
+    let mut spans = entities
+        .iter()
+        .filter_map(|ent| {
+            let (offset, length, replace) = match ent {
+                MessageEntity::Url(tl::types::MessageEntityUrl { offset, length })
+                | MessageEntity::Mention(tl::types::MessageEntityMention { offset, length })
+                | MessageEntity::Email(tl::types::MessageEntityEmail { offset, length })
+                | MessageEntity::Phone(tl::types::MessageEntityPhone { offset, length })
+                | MessageEntity::BankCard(tl::types::MessageEntityBankCard { offset, length }) => {
+                    (offset, length, true)
+                }
+                MessageEntity::MentionName(tl::types::MessageEntityMentionName {
+                    offset,
+                    length,
+                    ..
+                }) => (offset, length, true),
+                MessageEntity::CustomEmoji(tl::types::MessageEntityCustomEmoji {
+                    offset,
+                    length,
+                    ..
+                }) => (offset, length, false),
+                _ => return None,
+            };
+            // 长度越界直接忽略
+            let (l, r) = utf16_range_to_utf8(message, *offset as usize, *length as usize).ok()?;
+            Some((l, r, replace))
+        })
+        .collect::<Vec<_>>();
+    spans.sort();
+
+    let mut stripped = String::with_capacity(message.len());
+    let mut cursor = 0;
+    for (l, r, replace) in spans {
+        // 跳过重叠区间
+        if l < cursor {
+            continue;
+        }
+        stripped.push_str(&message[cursor..l]);
+        if replace {
+            stripped.push(' ');
+            stripped.push(PLACEHOLDER);
+            stripped.push(' ');
+        }
+        cursor = r;
+    }
+    stripped.push_str(&message[cursor..]);
+
+    let mut ret = String::with_capacity(stripped.len());
+    let mut last_space = true;
+    for c in stripped.nfkc().flat_map(char::to_lowercase) {
+        if is_invisible(c) || is_variation_selector(c) || is_skin_tone(c) {
+            continue;
+        }
+        if c.is_whitespace() || (c.is_ascii_punctuation() || is_general_punctuation(c)) {
+            if !last_space {
+                ret.push(' ');
+                last_space = true;
+            }
+            continue;
+        }
+        ret.push(c);
+        last_space = false;
+    }
+    ret.truncate(ret.trim_end().len());
+    ret
+}
+
+/// 将归一化文本切分为字符shingle
+/// 文本短于shingle长度时整体作为一个shingle
+pub fn shingles(normalized: &str) -> impl Iterator<Item = &str> {
+    let bounds = normalized
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(normalized.len()))
+        .collect::<Vec<_>>();
+    let count = bounds.len().saturating_sub(SHINGLE).max(1);
+    (0..count).map(move |i| {
+        let r = bounds[(i + SHINGLE).min(bounds.len() - 1)];
+        &normalized[bounds[i]..r]
+    })
+}
+
+/// 64位FNV-1a哈希
+/// 签名需要跨进程、跨版本保持稳定, 因此不使用标准库的`DefaultHasher`
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn is_skin_tone(c: char) -> bool {
+    matches!(c, '\u{1F3FB}'..='\u{1F3FF}')
+}
+
+fn is_general_punctuation(c: char) -> bool {
+    matches!(c, '\u{2000}'..='\u{206F}' | '\u{3000}'..='\u{303F}' | '\u{FF01}'..='\u{FF0F}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let text = "ＢＵＹ\u{200B} now!!! ❤\u{FE0F} 👍🏽 @seller_1";
+        let entities = vec![MessageEntity::Mention(tl::types::MessageEntityMention {
+            offset: 20,
+            length: 9,
+        })];
+        assert_eq!(normalize(text, &entities), "buy now ❤ 👍 \u{FFFC}");
+    }
+
+    #[test]
+    fn test_near_duplicate() {
+        let a = "Best crypto signals, 500% profit guaranteed! Join our VIP channel today";
+        let b = "Best crypto signals, 500% profit guaranteed!! Join our VIP channel today!";
+        let c = "Does anyone know a good place for dinner near the station?";
+
+        let (a, b, c) = (normalize(a, &[]), normalize(b, &[]), normalize(c, &[]));
+        assert_eq!(a, b);
+        let b = b.replace("today", "now");
+
+        let sa = SimHash::new(&a);
+        assert!(sa.similarity(&SimHash::new(&b)) > sa.similarity(&SimHash::new(&c)));
+
+        let ma = MinHash::new(&a);
+        assert!(ma.similarity(&MinHash::new(&b)) > 0.7);
+        assert!(ma.similarity(&MinHash::new(&c)) < 0.2);
+    }
+
+    #[test]
+    fn test_index() {
+        for threshold in [f64::NAN, 0., -0.5, 1.01, f64::INFINITY] {
+            assert!(LshIndex::new(threshold).is_err());
+        }
+        assert!(LshIndex::new(1.).is_ok());
+
+        let mut index = LshIndex::new(0.8).unwrap();
+        index.insert(
+            1,
+            &normalize(
+                "Cheap followers and likes, DM me now for the price list",
+                &[],
+            ),
+        );
+        index.insert(
+            2,
+            &normalize("Meeting moved to Thursday, please update calendars", &[]),
+        );
+
+        let hits = index.query(&normalize(
+            "cheap followers & likes!!! DM me now for the price list",
+            &[],
+        ));
+        assert_eq!(hits.iter().map(|x| x.0).collect::<Vec<_>>(), vec![1]);
+        assert!(
+            index
+                .query(&normalize("totally unrelated sentence here", &[]))
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use super::fnv1a;
+use super::minhash::{MinHash, NUM_PERM};
+use anyhow::{Result, ensure};
+use std::collections::HashMap;
+
+/// 基于MinHash分带的内存LSH索引
+/// 用于回答"是否见过相似度不低于阈值的消息"
+pub struct LshIndex {
+    threshold: f64,
+    bands: usize,
+    rows: usize,
+    buckets: Vec<HashMap<u64, Vec<u64>>>,
+    signatures: HashMap<u64, MinHash>,
+}
+
+impl LshIndex {
+    /// 根据相似度阈值选择分带参数
+    /// 阈值须在`(0, 1]`内, 否则返回错误
+    pub fn new(threshold: f64) -> Result<Self> {
+        ensure!(
+            threshold > 0. && threshold <= 1.,
+            "invalid lsh threshold: {threshold}"
+        );
+        let (bands, rows) = optimal_bands(threshold);
+        Ok(Self {
+            threshold,
+            bands,
+            rows,
+            buckets: vec![HashMap::new(); bands],
+            signatures: HashMap::new(),
+        })
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    pub fn len(&self) -> usize {
+        self.signatures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signatures.is_empty()
+    }
+
+    /// 插入一条归一化文本, 相同`id`会覆盖旧签名
+    pub fn insert(&mut self, id: u64, normalized: &str) {
+        self.insert_signature(id, MinHash::new(normalized));
+    }
+
+    pub fn insert_signature(&mut self, id: u64, signature: MinHash) {
+        self.remove(id);
+        for (band, key) in self.band_keys(&signature).enumerate() {
+            self.buckets[band].entry(key).or_default().push(id);
+        }
+        self.signatures.insert(id, signature);
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<MinHash> {
+        let signature = self.signatures.remove(&id)?;
+        for (band, key) in self.band_keys(&signature).enumerate() {
+            if let Some(ids) = self.buckets[band].get_mut(&key) {
+                ids.retain(|x| *x != id);
+                if ids.is_empty() {
+                    self.buckets[band].remove(&key);
+                }
+            }
+        }
+        Some(signature)
+    }
+
+    /// 查询相似度不低于阈值的已索引文本
+    /// 返回`(id, 估计相似度)`, 按相似度降序排列
+    pub fn query(&self, normalized: &str) -> Vec<(u64, f64)> {
+        self.query_signature(&MinHash::new(normalized))
+    }
+
+    pub fn query_signature(&self, signature: &MinHash) -> Vec<(u64, f64)> {
+        let mut candidates = self
+            .band_keys(signature)
+            .enumerate()
+            .filter_map(|(band, key)| self.buckets[band].get(&key))
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        let mut ret = candidates
+            .into_iter()
+            .filter_map(|id| {
+                let sim = self.signatures.get(&id)?.similarity(signature);
+                (sim >= self.threshold).then_some((id, sim))
+            })
+            .collect::<Vec<_>>();
+        ret.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ret
+    }
+
+    fn band_keys<'a>(&self, signature: &'a MinHash) -> impl Iterator<Item = u64> + 'a {
+        let rows = self.rows;
+        signature.0.chunks(rows).take(self.bands).map(|band| {
+            let bytes = band
+                .iter()
+                .flat_map(|x| x.to_le_bytes())
+                .collect::<Vec<_>>();
+            fnv1a(&bytes)
+        })
+    }
+}
+
+/// 选择分带参数, 要求`b * r <= NUM_PERM`
+/// `(1/b)^(1/r)`约为S型候选概率曲线的拐点, 将其放在略低于阈值处以提高召回,
+/// 多出的候选再由完整签名比对过滤
+fn optimal_bands(threshold: f64) -> (usize, usize) {
+    let target = threshold * 0.8;
+    (1..=NUM_PERM)
+        .map(|rows| (NUM_PERM / rows, rows))
+        .min_by(|a, b| {
+            let f = |(bands, rows): (usize, usize)| {
+                ((1. / bands as f64).powf(1. / rows as f64) - target).abs()
+            };
+            f(*a).total_cmp(&f(*b))
+        })
+        .unwrap()
+}
@@ -0,0 +1,41 @@
+use super::{fnv1a, shingles};
+
+/// MinHash签名的排列数
+pub const NUM_PERM: usize = 128;
+
+/// MinHash签名
+/// 相同位置取值相等的比例即为shingle集合Jaccard相似度的估计
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MinHash(pub Vec<u64>);
+
+impl MinHash {
+    /// 输入归一化文本, 参见`dedup::normalize`
+    pub fn new(normalized: &str) -> Self {
+        let mut ret = vec![u64::MAX; NUM_PERM];
+        for shingle in shingles(normalized) {
+            let h = fnv1a(shingle.as_bytes());
+            for (i, min) in ret.iter_mut().enumerate() {
+                *min = (*min).min(permute(h, i as u64));
+            }
+        }
+        Self(ret)
+    }
+
+    /// Jaccard相似度估计, 取值[0, 1]
+    pub fn similarity(&self, other: &MinHash) -> f64 {
+        let len = self.0.len().min(other.0.len());
+        if len == 0 {
+            return 0.;
+        }
+        let eq = self.0.iter().zip(&other.0).filter(|(a, b)| a == b).count();
+        eq as f64 / len as f64
+    }
+}
+
+/// 以排列序号为种子的splitmix64, 模拟独立的哈希函数
+fn permute(h: u64, seed: u64) -> u64 {
+    let mut z = h ^ seed.wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,38 @@
+use super::{fnv1a, shingles};
+
+/// 64位SimHash签名
+/// 海明距离越小, 文本越相似
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SimHash(pub u64);
+
+impl SimHash {
+    /// 输入归一化文本, 参见`dedup::normalize`
+    pub fn new(normalized: &str) -> Self {
+        let mut weights = [0i64; 64];
+        for shingle in shingles(normalized) {
+            let h = fnv1a(shingle.as_bytes());
+            for (bit, w) in weights.iter_mut().enumerate() {
+                if h >> bit & 1 == 1 {
+                    *w += 1;
+                } else {
+                    *w -= 1;
+                }
+            }
+        }
+        let ret = weights
+            .iter()
+            .enumerate()
+            .filter(|(_, w)| **w > 0)
+            .fold(0u64, |acc, (bit, _)| acc | 1 << bit);
+        Self(ret)
+    }
+
+    pub fn distance(&self, other: &SimHash) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+
+    /// 相似度, 取值[0, 1]
+    pub fn similarity(&self, other: &SimHash) -> f64 {
+        1. - self.distance(other) as f64 / 64.
+    }
+}
@@ -0,0 +1,68 @@
+use std::fmt;
+
+/// 可区分类型的错误
+/// 各函数仍返回`anyhow::Result`, 需要按类型处理时使用`anyhow::Error::downcast_ref::<Error>()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// entity解码失败, 如JSON格式错误、缺少字段
+    /// `line`和`column`为JSON中出错的位置, 从1开始; 不是JSON语法错误时为0
+    EntityDecode {
+        message: String,
+        line: usize,
+        column: usize,
+    },
+    /// UTF-16区间`[offset, offset + length)`超出文本范围, 或端点落在代理对中间
+    /// `index`为出错的端点, `text_length`为文本的UTF-16长度
+    Offset {
+        offset: usize,
+        length: usize,
+        index: usize,
+        text_length: usize,
+    },
+    /// 不支持的entity类型或HTML标签
+    UnsupportedEntity { kind: String },
+    /// 渲染失败, 如颜色、对齐方式等参数无效
+    Render(String),
+}
+
+impl Error {
+    pub fn entity_decode(message: impl Into<String>) -> Self {
+        Error::EntityDecode {
+            message: message.into(),
+            line: 0,
+            column: 0,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::EntityDecode { message, .. } => write!(f, "{message}"),
+            Error::Offset {
+                offset,
+                length,
+                index,
+                text_length,
+            } => write!(
+                f,
+                "invalid utf16 range {offset}..{} at index {index}, text length is {text_length}",
+                offset.saturating_add(*length)
+            ),
+            Error::UnsupportedEntity { kind } => write!(f, "unsupported entity: {kind}"),
+            Error::Render(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::EntityDecode {
+            message: e.to_string(),
+            line: e.line(),
+            column: e.column(),
+        }
+    }
+}
@@ -1,9 +1,20 @@
-use anyhow::{Result, anyhow};
+use crate::error::Error;
+use anyhow::Result;
 use grammers_tl_types as tl;
 use std::collections::HashSet;
 use tl::enums::MessageEntity;
 
 pub fn extract_entity<'a>(msg: &'a str, msg_entity: &MessageEntity) -> Result<Option<&'a str>> {
+    if let Some((offset, length)) = entity_range(msg_entity) {
+        let (l, r) = utf16_range_to_utf8(msg, offset as usize, length as usize)?;
+        return Ok(Some(&msg[l..r]));
+    }
+    Ok(None)
+}
+
+/// 获取entity在消息中的区间, 为UTF-16的`(offset, length)`
+/// 没有对应文本的entity返回None
+pub fn entity_range(msg_entity: &MessageEntity) -> Option<(i32, i32)> {
     let result = match msg_entity {
         MessageEntity::Unknown(_) => None,
         MessageEntity::Mention(tl::types::MessageEntityMention { offset, length }) => {
@@ -67,11 +78,7 @@ pub fn extract_entity<'a>(msg: &'a str, msg_entity: &MessageEntity) -> Result<Op
             offset, length, ..
         }) => Some((offset, length)),
     };
-    if let Some((offset, length)) = result {
-        let (l, r) = utf16_range_to_utf8(msg, *offset as usize, *length as usize)?;
-        return Ok(Some(&msg[l..r]));
-    }
-    Ok(None)
+    result.map(|(offset, length)| (*offset, *length))
 }
 
 pub fn extract_mentioned_users(
@@ -80,7 +87,7 @@ pub fn extract_mentioned_users(
 ) -> Result<(HashSet<String>, HashSet<i64>)> {
     let mut user_ids = HashSet::new();
     let mentions = msg_entities
-        .into_iter()
+        .iter()
         .filter_map(|ent| match ent {
             // messageEntityMention, Message entity mentioning a user by @username;
             // messageEntityMentionName can also be used to mention users by their ID.
@@ -107,7 +114,7 @@ pub fn extract_mentioned_users(
             _ => None,
         })
         // 长度越界直接忽略
-        .filter_map(|(offset, length)| utf16_range_to_utf8(&msg, offset, length).ok()) // 获取以utf8计算的字节长度
+        .filter_map(|(offset, length)| utf16_range_to_utf8(msg, offset, length).ok()) // 获取以utf8计算的字节长度
         .filter_map(|(start, end)| msg.get(start..end)) // 截取用户名部分
         .filter_map(|x| x.get(1..)) // 删除@键
         .map(|x| x.to_lowercase()) // 转换小写
@@ -117,7 +124,7 @@ pub fn extract_mentioned_users(
 }
 /// 把 UTF-16 的 [offset, offset+len) 区间映射成 UTF-8 字节区间 [ret.0, ret.1)
 ///
-/// 返回 `Ok((byte_start, byte_end))`，如果越界则返回 `Err`。
+/// 返回 `Ok((byte_start, byte_end))`，如果越界则返回 `Err(Error::Offset)`。
 pub fn utf16_range_to_utf8(s: &str, offset: usize, len: usize) -> Result<(usize, usize)> {
     let utf16_to_byte_idx = |idx: usize| -> Option<usize> {
         let mut utf16_cnt = 0;
@@ -134,7 +141,14 @@ pub fn utf16_range_to_utf8(s: &str, offset: usize, len: usize) -> Result<(usize,
         None
     };
 
-    let start = utf16_to_byte_idx(offset).ok_or(anyhow!("invalid utf16 offset"))?;
-    let end = utf16_to_byte_idx(offset + len).ok_or(anyhow!("invalid utf16 offset, length"))?;
+    let error = |index| Error::Offset {
+        offset,
+        length: len,
+        index,
+        text_length: s.encode_utf16().count(),
+    };
+    let start = utf16_to_byte_idx(offset).ok_or_else(|| error(offset))?;
+    let end = offset.saturating_add(len);
+    let end = utf16_to_byte_idx(end).ok_or_else(|| error(end))?;
     Ok((start, end))
 }
@@ -7,6 +7,7 @@ pub mod entities;
 
 /// 输入消息文本和消息entities
 /// 输出用户名集合和用户ID集合
+#[tracing::instrument(level = "debug", skip_all, fields(len = message.len()))]
 pub fn extract_usernames(
     message: &str,
     entities: Option<Vec<tl::enums::MessageEntity>>,
@@ -25,5 +26,130 @@ pub fn extract_usernames(
         usernames.extend(text_url_un);
         user_ids.extend(mention_uid);
     }
+    tracing::debug!(
+        "extracted {} username(s) and {} user id(s)",
+        usernames.len(),
+        user_ids.len()
+    );
     Ok((usernames, user_ids))
 }
+
+/// 带来源的提取结果
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Extraction {
+    /// 类型, 为`username`、`user_id`或`invite`
+    pub kind: &'static str,
+    /// 用户名(不带@)、用户ID或邀请码
+    pub value: String,
+    /// 来源, 为`text`(文本中的链接)、`mention`、`mention_name`或`text_url`
+    pub source: &'static str,
+    /// 来源在消息中的UTF-16区间
+    pub offset: usize,
+    pub length: usize,
+    /// 来源为链接时的原始链接
+    pub link: Option<String>,
+}
+
+/// 提取用户名、用户ID和邀请码, 保留每个结果的来源, 按来源在消息中的位置排列
+/// 同一个值可能出现多次; 区间越界的entity被忽略
+#[tracing::instrument(level = "debug", skip_all, fields(len = message.len(), entities = entities.len()))]
+pub fn extract_all(message: &str, entities: &[tl::enums::MessageEntity]) -> Vec<Extraction> {
+    use tl::enums::MessageEntity;
+    let utf16_len = |s: &str| s.encode_utf16().count();
+    let from_link = |link: &deeplink::DeepLink| match link.kind {
+        deeplink::LinkKind::Username => ("username", link.username.clone().unwrap_or_default()),
+        deeplink::LinkKind::Invite => ("invite", link.param("invite").unwrap_or_default().into()),
+    };
+
+    let mut ret = vec![];
+    for (range, link) in deeplink::find_links(message) {
+        let (kind, value) = from_link(&link);
+        ret.push(Extraction {
+            kind,
+            value,
+            source: "text",
+            offset: utf16_len(&message[..range.start]),
+            length: utf16_len(&message[range.clone()]),
+            link: Some(message[range].to_string()),
+        });
+    }
+    for entity in entities {
+        let Some((offset, length)) = super::entity::entity_range(entity) else {
+            continue;
+        };
+        let (offset, length) = (offset as usize, length as usize);
+        let Ok((l, r)) = super::entity::utf16_range_to_utf8(message, offset, length) else {
+            continue;
+        };
+        let (kind, value, source, link) = match entity {
+            MessageEntity::Mention(_) => match message[l..r].strip_prefix('@') {
+                Some(username) => ("username", username.to_lowercase(), "mention", None),
+                None => continue,
+            },
+            MessageEntity::MentionName(x) => {
+                ("user_id", x.user_id.to_string(), "mention_name", None)
+            }
+            MessageEntity::TextUrl(x) => match deeplink::DeepLink::parse(&x.url) {
+                Some(link) => {
+                    let (kind, value) = from_link(&link);
+                    (kind, value, "text_url", Some(x.url.clone()))
+                }
+                None => continue,
+            },
+            _ => continue,
+        };
+        ret.push(Extraction {
+            kind,
+            value,
+            source,
+            offset,
+            length,
+            link,
+        });
+    }
+    ret.sort_by_key(|x| x.offset);
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_all() {
+        use tl::types as t;
+        // UTF-16下emoji占2
+        let message = "👋 @Alice t.me/+AbCdEf bob https://t.me/carol/12?single";
+        let entities = vec![
+            tl::enums::MessageEntity::Mention(t::MessageEntityMention {
+                offset: 3,
+                length: 6,
+            }),
+            tl::enums::MessageEntity::MentionName(t::MessageEntityMentionName {
+                offset: 22,
+                length: 3,
+                user_id: 42,
+            }),
+            tl::enums::MessageEntity::TextUrl(t::MessageEntityTextUrl {
+                offset: 22,
+                length: 3,
+                url: "tg://resolve?domain=bob".to_string(),
+            }),
+        ];
+        let ret = extract_all(message, &entities)
+            .into_iter()
+            .map(|x| (x.kind, x.value, x.source, x.offset, x.length))
+            .collect::<Vec<_>>();
+        let s = String::from;
+        assert_eq!(
+            ret,
+            [
+                ("username", s("alice"), "mention", 3, 6),
+                ("invite", s("AbCdEf"), "text", 10, 12),
+                ("user_id", s("42"), "mention_name", 22, 3),
+                ("username", s("bob"), "text_url", 22, 3),
+                ("username", s("carol"), "text", 27, 28),
+            ]
+        );
+    }
+}
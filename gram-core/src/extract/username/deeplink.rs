@@ -1,10 +1,12 @@
 use regex::Regex;
 use std::collections::HashSet;
+use std::ops::Range;
+use std::str::FromStr;
 use std::sync::LazyLock;
-use url::Url;
+use url::{Url, form_urlencoded};
 use wildcard::Wildcard;
 
-const PATTERNS: LazyLock<Regex> =
+static PATTERNS: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"(?:https?://)?[^\s/$.?#].\S*").unwrap());
 
 /// 输入一个字符串
@@ -21,11 +23,171 @@ pub fn get_username(link: &str) -> Option<String> {
     Some(ret)
 }
 
-/// 检查链接是否为deeplink，并提取用户名
+/// 提取文本中的用户名链接和邀请链接, 按出现顺序排列
+pub fn extract_links(text: &str) -> Vec<DeepLink> {
+    find_links(text).map(|(_, link)| link).collect()
+}
+
+/// 文本中的链接及其字节区间
+pub(super) fn find_links(text: &str) -> impl Iterator<Item = (Range<usize>, DeepLink)> + '_ {
+    PATTERNS
+        .find_iter(text)
+        .filter_map(|m| Some((m.range(), DeepLink::parse(m.as_str())?)))
+}
+
+/// 输入一个字符串
+/// 提取其中的邀请链接中的邀请码
+pub fn extract_invites(text: &str) -> HashSet<String> {
+    PATTERNS
+        .find_iter(text)
+        .filter_map(|text| get_invite(text.as_str()))
+        .collect()
+}
+
+/// 从链接中提取邀请码
+/// 支持`t.me/+hash`、`t.me/joinchat/hash`和`tg://join?invite=hash`三种形式
+/// `t.me/+`后接纯数字时为手机号链接, 不视为邀请
+pub fn get_invite(link: &str) -> Option<String> {
+    if let Some((_, uri)) = tg_me_uri(link) {
+        let path = uri.split('?').next()?;
+        let hash = if let Some(hash) = path.strip_prefix('+') {
+            hash
+        } else {
+            path.strip_prefix("joinchat/")?
+        };
+        let hash = hash.split('/').next()?;
+        return is_invite_hash(hash).then(|| hash.to_string());
+    }
+
+    let url = tg_schema_url(link)?;
+    if url.path() != "/join" {
+        return None;
+    }
+    url.query_pairs()
+        .find(|(k, _)| k == "invite")
+        .map(|(_, v)| v.to_string())
+        .filter(|x| is_invite_hash(x))
+}
+
+/// 链接的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LinkKind {
+    /// 公开用户名, 如`t.me/username`、`tg://resolve?domain=username`
+    Username,
+    /// 邀请链接, 如`t.me/+hash`、`tg://join?invite=hash`
+    Invite,
+}
+
+impl LinkKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkKind::Username => "username",
+            LinkKind::Invite => "invite",
+        }
+    }
+}
+
+impl FromStr for LinkKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "username" => Ok(LinkKind::Username),
+            "invite" => Ok(LinkKind::Invite),
+            _ => Err(anyhow::anyhow!("unknown link kind: {s}")),
+        }
+    }
+}
+
+/// 解析后的用户名链接或邀请链接
 /// 参考: https://core.telegram.org/api/links
-/// TODO: 完整实现
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DeepLink {
+    pub kind: LinkKind,
+    /// 用户名, 邀请链接为None
+    pub username: Option<String>,
+    /// 链接参数, 与`tg://`形式的参数名一致, 如`start`、`post`; 邀请码为`invite`
+    pub params: Vec<(String, String)>,
+}
+
+impl DeepLink {
+    /// 解析`t.me`系列域名或`tg:`协议的链接, 不是用户名链接或邀请链接时返回None
+    /// `t.me/username/123`中的消息ID为参数`post`
+    pub fn parse(link: &str) -> Option<DeepLink> {
+        if let Some(hash) = get_invite(link) {
+            return Some(DeepLink {
+                kind: LinkKind::Invite,
+                username: None,
+                params: vec![("invite".to_string(), hash)],
+            });
+        }
+        if let Some((_, uri)) = tg_me_uri(link) {
+            let username = get_uri_username(uri)?.to_string();
+            let (path, query) = uri.split_once('?').unwrap_or((uri, ""));
+            let post = path
+                .split('/')
+                .nth(1)
+                .filter(|x| !x.is_empty() && x.chars().all(|c| c.is_ascii_digit()));
+            let params = post
+                .map(|x| ("post".to_string(), x.to_string()))
+                .into_iter()
+                .chain(form_urlencoded::parse(query.as_bytes()).into_owned())
+                .collect();
+            return Some(DeepLink {
+                kind: LinkKind::Username,
+                username: Some(username),
+                params,
+            });
+        }
+        let username = tg_schema_preparse(link)?.username();
+        let url = tg_schema_url(link)?;
+        let params = url
+            .query_pairs()
+            .into_owned()
+            .filter(|(k, _)| k != "domain")
+            .collect();
+        Some(DeepLink {
+            kind: LinkKind::Username,
+            username: Some(username),
+            params,
+        })
+    }
+
+    /// 参数的值
+    pub fn param(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// 转换为规范的`https://t.me/`链接
+    pub fn to_url(&self) -> String {
+        if self.kind == LinkKind::Invite {
+            return format!("https://t.me/+{}", self.param("invite").unwrap_or_default());
+        }
+        let mut ret = format!(
+            "https://t.me/{}",
+            self.username.as_deref().unwrap_or_default()
+        );
+        if let Some(post) = self.param("post") {
+            ret.push('/');
+            ret.push_str(post);
+        }
+        let query = form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(self.params.iter().filter(|(k, _)| k != "post"))
+            .finish();
+        if !query.is_empty() {
+            ret.push('?');
+            ret.push_str(&query);
+        }
+        ret
+    }
+}
+
+/// 链接的初步解析结果
 #[derive(Debug, Eq, PartialEq)]
-enum DeepLink {
+enum Preparsed {
     TMe {
         schema: &'static str,
         username: String,
@@ -34,16 +196,23 @@ enum DeepLink {
         username: String,
     },
 }
-impl DeepLink {
+impl Preparsed {
     fn username(&self) -> String {
         match self {
-            DeepLink::TMe { username, .. } => username.clone(),
-            DeepLink::TG { username } => username.clone(),
+            Preparsed::TMe { username, .. } => username.clone(),
+            Preparsed::TG { username } => username.clone(),
         }
     }
 }
 
-fn tg_me_preparse(url: &str) -> Option<DeepLink> {
+fn tg_me_preparse(url: &str) -> Option<Preparsed> {
+    let (schema, uri) = tg_me_uri(url)?;
+    let username = get_uri_username(uri)?.to_string();
+    Some(Preparsed::TMe { schema, username })
+}
+
+/// 匹配`t.me`系列域名的链接, 返回协议和域名之后的部分
+fn tg_me_uri(url: &str) -> Option<(&'static str, &str)> {
     let mut patterns = vec![];
     for domain in ["t.me", "telegram.me", "telegram.dog"] {
         patterns.push(("https", format!("https://{domain}/*")));
@@ -53,27 +222,23 @@ fn tg_me_preparse(url: &str) -> Option<DeepLink> {
     patterns
         .iter()
         .map(|(schema, pat)| (schema, Wildcard::new(pat.as_bytes()).unwrap()))
-        .filter_map(|(schema, pat)| {
+        .find_map(|(schema, pat)| {
             let a = pat.captures(url.as_bytes())?;
             let uri = str::from_utf8(a[0]).ok()?;
-            Some((schema, uri))
+            Some((*schema, uri))
         })
-        .filter_map(|(schema, uri)| {
-            let username = get_uri_username(uri)?.to_string();
-            Some(DeepLink::TMe { schema, username })
-        })
-        .next()
 }
 
-fn tg_schema_preparse(url: &str) -> Option<DeepLink> {
-    let url_no_schema = if url.starts_with("tg://") {
-        &url[5..]
-    } else if url.starts_with("tg:") {
-        &url[3..]
-    } else {
-        return None;
-    };
-    let url = Url::parse(&format!("http://t.me/{url_no_schema}")).ok()?;
+/// 将`tg:`协议的链接转换为可解析的URL
+fn tg_schema_url(url: &str) -> Option<Url> {
+    let url_no_schema = url
+        .strip_prefix("tg://")
+        .or_else(|| url.strip_prefix("tg:"))?;
+    Url::parse(&format!("http://t.me/{url_no_schema}")).ok()
+}
+
+fn tg_schema_preparse(url: &str) -> Option<Preparsed> {
+    let url = tg_schema_url(url)?;
     let username = url
         .path()
         .eq("/resolve")
@@ -83,9 +248,9 @@ fn tg_schema_preparse(url: &str) -> Option<DeepLink> {
                 .next()
         })
         .flatten()
-        .filter(|x| is_username(&x.to_string()))?
+        .filter(|x| is_username(x))?
         .to_string();
-    Some(DeepLink::TG { username })
+    Some(Preparsed::TG { username })
 }
 
 fn get_uri_username(uri: &str) -> Option<&str> {
@@ -96,6 +261,14 @@ fn get_uri_username(uri: &str) -> Option<&str> {
         .filter(|&x| is_username(x))
 }
 
+fn is_invite_hash(text: &str) -> bool {
+    !text.is_empty()
+        && !text.chars().all(|c| c.is_ascii_digit())
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
 fn is_username(text: &str) -> bool {
     (![
         "www",
@@ -140,7 +313,7 @@ mod tests {
         let url = tg_me_preparse("t.me/path?query").unwrap();
         assert_eq!(
             url,
-            DeepLink::TMe {
+            Preparsed::TMe {
                 schema: "",
                 username: "path".to_owned()
             }
@@ -148,7 +321,7 @@ mod tests {
         let url = tg_me_preparse("http://t.me/path?query").unwrap();
         assert_eq!(
             url,
-            DeepLink::TMe {
+            Preparsed::TMe {
                 schema: "http",
                 username: "path".to_owned()
             }
@@ -156,7 +329,7 @@ mod tests {
         let url = tg_me_preparse("https://t.me/my-user?query").unwrap();
         assert_eq!(
             url,
-            DeepLink::TMe {
+            Preparsed::TMe {
                 schema: "https",
                 username: "my-user".to_owned()
             }
@@ -212,6 +385,60 @@ mod tests {
         assert_eq!(get_username("tg://resolve?domains=v"), None);
     }
 
+    #[test]
+    fn test_invite() {
+        assert_eq!(
+            get_invite("https://t.me/+AbCdEf123_-x"),
+            Some("AbCdEf123_-x".to_owned())
+        );
+        assert_eq!(
+            get_invite("t.me/joinchat/AbCdEf123"),
+            Some("AbCdEf123".to_owned())
+        );
+        assert_eq!(
+            get_invite("tg://join?invite=AbCdEf123"),
+            Some("AbCdEf123".to_owned())
+        );
+        // 手机号链接
+        assert_eq!(get_invite("https://t.me/+1234567890"), None);
+        assert_eq!(get_invite("https://t.me/my-username"), None);
+        // 邀请链接不是用户名
+        assert_eq!(get_username("https://t.me/+AbCdEf123"), None);
+    }
+
+    #[test]
+    fn test_deeplink() {
+        let link = DeepLink::parse("https://t.me/my-channel/123?single&comment=4").unwrap();
+        assert_eq!(link.kind, LinkKind::Username);
+        assert_eq!(link.username.as_deref(), Some("my-channel"));
+        assert_eq!(link.param("post"), Some("123"));
+        assert_eq!(link.param("comment"), Some("4"));
+        assert_eq!(
+            link.to_url(),
+            "https://t.me/my-channel/123?single=&comment=4"
+        );
+
+        let link = DeepLink::parse("tg://resolve?domain=my-bot&start=a%20b").unwrap();
+        assert_eq!(link.username.as_deref(), Some("my-bot"));
+        assert_eq!(link.params, [("start".to_string(), "a b".to_string())]);
+        assert_eq!(link.to_url(), "https://t.me/my-bot?start=a+b");
+        assert_eq!(DeepLink::parse(&link.to_url()), Some(link));
+
+        let link = DeepLink::parse("tg:join?invite=AbCdEf").unwrap();
+        assert_eq!(link.kind, LinkKind::Invite);
+        assert_eq!(link.username, None);
+        assert_eq!(link.to_url(), "https://t.me/+AbCdEf");
+        assert_eq!(DeepLink::parse("https://example.com/my-bot"), None);
+
+        assert_eq!(
+            extract_links("t.me/a1 https://t.me/joinchat/AbC tg:resolve?domain=b2")
+                .iter()
+                .map(DeepLink::to_url)
+                .collect::<Vec<_>>(),
+            ["https://t.me/a1", "https://t.me/+AbC", "https://t.me/b2"]
+        );
+    }
+
     #[test]
     fn test_batch() {
         let text = "\
@@ -1,23 +1,275 @@
 use grammers_tl_types::enums::MessageEntity;
-use anyhow::Result;
+use crate::error::Error;
+use crate::extract::entity::entity_range;
+use anyhow::{Result, anyhow};
 use grammers_client::grammers_tl_types as tl;
 use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::str::FromStr;
+
+pub mod botapi;
+pub mod desktop;
+pub mod html;
 
 /// 将telethon的entities的json列表转换为grammers的entities
 pub fn deserialize_telethon_entities(entities: &str) -> Result<Vec<MessageEntity>> {
-    let entities: Vec<TelethonEntity> = serde_json::from_str(entities)?;
+    let entities: Vec<TelethonEntity> = serde_json::from_str(entities).map_err(Error::from)?;
     let ret = entities.into_iter().map(|x| x.into()).collect();
     Ok(ret)
 }
 
 /// 将telethon的entity的json对象转换为grammers的entity
 pub fn deserialize_telethon_entity(entity: &str) -> Result<MessageEntity> {
-    let ret: TelethonEntity = serde_json::from_str(entity)?;
+    let ret: TelethonEntity = serde_json::from_str(entity).map_err(Error::from)?;
     Ok(ret.into())
 }
 
+/// 将任意来源的entities的json列表转换为grammers的entities, 支持的格式参见`EntityFields::from_value`
+pub fn deserialize_entities(entities: &str) -> Result<Vec<MessageEntity>> {
+    let entities: Vec<Value> = serde_json::from_str(entities).map_err(Error::from)?;
+    entities
+        .iter()
+        .map(|x| MessageEntity::try_from(EntityFields::from_value(x)?))
+        .collect()
+}
+
+/// 将任意来源的entity的json对象转换为grammers的entity, 支持的格式参见`EntityFields::from_value`
+pub fn deserialize_entity(entity: &str) -> Result<MessageEntity> {
+    let entity: Value = serde_json::from_str(entity).map_err(Error::from)?;
+    MessageEntity::try_from(EntityFields::from_value(&entity)?)
+}
+
+/// 将grammers的entities转换为telethon的entities的json列表
+pub fn serialize_telethon_entities(entities: &[MessageEntity]) -> Result<String> {
+    let entities = entities
+        .iter()
+        .cloned()
+        .map(TelethonEntity::from)
+        .collect::<Vec<_>>();
+    Ok(serde_json::to_string(&entities)?)
+}
+
+/// 按字段描述的entity, 用于从其他来源的对象构造entity, 如Python中telethon、pyrogram的entity对象
+/// 不适用于该类型的字段被忽略
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct EntityFields {
+    /// 类型名, 支持telethon的类名(如`MessageEntityTextUrl`)、
+    /// pyrogram的`MessageEntityType`(如`TEXT_LINK`)以及Bot API的type(如`text_link`)
+    pub kind: String,
+    pub offset: i32,
+    pub length: i32,
+    /// `TextUrl`的链接
+    pub url: Option<String>,
+    /// `MentionName`提及的用户
+    pub user_id: Option<i64>,
+    /// `Pre`的语言
+    pub language: Option<String>,
+    /// `CustomEmoji`的文档ID
+    pub custom_emoji_id: Option<i64>,
+    /// `Blockquote`是否折叠
+    pub collapsed: bool,
+}
+
+impl EntityFields {
+    /// 读取entity的json对象, 支持telethon的`to_dict()`、GramJS(`className`及驼峰命名的字段)和Bot API的格式
+    /// 类型取`type`、`_`或`className`; 用户ID取`user_id`、`userId`或`user`, 可为对象;
+    /// 数值可为数字字符串, 如GramJS中`BigInteger`的序列化结果和Bot API的`custom_emoji_id`
+    pub fn from_value(value: &Value) -> Result<Self> {
+        let get = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| value.get(name))
+                .filter(|x| !x.is_null())
+        };
+        let kind = get(&["type", "_", "className"])
+            .and_then(Value::as_str)
+            .ok_or_else(|| Error::entity_decode("missing field `type`"))?
+            .to_string();
+        let int = |names: &[&str]| get(names).map(|x| json_int(x, names[0])).transpose();
+        let required = |name: &str| -> Result<i32> {
+            let ret = int(&[name])?.and_then(|x| i32::try_from(x).ok());
+            Ok(ret.ok_or_else(|| {
+                Error::entity_decode(format!("missing field `{name}` for {kind}"))
+            })?)
+        };
+        let string = |names: &[&str]| get(names).and_then(Value::as_str).map(str::to_string);
+        // 用户可为ID, 也可为对象, 如GramJS的`InputUser`、Bot API的`User`
+        let user_id = match get(&["user_id", "userId", "user"]) {
+            Some(user @ Value::Object(_)) => {
+                let id = ["user_id", "userId", "id"]
+                    .iter()
+                    .find_map(|name| user.get(name));
+                id.map(|x| json_int(x, "user_id")).transpose()?
+            }
+            Some(user) => Some(json_int(user, "user_id")?),
+            None => None,
+        };
+        Ok(EntityFields {
+            offset: required("offset")?,
+            length: required("length")?,
+            url: string(&["url"]),
+            user_id,
+            language: string(&["language"]),
+            custom_emoji_id: int(&[
+                "custom_emoji_id",
+                "customEmojiId",
+                "document_id",
+                "documentId",
+            ])?,
+            collapsed: get(&["collapsed"])
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            kind,
+        })
+    }
+}
+
+/// 数字或数字字符串
+fn json_int(value: &Value, name: &str) -> Result<i64> {
+    let ret = match value {
+        Value::Number(x) => x.as_i64(),
+        Value::String(x) => x.parse().ok(),
+        _ => None,
+    };
+    ret.ok_or_else(|| Error::entity_decode(format!("invalid field `{name}`: {value}")).into())
+}
+
+/// 类型名为TL类型名的蛇形命名, 如`text_url`、`mention_name`, 可转换回entity
+impl From<&MessageEntity> for EntityFields {
+    fn from(value: &MessageEntity) -> Self {
+        let (offset, length) = entity_range(value).unwrap_or_default();
+        let mut ret = EntityFields {
+            offset,
+            length,
+            ..Default::default()
+        };
+        ret.kind = match value {
+            MessageEntity::Unknown(_) => "unknown",
+            MessageEntity::Mention(_) => "mention",
+            MessageEntity::Hashtag(_) => "hashtag",
+            MessageEntity::BotCommand(_) => "bot_command",
+            MessageEntity::Url(_) => "url",
+            MessageEntity::Email(_) => "email",
+            MessageEntity::Bold(_) => "bold",
+            MessageEntity::Italic(_) => "italic",
+            MessageEntity::Code(_) => "code",
+            MessageEntity::Pre(x) => {
+                ret.language = Some(x.language.clone()).filter(|x| !x.is_empty());
+                "pre"
+            }
+            MessageEntity::TextUrl(x) => {
+                ret.url = Some(x.url.clone());
+                "text_url"
+            }
+            MessageEntity::MentionName(x) => {
+                ret.user_id = Some(x.user_id);
+                "mention_name"
+            }
+            MessageEntity::InputMessageEntityMentionName(x) => {
+                ret.user_id = match &x.user_id {
+                    tl::enums::InputUser::User(user) => Some(user.user_id),
+                    tl::enums::InputUser::FromMessage(user) => Some(user.user_id),
+                    _ => None,
+                };
+                "input_mention_name"
+            }
+            MessageEntity::Phone(_) => "phone",
+            MessageEntity::Cashtag(_) => "cashtag",
+            MessageEntity::Underline(_) => "underline",
+            MessageEntity::Strike(_) => "strike",
+            MessageEntity::BankCard(_) => "bank_card",
+            MessageEntity::Spoiler(_) => "spoiler",
+            MessageEntity::CustomEmoji(x) => {
+                ret.custom_emoji_id = Some(x.document_id);
+                "custom_emoji"
+            }
+            MessageEntity::Blockquote(x) => {
+                ret.collapsed = x.collapsed;
+                "blockquote"
+            }
+        }
+        .to_string();
+        ret
+    }
+}
+
+impl TryFrom<EntityFields> for MessageEntity {
+    type Error = anyhow::Error;
+
+    fn try_from(value: EntityFields) -> Result<Self> {
+        use tl::types as t;
+        let EntityFields { offset, length, .. } = value;
+        // 统一为小写无下划线的形式, 如`MessageEntityType.TEXT_LINK`为`textlink`
+        let kind = value.kind.to_lowercase().replace('_', "");
+        let kind = kind.strip_prefix("messageentitytype.").unwrap_or(&kind);
+        let kind = kind.strip_prefix("input").unwrap_or(kind);
+        let kind = kind.strip_prefix("messageentity").unwrap_or(kind);
+        let ret = match kind {
+            "unknown" => MessageEntity::Unknown(t::MessageEntityUnknown { offset, length }),
+            "mention" => MessageEntity::Mention(t::MessageEntityMention { offset, length }),
+            "hashtag" => MessageEntity::Hashtag(t::MessageEntityHashtag { offset, length }),
+            "cashtag" => MessageEntity::Cashtag(t::MessageEntityCashtag { offset, length }),
+            "botcommand" => {
+                MessageEntity::BotCommand(t::MessageEntityBotCommand { offset, length })
+            }
+            "url" => MessageEntity::Url(t::MessageEntityUrl { offset, length }),
+            "email" => MessageEntity::Email(t::MessageEntityEmail { offset, length }),
+            "phone" | "phonenumber" => {
+                MessageEntity::Phone(t::MessageEntityPhone { offset, length })
+            }
+            "bankcard" => MessageEntity::BankCard(t::MessageEntityBankCard { offset, length }),
+            "bold" => MessageEntity::Bold(t::MessageEntityBold { offset, length }),
+            "italic" => MessageEntity::Italic(t::MessageEntityItalic { offset, length }),
+            "underline" => MessageEntity::Underline(t::MessageEntityUnderline { offset, length }),
+            "strike" | "strikethrough" => {
+                MessageEntity::Strike(t::MessageEntityStrike { offset, length })
+            }
+            "spoiler" => MessageEntity::Spoiler(t::MessageEntitySpoiler { offset, length }),
+            "code" => MessageEntity::Code(t::MessageEntityCode { offset, length }),
+            "pre" => MessageEntity::Pre(t::MessageEntityPre {
+                offset,
+                length,
+                language: value.language.unwrap_or_default(),
+            }),
+            "blockquote" | "expandableblockquote" => {
+                MessageEntity::Blockquote(t::MessageEntityBlockquote {
+                    collapsed: value.collapsed || kind == "expandableblockquote",
+                    offset,
+                    length,
+                })
+            }
+            "texturl" | "textlink" => MessageEntity::TextUrl(t::MessageEntityTextUrl {
+                offset,
+                length,
+                url: value.url.ok_or_else(|| {
+                    Error::entity_decode(format!("missing field `url` for {kind}"))
+                })?,
+            }),
+            "mentionname" | "textmention" => {
+                MessageEntity::MentionName(t::MessageEntityMentionName {
+                    offset,
+                    length,
+                    user_id: value.user_id.ok_or_else(|| {
+                        Error::entity_decode(format!("missing field `user_id` for {kind}"))
+                    })?,
+                })
+            }
+            "customemoji" => MessageEntity::CustomEmoji(t::MessageEntityCustomEmoji {
+                offset,
+                length,
+                document_id: value.custom_emoji_id.ok_or_else(|| {
+                    Error::entity_decode(format!("missing field `custom_emoji_id` for {kind}"))
+                })?,
+            }),
+            _ => return Err(Error::UnsupportedEntity { kind: value.kind }.into()),
+        };
+        Ok(ret)
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(tag = "_")]
+#[allow(clippy::enum_variant_names)]
 enum TelethonEntity {
     MessageEntityUnknown(tl::types::MessageEntityUnknown),
     MessageEntityMention(tl::types::MessageEntityMention),
@@ -31,6 +283,10 @@ enum TelethonEntity {
     MessageEntityPre(tl::types::MessageEntityPre),
     MessageEntityTextUrl(tl::types::MessageEntityTextUrl),
     MessageEntityMentionName(tl::types::MessageEntityMentionName),
+    #[serde(
+        rename = "InputMessageEntityMentionName",
+        alias = "MessageEntityInputMessageEntityMentionName"
+    )]
     MessageEntityInputMessageEntityMentionName(tl::types::InputMessageEntityMentionName),
     MessageEntityPhone(tl::types::MessageEntityPhone),
     MessageEntityCashtag(tl::types::MessageEntityCashtag),
@@ -41,9 +297,9 @@ enum TelethonEntity {
     MessageEntityCustomEmoji(tl::types::MessageEntityCustomEmoji),
     MessageEntityBlockquote(tl::types::MessageEntityBlockquote),
 }
-impl Into<MessageEntity> for TelethonEntity {
-    fn into(self) -> MessageEntity {
-        match self {
+impl From<TelethonEntity> for MessageEntity {
+    fn from(value: TelethonEntity) -> Self {
+        match value {
             TelethonEntity::MessageEntityUnknown(unknown) => MessageEntity::Unknown(unknown),
             TelethonEntity::MessageEntityMention(mention) => MessageEntity::Mention(mention),
             TelethonEntity::MessageEntityHashtag(hashtag) => MessageEntity::Hashtag(hashtag),
@@ -72,3 +328,324 @@ impl Into<MessageEntity> for TelethonEntity {
         }
     }
 }
+
+impl From<MessageEntity> for TelethonEntity {
+    fn from(value: MessageEntity) -> Self {
+        match value {
+            MessageEntity::Unknown(unknown) => TelethonEntity::MessageEntityUnknown(unknown),
+            MessageEntity::Mention(mention) => TelethonEntity::MessageEntityMention(mention),
+            MessageEntity::Hashtag(hashtag) => TelethonEntity::MessageEntityHashtag(hashtag),
+            MessageEntity::BotCommand(bc) => TelethonEntity::MessageEntityBotCommand(bc),
+            MessageEntity::Url(url) => TelethonEntity::MessageEntityUrl(url),
+            MessageEntity::Email(email) => TelethonEntity::MessageEntityEmail(email),
+            MessageEntity::Bold(b) => TelethonEntity::MessageEntityBold(b),
+            MessageEntity::Italic(i) => TelethonEntity::MessageEntityItalic(i),
+            MessageEntity::Code(code) => TelethonEntity::MessageEntityCode(code),
+            MessageEntity::Pre(pre) => TelethonEntity::MessageEntityPre(pre),
+            MessageEntity::TextUrl(text_url) => TelethonEntity::MessageEntityTextUrl(text_url),
+            MessageEntity::MentionName(mention) => {
+                TelethonEntity::MessageEntityMentionName(mention)
+            }
+            MessageEntity::InputMessageEntityMentionName(input_message_entity_mention_name) => {
+                TelethonEntity::MessageEntityInputMessageEntityMentionName(
+                    input_message_entity_mention_name,
+                )
+            }
+            MessageEntity::Phone(phone) => TelethonEntity::MessageEntityPhone(phone),
+            MessageEntity::Cashtag(c) => TelethonEntity::MessageEntityCashtag(c),
+            MessageEntity::Underline(u) => TelethonEntity::MessageEntityUnderline(u),
+            MessageEntity::Strike(s) => TelethonEntity::MessageEntityStrike(s),
+            MessageEntity::BankCard(bc) => TelethonEntity::MessageEntityBankCard(bc),
+            MessageEntity::Spoiler(spo) => TelethonEntity::MessageEntitySpoiler(spo),
+            MessageEntity::CustomEmoji(e) => TelethonEntity::MessageEntityCustomEmoji(e),
+            MessageEntity::Blockquote(b) => TelethonEntity::MessageEntityBlockquote(b),
+        }
+    }
+}
+
+/// 支持互相转换的消息格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// telethon的`Message.to_dict()`, 文本在`message`字段, entities在`entities`字段
+    Telethon,
+    /// Bot API的Message对象, 文本在`text`或`caption`字段
+    BotApi,
+    /// Telegram Desktop导出的`result.json`中的消息
+    Desktop,
+    /// Bot API的HTML格式, 为`{"html": "..."}`或一个JSON字符串
+    Html,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "telethon" => Ok(Format::Telethon),
+            "botapi" | "bot-api" | "bot_api" => Ok(Format::BotApi),
+            "desktop" | "tdesktop" => Ok(Format::Desktop),
+            "html" => Ok(Format::Html),
+            _ => Err(anyhow!("unknown format: {s}")),
+        }
+    }
+}
+
+/// 与格式无关的消息, 仅包含文本和entities
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub id: Option<i64>,
+    pub text: String,
+    pub entities: Vec<MessageEntity>,
+}
+
+impl Message {
+    /// 从指定格式的json对象读取消息
+    pub fn from_value(format: Format, value: &Value) -> Result<Self> {
+        let id = value.get("id").and_then(Value::as_i64);
+        let (text, entities) = match format {
+            Format::Telethon => {
+                let text = value
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string();
+                let entities = match value.get("entities") {
+                    Some(Value::Array(entities)) => entities
+                        .iter()
+                        .map(|x| {
+                            let entity = serde_json::from_value::<TelethonEntity>(x.clone())
+                                .map_err(Error::from)?;
+                            Ok(entity.into())
+                        })
+                        .collect::<Result<_>>()?,
+                    _ => vec![],
+                };
+                (text, entities)
+            }
+            Format::BotApi => botapi::from_value(value)?,
+            Format::Desktop => desktop::from_value(value)?,
+            Format::Html => {
+                let html = match value {
+                    Value::String(html) => html.as_str(),
+                    _ => value
+                        .get("html")
+                        .and_then(Value::as_str)
+                        .ok_or(anyhow!("missing field `html`"))?,
+                };
+                html::parse(html)?
+            }
+        };
+        Ok(Self { id, text, entities })
+    }
+
+    /// 将消息写为指定格式的json对象
+    pub fn to_value(&self, format: Format) -> Result<Value> {
+        let mut ret = match format {
+            Format::Telethon => {
+                let entities = self
+                    .entities
+                    .iter()
+                    .cloned()
+                    .map(TelethonEntity::from)
+                    .collect::<Vec<_>>();
+                json!({
+                    "_": "Message",
+                    "message": self.text,
+                    "entities": entities,
+                })
+            }
+            Format::BotApi => botapi::to_value(&self.text, &self.entities)?,
+            Format::Desktop => desktop::to_value(&self.text, &self.entities)?,
+            Format::Html => json!({ "html": html::render(&self.text, &self.entities)? }),
+        };
+        if let (Some(id), Some(obj)) = (self.id, ret.as_object_mut()) {
+            obj.insert("id".to_string(), id.into());
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Message {
+        use tl::types as t;
+        // "Hi 👋 bold link @user"
+        Message {
+            id: Some(1),
+            text: "Hi 👋 bold link @user".to_string(),
+            entities: vec![
+                MessageEntity::Bold(t::MessageEntityBold {
+                    offset: 6,
+                    length: 9,
+                }),
+                MessageEntity::TextUrl(t::MessageEntityTextUrl {
+                    offset: 11,
+                    length: 4,
+                    url: "https://example.com/?a=1&b=2".to_string(),
+                }),
+                MessageEntity::Mention(t::MessageEntityMention {
+                    offset: 16,
+                    length: 5,
+                }),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut msg = sample();
+        for format in [Format::Telethon, Format::BotApi] {
+            let value = msg.to_value(format).unwrap();
+            assert_eq!(Message::from_value(format, &value).unwrap(), msg, "{format:?}");
+        }
+        // 导出格式不支持嵌套, 仅在entities不重叠时无损
+        msg.entities[0] = MessageEntity::Bold(tl::types::MessageEntityBold {
+            offset: 6,
+            length: 5,
+        });
+        let value = msg.to_value(Format::Desktop).unwrap();
+        assert_eq!(Message::from_value(Format::Desktop, &value).unwrap(), msg);
+    }
+
+    #[test]
+    fn test_html() {
+        let msg = sample();
+        let value = msg.to_value(Format::Html).unwrap();
+        assert_eq!(
+            value["html"],
+            "Hi 👋 <b>bold <a href=\"https://example.com/?a=1&amp;b=2\">link</a></b> @user"
+        );
+        let parsed = Message::from_value(Format::Html, &value).unwrap();
+        assert_eq!(parsed.text, msg.text);
+        // 提及由服务器识别, 不在HTML中
+        assert_eq!(parsed.entities, msg.entities[..2]);
+
+        let (text, entities) =
+            html::parse("<b>a<i>b</b>c</i> <pre><code class=\"language-rust\">x &lt; 1</code></pre>")
+                .unwrap();
+        assert_eq!(text, "abc x < 1");
+        assert_eq!(
+            html::render(&text, &entities).unwrap(),
+            "<b>a<i>b</i></b><i>c</i> <pre><code class=\"language-rust\">x &lt; 1</code></pre>"
+        );
+    }
+
+    #[test]
+    fn test_entity_fields() {
+        use tl::types as t;
+        let fields = |kind: &str| EntityFields {
+            kind: kind.to_string(),
+            offset: 1,
+            length: 2,
+            ..Default::default()
+        };
+        let bold = MessageEntity::Bold(t::MessageEntityBold {
+            offset: 1,
+            length: 2,
+        });
+        for kind in [
+            "MessageEntityBold",
+            "MessageEntityType.BOLD",
+            "BOLD",
+            "bold",
+        ] {
+            assert_eq!(MessageEntity::try_from(fields(kind)).unwrap(), bold);
+        }
+        let link = EntityFields {
+            url: Some("https://t.me".to_string()),
+            ..fields("text_link")
+        };
+        assert_eq!(
+            MessageEntity::try_from(link).unwrap(),
+            MessageEntity::TextUrl(t::MessageEntityTextUrl {
+                offset: 1,
+                length: 2,
+                url: "https://t.me".to_string(),
+            })
+        );
+        let mention = EntityFields {
+            user_id: Some(42),
+            ..fields("InputMessageEntityMentionName")
+        };
+        assert!(matches!(
+            MessageEntity::try_from(mention).unwrap(),
+            MessageEntity::MentionName(t::MessageEntityMentionName { user_id: 42, .. })
+        ));
+        for entity in sample().entities {
+            let fields = EntityFields::from(&entity);
+            assert_eq!(MessageEntity::try_from(fields).unwrap(), entity);
+        }
+        let err = MessageEntity::try_from(fields("MessageEntityTextUrl")).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(Error::EntityDecode { line: 0, .. })
+        ));
+        let err = MessageEntity::try_from(fields("MessageEntityFoo")).unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&Error::UnsupportedEntity {
+                kind: "MessageEntityFoo".to_string()
+            })
+        );
+        // GramJS和Bot API的格式
+        let entities = deserialize_entities(
+            r#"[
+                {"className": "MessageEntityMentionName", "offset": 1, "length": 2, "userId": "42"},
+                {"className": "InputMessageEntityMentionName", "offset": 1, "length": 2,
+                 "userId": {"className": "InputUser", "userId": "42", "accessHash": "-1"}},
+                {"type": "text_mention", "offset": 1, "length": 2, "user": {"id": 42}},
+                {"type": "custom_emoji", "offset": 1, "length": 2, "custom_emoji_id": "7"},
+                {"className": "MessageEntityCustomEmoji", "offset": 1, "length": 2, "documentId": 7}
+            ]"#,
+        )
+        .unwrap();
+        assert!(entities[..3].iter().all(|x| matches!(
+            x,
+            MessageEntity::MentionName(t::MessageEntityMentionName { user_id: 42, .. })
+        )));
+        assert!(entities[3..].iter().all(|x| matches!(
+            x,
+            MessageEntity::CustomEmoji(t::MessageEntityCustomEmoji { document_id: 7, .. })
+        )));
+        let telethon = serialize_telethon_entities(&sample().entities).unwrap();
+        assert_eq!(deserialize_entities(&telethon).unwrap(), sample().entities);
+        let err =
+            deserialize_entity(r#"{"type": "bold", "offset": "x", "length": 1}"#).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(Error::EntityDecode { .. })
+        ));
+
+        let err = deserialize_telethon_entities("[{").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(Error::EntityDecode { line: 1, .. })
+        ));
+        // 第2个字符为代理对
+        let err = crate::extract::entity::utf16_range_to_utf8("a😀b", 2, 2).unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&Error::Offset {
+                offset: 2,
+                length: 2,
+                index: 2,
+                text_length: 4
+            })
+        );
+    }
+
+    #[test]
+    fn test_desktop_overlap() {
+        let msg = sample();
+        let value = msg.to_value(Format::Desktop).unwrap();
+        let types = value["text_entities"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x["type"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(types, ["plain", "bold", "text_link", "plain", "mention"]);
+    }
+}
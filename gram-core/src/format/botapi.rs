@@ -0,0 +1,341 @@
+use crate::error::Error;
+use anyhow::Result;
+use grammers_tl_types as tl;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tl::enums::MessageEntity;
+
+/// 将Bot API的entities的json列表转换为grammers的entities
+/// Bot API没有对应类型的entity(如未知类型)会被忽略
+pub fn deserialize_botapi_entities(entities: &str) -> Result<Vec<MessageEntity>> {
+    let entities: Vec<Value> = serde_json::from_str(entities).map_err(Error::from)?;
+    Ok(entities_from_values(&entities))
+}
+
+/// 将grammers的entities转换为Bot API的entities的json列表
+pub fn serialize_botapi_entities(entities: &[MessageEntity]) -> Result<String> {
+    let entities = entities
+        .iter()
+        .filter_map(BotApiEntity::from_entity)
+        .collect::<Vec<_>>();
+    Ok(serde_json::to_string(&entities)?)
+}
+
+/// 读取Bot API的Message对象, 支持`text`/`entities`和`caption`/`caption_entities`
+pub(super) fn from_value(value: &Value) -> Result<(String, Vec<MessageEntity>)> {
+    let (text, entities) = match value.get("text") {
+        Some(text) => (text, value.get("entities")),
+        None => (
+            value.get("caption").unwrap_or(&Value::Null),
+            value.get("caption_entities"),
+        ),
+    };
+    let text = text.as_str().unwrap_or_default().to_string();
+    let entities = match entities {
+        Some(Value::Array(entities)) => entities_from_values(entities),
+        _ => vec![],
+    };
+    Ok((text, entities))
+}
+
+pub(super) fn to_value(text: &str, entities: &[MessageEntity]) -> Result<Value> {
+    let entities = entities
+        .iter()
+        .filter_map(BotApiEntity::from_entity)
+        .collect::<Vec<_>>();
+    Ok(json!({ "text": text, "entities": entities }))
+}
+
+fn entities_from_values(entities: &[Value]) -> Vec<MessageEntity> {
+    entities
+        .iter()
+        .filter_map(|x| serde_json::from_value::<BotApiEntity>(x.clone()).ok())
+        .map(BotApiEntity::into_entity)
+        .collect()
+}
+
+#[derive(Serialize, Deserialize)]
+struct User {
+    id: i64,
+}
+
+/// Bot API的MessageEntity, 参见<https://core.telegram.org/bots/api#messageentity>
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BotApiEntity {
+    Mention {
+        offset: i32,
+        length: i32,
+    },
+    Hashtag {
+        offset: i32,
+        length: i32,
+    },
+    Cashtag {
+        offset: i32,
+        length: i32,
+    },
+    BotCommand {
+        offset: i32,
+        length: i32,
+    },
+    Url {
+        offset: i32,
+        length: i32,
+    },
+    Email {
+        offset: i32,
+        length: i32,
+    },
+    PhoneNumber {
+        offset: i32,
+        length: i32,
+    },
+    Bold {
+        offset: i32,
+        length: i32,
+    },
+    Italic {
+        offset: i32,
+        length: i32,
+    },
+    Underline {
+        offset: i32,
+        length: i32,
+    },
+    Strikethrough {
+        offset: i32,
+        length: i32,
+    },
+    Spoiler {
+        offset: i32,
+        length: i32,
+    },
+    Blockquote {
+        offset: i32,
+        length: i32,
+    },
+    ExpandableBlockquote {
+        offset: i32,
+        length: i32,
+    },
+    Code {
+        offset: i32,
+        length: i32,
+    },
+    Pre {
+        offset: i32,
+        length: i32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        language: Option<String>,
+    },
+    TextLink {
+        offset: i32,
+        length: i32,
+        url: String,
+    },
+    TextMention {
+        offset: i32,
+        length: i32,
+        user: User,
+    },
+    CustomEmoji {
+        offset: i32,
+        length: i32,
+        custom_emoji_id: String,
+    },
+}
+
+impl BotApiEntity {
+    fn into_entity(self) -> MessageEntity {
+        use tl::types as t;
+        match self {
+            BotApiEntity::Mention { offset, length } => {
+                MessageEntity::Mention(t::MessageEntityMention { offset, length })
+            }
+            BotApiEntity::Hashtag { offset, length } => {
+                MessageEntity::Hashtag(t::MessageEntityHashtag { offset, length })
+            }
+            BotApiEntity::Cashtag { offset, length } => {
+                MessageEntity::Cashtag(t::MessageEntityCashtag { offset, length })
+            }
+            BotApiEntity::BotCommand { offset, length } => {
+                MessageEntity::BotCommand(t::MessageEntityBotCommand { offset, length })
+            }
+            BotApiEntity::Url { offset, length } => {
+                MessageEntity::Url(t::MessageEntityUrl { offset, length })
+            }
+            BotApiEntity::Email { offset, length } => {
+                MessageEntity::Email(t::MessageEntityEmail { offset, length })
+            }
+            BotApiEntity::PhoneNumber { offset, length } => {
+                MessageEntity::Phone(t::MessageEntityPhone { offset, length })
+            }
+            BotApiEntity::Bold { offset, length } => {
+                MessageEntity::Bold(t::MessageEntityBold { offset, length })
+            }
+            BotApiEntity::Italic { offset, length } => {
+                MessageEntity::Italic(t::MessageEntityItalic { offset, length })
+            }
+            BotApiEntity::Underline { offset, length } => {
+                MessageEntity::Underline(t::MessageEntityUnderline { offset, length })
+            }
+            BotApiEntity::Strikethrough { offset, length } => {
+                MessageEntity::Strike(t::MessageEntityStrike { offset, length })
+            }
+            BotApiEntity::Spoiler { offset, length } => {
+                MessageEntity::Spoiler(t::MessageEntitySpoiler { offset, length })
+            }
+            BotApiEntity::Blockquote { offset, length } => {
+                MessageEntity::Blockquote(t::MessageEntityBlockquote {
+                    collapsed: false,
+                    offset,
+                    length,
+                })
+            }
+            BotApiEntity::ExpandableBlockquote { offset, length } => {
+                MessageEntity::Blockquote(t::MessageEntityBlockquote {
+                    collapsed: true,
+                    offset,
+                    length,
+                })
+            }
+            BotApiEntity::Code { offset, length } => {
+                MessageEntity::Code(t::MessageEntityCode { offset, length })
+            }
+            BotApiEntity::Pre {
+                offset,
+                length,
+                language,
+            } => MessageEntity::Pre(t::MessageEntityPre {
+                offset,
+                length,
+                language: language.unwrap_or_default(),
+            }),
+            BotApiEntity::TextLink {
+                offset,
+                length,
+                url,
+            } => MessageEntity::TextUrl(t::MessageEntityTextUrl {
+                offset,
+                length,
+                url,
+            }),
+            BotApiEntity::TextMention {
+                offset,
+                length,
+                user,
+            } => MessageEntity::MentionName(t::MessageEntityMentionName {
+                offset,
+                length,
+                user_id: user.id,
+            }),
+            BotApiEntity::CustomEmoji {
+                offset,
+                length,
+                custom_emoji_id,
+            } => MessageEntity::CustomEmoji(t::MessageEntityCustomEmoji {
+                offset,
+                length,
+                document_id: custom_emoji_id.parse().unwrap_or_default(),
+            }),
+        }
+    }
+
+    /// Bot API中没有对应类型的entity返回None
+    fn from_entity(entity: &MessageEntity) -> Option<Self> {
+        use tl::types as t;
+        let ret = match entity.clone() {
+            MessageEntity::Mention(t::MessageEntityMention { offset, length }) => {
+                BotApiEntity::Mention { offset, length }
+            }
+            MessageEntity::Hashtag(t::MessageEntityHashtag { offset, length }) => {
+                BotApiEntity::Hashtag { offset, length }
+            }
+            MessageEntity::Cashtag(t::MessageEntityCashtag { offset, length }) => {
+                BotApiEntity::Cashtag { offset, length }
+            }
+            MessageEntity::BotCommand(t::MessageEntityBotCommand { offset, length }) => {
+                BotApiEntity::BotCommand { offset, length }
+            }
+            MessageEntity::Url(t::MessageEntityUrl { offset, length }) => {
+                BotApiEntity::Url { offset, length }
+            }
+            MessageEntity::Email(t::MessageEntityEmail { offset, length }) => {
+                BotApiEntity::Email { offset, length }
+            }
+            MessageEntity::Phone(t::MessageEntityPhone { offset, length }) => {
+                BotApiEntity::PhoneNumber { offset, length }
+            }
+            MessageEntity::Bold(t::MessageEntityBold { offset, length }) => {
+                BotApiEntity::Bold { offset, length }
+            }
+            MessageEntity::Italic(t::MessageEntityItalic { offset, length }) => {
+                BotApiEntity::Italic { offset, length }
+            }
+            MessageEntity::Underline(t::MessageEntityUnderline { offset, length }) => {
+                BotApiEntity::Underline { offset, length }
+            }
+            MessageEntity::Strike(t::MessageEntityStrike { offset, length }) => {
+                BotApiEntity::Strikethrough { offset, length }
+            }
+            MessageEntity::Spoiler(t::MessageEntitySpoiler { offset, length }) => {
+                BotApiEntity::Spoiler { offset, length }
+            }
+            MessageEntity::Blockquote(t::MessageEntityBlockquote {
+                collapsed,
+                offset,
+                length,
+            }) => {
+                if collapsed {
+                    BotApiEntity::ExpandableBlockquote { offset, length }
+                } else {
+                    BotApiEntity::Blockquote { offset, length }
+                }
+            }
+            MessageEntity::Code(t::MessageEntityCode { offset, length }) => {
+                BotApiEntity::Code { offset, length }
+            }
+            MessageEntity::Pre(t::MessageEntityPre {
+                offset,
+                length,
+                language,
+            }) => BotApiEntity::Pre {
+                offset,
+                length,
+                language: (!language.is_empty()).then_some(language),
+            },
+            MessageEntity::TextUrl(t::MessageEntityTextUrl {
+                offset,
+                length,
+                url,
+            }) => BotApiEntity::TextLink {
+                offset,
+                length,
+                url,
+            },
+            MessageEntity::MentionName(t::MessageEntityMentionName {
+                offset,
+                length,
+                user_id,
+            }) => BotApiEntity::TextMention {
+                offset,
+                length,
+                user: User { id: user_id },
+            },
+            MessageEntity::CustomEmoji(t::MessageEntityCustomEmoji {
+                offset,
+                length,
+                document_id,
+            }) => BotApiEntity::CustomEmoji {
+                offset,
+                length,
+                custom_emoji_id: document_id.to_string(),
+            },
+            MessageEntity::Unknown(_)
+            | MessageEntity::BankCard(_)
+            | MessageEntity::InputMessageEntityMentionName(_) => return None,
+        };
+        Some(ret)
+    }
+}
@@ -0,0 +1,219 @@
+use crate::extract::entity::{entity_range, utf16_range_to_utf8};
+use anyhow::{Result, anyhow};
+use grammers_tl_types as tl;
+use serde_json::{Map, Value, json};
+use tl::enums::MessageEntity;
+
+/// 读取Telegram Desktop导出的消息
+/// 优先使用`text_entities`, 旧版导出只有`text`字段, 其值为字符串或由字符串和对象组成的列表
+pub(super) fn from_value(value: &Value) -> Result<(String, Vec<MessageEntity>)> {
+    let parts = match (value.get("text_entities"), value.get("text")) {
+        (Some(Value::Array(parts)), _) => parts.clone(),
+        (_, Some(Value::Array(parts))) => parts.clone(),
+        (_, Some(Value::String(text))) => vec![Value::String(text.clone())],
+        _ => vec![],
+    };
+
+    let mut text = String::new();
+    let mut offset = 0;
+    let mut entities = vec![];
+    for part in parts {
+        let (kind, part_text) = match &part {
+            Value::String(s) => ("plain", s.as_str()),
+            Value::Object(obj) => (
+                obj.get("type").and_then(Value::as_str).unwrap_or("plain"),
+                obj.get("text")
+                    .and_then(Value::as_str)
+                    .ok_or(anyhow!("missing field `text` in text entity"))?,
+            ),
+            _ => return Err(anyhow!("invalid text entity: {part}")),
+        };
+        let length = part_text.encode_utf16().count() as i32;
+        text.push_str(part_text);
+        if let Some(ent) = entity_from_part(kind, offset, length, &part) {
+            entities.push(ent);
+        }
+        offset += length;
+    }
+    Ok((text, entities))
+}
+
+/// 写为Telegram Desktop导出的消息格式
+/// 导出格式不支持嵌套, 重叠的entities按最内层拆分
+pub(super) fn to_value(text: &str, entities: &[MessageEntity]) -> Result<Value> {
+    let ranges = entities
+        .iter()
+        .filter_map(|ent| {
+            let (offset, length) = entity_range(ent)?;
+            desktop_type(ent)?;
+            Some((offset, offset + length, ent))
+        })
+        .collect::<Vec<_>>();
+    let total = text.encode_utf16().count() as i32;
+    let mut bounds = ranges
+        .iter()
+        .flat_map(|(l, r, _)| [*l, *r])
+        .chain([0, total])
+        .filter(|x| (0..=total).contains(x))
+        .collect::<Vec<_>>();
+    bounds.sort();
+    bounds.dedup();
+
+    // (起点, 终点, 对应entity在ranges中的下标)
+    let mut segments: Vec<(i32, i32, Option<usize>)> = vec![];
+    for w in bounds.windows(2) {
+        let (l, r) = (w[0], w[1]);
+        // 起点最靠后的即为最内层
+        let ent = ranges
+            .iter()
+            .enumerate()
+            .filter(|(_, (el, er, _))| *el <= l && r <= *er)
+            .max_by_key(|(_, (el, er, _))| (*el, -*er))
+            .map(|x| x.0);
+        match segments.last_mut() {
+            Some(last) if last.2 == ent => last.1 = r,
+            _ => segments.push((l, r, ent)),
+        }
+    }
+
+    let mut parts = vec![];
+    for (l, r, ent) in segments {
+        let (bl, br) = utf16_range_to_utf8(text, l as usize, (r - l) as usize)?;
+        let part_text = &text[bl..br];
+        let part = match ent {
+            Some(i) => part_from_entity(ranges[i].2, part_text),
+            None => json!({ "type": "plain", "text": part_text }),
+        };
+        parts.push(part);
+    }
+
+    let text_value = if parts.iter().all(|x| x["type"] == "plain") {
+        Value::String(text.to_string())
+    } else {
+        Value::Array(
+            parts
+                .iter()
+                .map(|x| {
+                    if x["type"] == "plain" {
+                        x["text"].clone()
+                    } else {
+                        x.clone()
+                    }
+                })
+                .collect(),
+        )
+    };
+    Ok(json!({ "text": text_value, "text_entities": parts }))
+}
+
+fn entity_from_part(kind: &str, offset: i32, length: i32, part: &Value) -> Option<MessageEntity> {
+    use tl::types as t;
+    let str_field = |name: &str| {
+        part.get(name)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+    let ret = match kind {
+        "mention" => MessageEntity::Mention(t::MessageEntityMention { offset, length }),
+        "hashtag" => MessageEntity::Hashtag(t::MessageEntityHashtag { offset, length }),
+        "cashtag" => MessageEntity::Cashtag(t::MessageEntityCashtag { offset, length }),
+        "bot_command" => MessageEntity::BotCommand(t::MessageEntityBotCommand { offset, length }),
+        "link" => MessageEntity::Url(t::MessageEntityUrl { offset, length }),
+        "email" => MessageEntity::Email(t::MessageEntityEmail { offset, length }),
+        "phone" => MessageEntity::Phone(t::MessageEntityPhone { offset, length }),
+        "bold" => MessageEntity::Bold(t::MessageEntityBold { offset, length }),
+        "italic" => MessageEntity::Italic(t::MessageEntityItalic { offset, length }),
+        "underline" => MessageEntity::Underline(t::MessageEntityUnderline { offset, length }),
+        "strikethrough" => MessageEntity::Strike(t::MessageEntityStrike { offset, length }),
+        "spoiler" => MessageEntity::Spoiler(t::MessageEntitySpoiler { offset, length }),
+        "code" => MessageEntity::Code(t::MessageEntityCode { offset, length }),
+        "bank_card" => MessageEntity::BankCard(t::MessageEntityBankCard { offset, length }),
+        "blockquote" => MessageEntity::Blockquote(t::MessageEntityBlockquote {
+            collapsed: part
+                .get("collapsed")
+                .and_then(Value::as_bool)
+                .unwrap_or_default(),
+            offset,
+            length,
+        }),
+        "pre" => MessageEntity::Pre(t::MessageEntityPre {
+            offset,
+            length,
+            language: str_field("language"),
+        }),
+        "text_link" => MessageEntity::TextUrl(t::MessageEntityTextUrl {
+            offset,
+            length,
+            url: str_field("href"),
+        }),
+        "mention_name" => MessageEntity::MentionName(t::MessageEntityMentionName {
+            offset,
+            length,
+            user_id: part.get("user_id").and_then(Value::as_i64)?,
+        }),
+        "custom_emoji" => MessageEntity::CustomEmoji(t::MessageEntityCustomEmoji {
+            offset,
+            length,
+            document_id: str_field("document_id").parse().unwrap_or_default(),
+        }),
+        _ => return None,
+    };
+    Some(ret)
+}
+
+fn desktop_type(entity: &MessageEntity) -> Option<&'static str> {
+    let ret = match entity {
+        MessageEntity::Mention(_) => "mention",
+        MessageEntity::Hashtag(_) => "hashtag",
+        MessageEntity::Cashtag(_) => "cashtag",
+        MessageEntity::BotCommand(_) => "bot_command",
+        MessageEntity::Url(_) => "link",
+        MessageEntity::Email(_) => "email",
+        MessageEntity::Phone(_) => "phone",
+        MessageEntity::Bold(_) => "bold",
+        MessageEntity::Italic(_) => "italic",
+        MessageEntity::Underline(_) => "underline",
+        MessageEntity::Strike(_) => "strikethrough",
+        MessageEntity::Spoiler(_) => "spoiler",
+        MessageEntity::Code(_) => "code",
+        MessageEntity::BankCard(_) => "bank_card",
+        MessageEntity::Blockquote(_) => "blockquote",
+        MessageEntity::Pre(_) => "pre",
+        MessageEntity::TextUrl(_) => "text_link",
+        MessageEntity::MentionName(_) => "mention_name",
+        MessageEntity::CustomEmoji(_) => "custom_emoji",
+        MessageEntity::Unknown(_) | MessageEntity::InputMessageEntityMentionName(_) => {
+            return None;
+        }
+    };
+    Some(ret)
+}
+
+fn part_from_entity(entity: &MessageEntity, text: &str) -> Value {
+    let mut ret = Map::new();
+    ret.insert(
+        "type".into(),
+        desktop_type(entity).unwrap_or("plain").into(),
+    );
+    ret.insert("text".into(), text.into());
+    match entity {
+        MessageEntity::Pre(tl::types::MessageEntityPre { language, .. }) => {
+            ret.insert("language".into(), language.clone().into());
+        }
+        MessageEntity::TextUrl(tl::types::MessageEntityTextUrl { url, .. }) => {
+            ret.insert("href".into(), url.clone().into());
+        }
+        MessageEntity::MentionName(tl::types::MessageEntityMentionName { user_id, .. }) => {
+            ret.insert("user_id".into(), (*user_id).into());
+        }
+        MessageEntity::CustomEmoji(tl::types::MessageEntityCustomEmoji { document_id, .. }) => {
+            ret.insert("document_id".into(), document_id.to_string().into());
+        }
+        MessageEntity::Blockquote(tl::types::MessageEntityBlockquote { collapsed, .. }) => {
+            ret.insert("collapsed".into(), (*collapsed).into());
+        }
+        _ => {}
+    }
+    Value::Object(ret)
+}
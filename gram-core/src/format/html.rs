@@ -0,0 +1,341 @@
+use crate::error::Error;
+use crate::extract::entity::entity_range;
+use anyhow::{Result, anyhow};
+use grammers_tl_types as tl;
+use tl::enums::MessageEntity;
+
+/// 将文本和entities写为Bot API的HTML格式
+/// 参考: https://core.telegram.org/bots/api#html-style
+/// 提及、链接等由服务器自动识别的entities不会写出
+pub fn render(text: &str, entities: &[MessageEntity]) -> Result<String> {
+    // (起点, 终点, 开标签, 闭标签), 以UTF-16计
+    let tags = entities
+        .iter()
+        .filter_map(|ent| {
+            let (offset, length) = entity_range(ent)?;
+            let (open, close) = html_tags(ent)?;
+            (length > 0).then_some((offset, offset + length, open, close))
+        })
+        .collect::<Vec<_>>();
+
+    let mut ret = String::with_capacity(text.len());
+    let mut stack: Vec<usize> = vec![];
+    let mut pos = 0;
+    let mut chars = text.chars();
+    loop {
+        // 关闭在此处结束的标签, 交叉的标签先关闭再重新打开
+        if stack.iter().any(|&i| tags[i].1 <= pos) {
+            let mut reopen = vec![];
+            while let Some(i) = stack.pop() {
+                ret.push_str(&tags[i].3);
+                if tags[i].1 > pos {
+                    reopen.push(i);
+                }
+                if !stack.iter().any(|&i| tags[i].1 <= pos) {
+                    break;
+                }
+            }
+            for i in reopen.into_iter().rev() {
+                ret.push_str(&tags[i].2);
+                stack.push(i);
+            }
+        }
+
+        // 打开在此处开始的标签, 范围大的在外层
+        let mut opening = (0..tags.len())
+            .filter(|&i| tags[i].0 == pos)
+            .collect::<Vec<_>>();
+        opening.sort_by_key(|&i| -tags[i].1);
+        for i in opening {
+            ret.push_str(&tags[i].2);
+            stack.push(i);
+        }
+
+        let Some(c) = chars.next() else {
+            break;
+        };
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            c => ret.push(c),
+        }
+        pos += c.len_utf16() as i32;
+    }
+    while let Some(i) = stack.pop() {
+        ret.push_str(&tags[i].3);
+    }
+    Ok(ret)
+}
+
+type Attrs = Vec<(String, String)>;
+
+/// 解析Bot API的HTML格式, 返回纯文本和entities
+pub fn parse(html: &str) -> Result<(String, Vec<MessageEntity>)> {
+    let mut text = String::with_capacity(html.len());
+    let mut pos = 0i32;
+    let mut entities = vec![];
+    // (标签名, 起点, 属性)
+    let mut stack: Vec<(String, i32, Attrs)> = vec![];
+
+    let mut rest = html;
+    while !rest.is_empty() {
+        if rest.starts_with('<')
+            && let Some(end) = rest.find('>')
+        {
+            let tag = &rest[1..end];
+            rest = &rest[end + 1..];
+            if let Some(name) = tag.strip_prefix('/') {
+                let name = name.trim().to_lowercase();
+                let idx = stack
+                    .iter()
+                    .rposition(|x| x.0 == name)
+                    .ok_or(anyhow!("unexpected end tag </{name}>"))?;
+                let (name, start, attrs) = stack.remove(idx);
+                let length = pos - start;
+                if length == 0 {
+                    continue;
+                }
+                // `<pre><code class="language-x">`中的code只用于声明语言
+                if name == "code"
+                    && let Some(pre) = stack.last_mut().filter(|x| x.0 == "pre")
+                {
+                    if let Some(lang) = attr(&attrs, "class")
+                        .and_then(|x| x.strip_prefix("language-"))
+                        .map(String::from)
+                    {
+                        pre.2.push(("language".to_string(), lang));
+                    }
+                    continue;
+                }
+                entities.push(entity_from_tag(&name, &attrs, start, length)?);
+            } else {
+                let (name, attrs) = parse_tag(tag);
+                if name == "br" {
+                    text.push('\n');
+                    pos += 1;
+                    continue;
+                }
+                stack.push((name, pos, attrs));
+            }
+            continue;
+        }
+
+        let first = rest.chars().next().map(char::len_utf8).unwrap_or_default();
+        let next = rest[first..]
+            .find('<')
+            .map(|x| x + first)
+            .unwrap_or(rest.len());
+        let decoded = unescape(&rest[..next]);
+        pos += decoded.encode_utf16().count() as i32;
+        text.push_str(&decoded);
+        rest = &rest[next..];
+    }
+    if let Some((name, ..)) = stack.last() {
+        return Err(anyhow!("unclosed tag <{name}>"));
+    }
+
+    entities.sort_by_key(|x| {
+        let (offset, length) = entity_range(x).unwrap_or_default();
+        (offset, -length)
+    });
+    Ok((text, entities))
+}
+
+fn html_tags(entity: &MessageEntity) -> Option<(String, String)> {
+    let simple = |tag: &str| Some((format!("<{tag}>"), format!("</{tag}>")));
+    match entity {
+        MessageEntity::Bold(_) => simple("b"),
+        MessageEntity::Italic(_) => simple("i"),
+        MessageEntity::Underline(_) => simple("u"),
+        MessageEntity::Strike(_) => simple("s"),
+        MessageEntity::Spoiler(_) => simple("tg-spoiler"),
+        MessageEntity::Code(_) => simple("code"),
+        MessageEntity::Pre(tl::types::MessageEntityPre { language, .. }) => {
+            if language.is_empty() {
+                simple("pre")
+            } else {
+                Some((
+                    format!("<pre><code class=\"language-{}\">", escape(language)),
+                    "</code></pre>".to_string(),
+                ))
+            }
+        }
+        MessageEntity::TextUrl(tl::types::MessageEntityTextUrl { url, .. }) => {
+            Some((format!("<a href=\"{}\">", escape(url)), "</a>".to_string()))
+        }
+        MessageEntity::MentionName(tl::types::MessageEntityMentionName { user_id, .. }) => Some((
+            format!("<a href=\"tg://user?id={user_id}\">"),
+            "</a>".to_string(),
+        )),
+        MessageEntity::CustomEmoji(tl::types::MessageEntityCustomEmoji { document_id, .. }) => {
+            Some((
+                format!("<tg-emoji emoji-id=\"{document_id}\">"),
+                "</tg-emoji>".to_string(),
+            ))
+        }
+        MessageEntity::Blockquote(tl::types::MessageEntityBlockquote { collapsed, .. }) => {
+            if *collapsed {
+                Some((
+                    "<blockquote expandable>".to_string(),
+                    "</blockquote>".to_string(),
+                ))
+            } else {
+                simple("blockquote")
+            }
+        }
+        _ => None,
+    }
+}
+
+fn entity_from_tag(
+    name: &str,
+    attrs: &[(String, String)],
+    offset: i32,
+    length: i32,
+) -> Result<MessageEntity> {
+    use tl::types as t;
+    let ret = match name {
+        "b" | "strong" => MessageEntity::Bold(t::MessageEntityBold { offset, length }),
+        "i" | "em" => MessageEntity::Italic(t::MessageEntityItalic { offset, length }),
+        "u" | "ins" => MessageEntity::Underline(t::MessageEntityUnderline { offset, length }),
+        "s" | "strike" | "del" => MessageEntity::Strike(t::MessageEntityStrike { offset, length }),
+        "tg-spoiler" => MessageEntity::Spoiler(t::MessageEntitySpoiler { offset, length }),
+        "span" if attr(attrs, "class") == Some("tg-spoiler") => {
+            MessageEntity::Spoiler(t::MessageEntitySpoiler { offset, length })
+        }
+        "code" => MessageEntity::Code(t::MessageEntityCode { offset, length }),
+        "pre" => MessageEntity::Pre(t::MessageEntityPre {
+            offset,
+            length,
+            language: attr(attrs, "language").unwrap_or_default().to_string(),
+        }),
+        "a" => {
+            let href = attr(attrs, "href").ok_or(anyhow!("missing attribute `href` in <a>"))?;
+            match href
+                .strip_prefix("tg://user?id=")
+                .and_then(|x| x.parse().ok())
+            {
+                Some(user_id) => MessageEntity::MentionName(t::MessageEntityMentionName {
+                    offset,
+                    length,
+                    user_id,
+                }),
+                None => MessageEntity::TextUrl(t::MessageEntityTextUrl {
+                    offset,
+                    length,
+                    url: href.to_string(),
+                }),
+            }
+        }
+        "tg-emoji" => MessageEntity::CustomEmoji(t::MessageEntityCustomEmoji {
+            offset,
+            length,
+            document_id: attr(attrs, "emoji-id")
+                .and_then(|x| x.parse().ok())
+                .ok_or(anyhow!("invalid attribute `emoji-id` in <tg-emoji>"))?,
+        }),
+        "blockquote" => MessageEntity::Blockquote(t::MessageEntityBlockquote {
+            collapsed: attr(attrs, "expandable").is_some(),
+            offset,
+            length,
+        }),
+        _ => {
+            return Err(Error::UnsupportedEntity {
+                kind: format!("<{name}>"),
+            }
+            .into());
+        }
+    };
+    Ok(ret)
+}
+
+fn attr<'a>(attrs: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attrs
+        .iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// 解析开标签, 返回小写的标签名和属性列表
+/// 无值属性(如`expandable`)的值为空字符串
+fn parse_tag(tag: &str) -> (String, Attrs) {
+    let tag = tag.trim().trim_end_matches('/');
+    let (name, mut rest) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+    let mut attrs = vec![];
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        let key_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let key = rest[..key_end].to_lowercase();
+        rest = rest[key_end..].trim_start();
+        let value = if let Some(v) = rest.strip_prefix('=') {
+            let v = v.trim_start();
+            let (value, remain) = match v.chars().next() {
+                Some(q @ ('"' | '\'')) => v[1..].split_once(q).unwrap_or((&v[1..], "")),
+                _ => v.split_once(char::is_whitespace).unwrap_or((v, "")),
+            };
+            rest = remain;
+            unescape(value)
+        } else {
+            String::new()
+        };
+        attrs.push((key, value));
+    }
+    (name.to_lowercase(), attrs)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 解码HTML字符引用, 无法识别的引用原样保留
+fn unescape(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        ret.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let decoded = rest.find(';').and_then(|end| {
+            let name = &rest[1..end];
+            let c = match name {
+                "lt" => '<',
+                "gt" => '>',
+                "amp" => '&',
+                "quot" => '"',
+                "apos" => '\'',
+                _ => {
+                    let code = if let Some(hex) =
+                        name.strip_prefix("#x").or_else(|| name.strip_prefix("#X"))
+                    {
+                        u32::from_str_radix(hex, 16).ok()?
+                    } else {
+                        name.strip_prefix('#')?.parse().ok()?
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                ret.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                ret.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    ret.push_str(rest);
+    ret
+}
@@ -1,4 +1,8 @@
+pub mod error;
 pub mod format;
 pub mod log;
 pub mod extract;
 pub mod render;
+pub mod dedup;
+pub mod spam;
+pub mod unicode;
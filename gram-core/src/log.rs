@@ -1,29 +1,198 @@
-use std::sync::Once;
-
-static LOG_INIT: Once = Once::new();
-
-#[cfg(not(debug_assertions))]
-pub fn init_tracing() {
-    LOG_INIT.call_once(|| {
-        use tracing::Level;
-
-        tracing_subscriber::fmt()
-            .with_max_level(Level::INFO)
-            .with_target(false)
-            .init();
-    });
-}
-
-#[cfg(debug_assertions)]
-pub fn init_tracing() {
-    LOG_INIT.call_once(|| {
-        use tracing::Level;
-
-        tracing_subscriber::fmt()
-            .with_file(true)
-            .with_line_number(true)
-            .with_thread_names(true)
-            .with_max_level(Level::DEBUG)
-            .init();
-    });
+use anyhow::{Context, Result, anyhow};
+use std::path::PathBuf;
+use std::str::FromStr;
+use tracing_appender::rolling::RollingFileAppender;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry, fmt};
+
+pub use tracing_appender::rolling::Rotation;
+
+/// 使用默认配置初始化全局日志, 参见`LogBuilder::new`
+pub fn init_tracing() -> Result<()> {
+    LogBuilder::new().init()
+}
+
+/// 日志格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// 多行, 便于阅读
+    Pretty,
+    /// 单行
+    #[default]
+    Compact,
+    /// 每行一个JSON对象
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "pretty" => Ok(LogFormat::Pretty),
+            "compact" => Ok(LogFormat::Compact),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow!("unknown log format: {s}")),
+        }
+    }
+}
+
+/// 按周期滚动的日志文件
+#[derive(Debug, Clone)]
+struct FileSink {
+    directory: PathBuf,
+    prefix: String,
+    rotation: Rotation,
+    max_files: Option<usize>,
+}
+
+/// 全局日志的配置
+/// 过滤规则为`EnvFilter`的语法, 如`info,gram_core::render=debug`;
+/// 默认在debug构建中为`debug`并输出文件名、行号和线程名, 在release构建中为`info`
+#[derive(Debug, Clone)]
+pub struct LogBuilder {
+    directives: String,
+    env: bool,
+    format: LogFormat,
+    ansi: bool,
+    file: Option<FileSink>,
+}
+
+impl Default for LogBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogBuilder {
+    pub fn new() -> Self {
+        Self {
+            directives: match cfg!(debug_assertions) {
+                true => "debug".to_string(),
+                false => "info".to_string(),
+            },
+            env: true,
+            format: LogFormat::default(),
+            ansi: true,
+            file: None,
+        }
+    }
+
+    /// 过滤规则, `RUST_LOG`存在时被其覆盖
+    pub fn directives(mut self, directives: impl Into<String>) -> Self {
+        self.directives = directives.into();
+        self
+    }
+
+    /// 是否读取`RUST_LOG`, 默认读取
+    pub fn with_env(mut self, env: bool) -> Self {
+        self.env = env;
+        self
+    }
+
+    pub fn format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// 是否输出ANSI颜色, 写入文件时总是关闭
+    pub fn ansi(mut self, ansi: bool) -> Self {
+        self.ansi = ansi;
+        self
+    }
+
+    /// 写入`directory`下的日志文件而不是标准输出, 文件名为`prefix.日期`, 不滚动时为`prefix`
+    pub fn file(
+        mut self,
+        directory: impl Into<PathBuf>,
+        prefix: impl Into<String>,
+        rotation: Rotation,
+    ) -> Self {
+        self.file = Some(FileSink {
+            directory: directory.into(),
+            prefix: prefix.into(),
+            rotation,
+            max_files: None,
+        });
+        self
+    }
+
+    /// 保留的日志文件数量, 超出时删除最旧的文件; 需先调用`file`
+    pub fn max_files(mut self, max_files: usize) -> Self {
+        if let Some(file) = &mut self.file {
+            file.max_files = Some(max_files);
+        }
+        self
+    }
+
+    /// 生效的过滤规则
+    pub fn filter(&self) -> Result<EnvFilter> {
+        let env = match self.env {
+            true => std::env::var(EnvFilter::DEFAULT_ENV).ok(),
+            false => None,
+        };
+        let directives = env.as_deref().unwrap_or(&self.directives);
+        EnvFilter::try_new(directives).with_context(|| format!("invalid log filter: {directives}"))
+    }
+
+    /// 设为全局日志, 已存在全局subscriber时返回错误
+    pub fn init(self) -> Result<()> {
+        let filter = self.filter()?;
+        let (writer, ansi) = match &self.file {
+            Some(file) => {
+                let mut builder = RollingFileAppender::builder()
+                    .rotation(file.rotation.clone())
+                    .filename_prefix(&file.prefix);
+                if let Some(max_files) = file.max_files {
+                    builder = builder.max_log_files(max_files);
+                }
+                let appender = builder
+                    .build(&file.directory)
+                    .with_context(|| format!("create log file in {}", file.directory.display()))?;
+                (BoxMakeWriter::new(appender), false)
+            }
+            None => (BoxMakeWriter::new(std::io::stdout), self.ansi),
+        };
+
+        let debug = cfg!(debug_assertions);
+        let layer = fmt::layer()
+            .with_writer(writer)
+            .with_ansi(ansi)
+            .with_target(debug)
+            .with_file(debug)
+            .with_line_number(debug)
+            .with_thread_names(debug);
+        let layer: Box<dyn Layer<Registry> + Send + Sync> = match self.format {
+            LogFormat::Pretty => layer.pretty().boxed(),
+            LogFormat::Compact => layer.compact().boxed(),
+            LogFormat::Json => layer.json().boxed(),
+        };
+        tracing_subscriber::registry()
+            .with(layer)
+            .with(filter)
+            .try_init()
+            .map_err(|e| anyhow!("failed to set global logger: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_builder() {
+        assert_eq!("JSON".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert!("xml".parse::<LogFormat>().is_err());
+
+        let builder = LogBuilder::new()
+            .with_env(false)
+            .directives("warn,gram_core::render=trace");
+        assert_eq!(
+            builder.filter().unwrap().to_string(),
+            "gram_core::render=trace,warn"
+        );
+        assert!(builder.directives("=").init().is_err());
+    }
 }
@@ -1,2 +1,286 @@
+pub mod avatar;
+pub mod captcha;
+pub mod color;
 pub mod font;
 pub mod glyph;
+pub mod layout;
+pub mod path;
+pub mod registry;
+pub mod rich;
+
+use glyph::{Coverage, Scale};
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+use layout::{Layout, LayoutOptions, TextMetrics};
+use path::GlyphPath;
+use registry::FontRegistry;
+
+/// 渲染文本为白底黑字的灰度图, 支持多行和自动换行
+/// 使用`FontRegistry::global()`中的字体
+pub fn render_text(text: &str, scale: f32, options: &LayoutOptions) -> GrayImage {
+    let registry = FontRegistry::read_global();
+    let layout = Layout::new(text, Scale::uniform(scale), &registry, options);
+    let mut img = GrayImage::from_pixel(
+        layout.width().ceil() as u32,
+        layout.height().ceil() as u32,
+        Luma([u8::MAX]),
+    );
+
+    // 字形可能重叠, 取较深的值
+    layout.draw(|x, y, d| {
+        if let Some(pixel) = img.get_pixel_mut_checked(y, x) {
+            pixel.0[0] = pixel.0[0].min((d * 255.) as u8);
+        }
+    });
+    img
+}
+
+/// 渲染文本为RGBA图片, 彩色emoji等彩色字形保留字体自带的颜色
+/// 背景色可以是透明色
+pub fn render_text_rgba(
+    text: &str,
+    scale: f32,
+    options: &LayoutOptions,
+    foreground: Rgba<u8>,
+    background: Rgba<u8>,
+) -> RgbaImage {
+    let registry = FontRegistry::read_global();
+    let layout = Layout::new(text, Scale::uniform(scale), &registry, options);
+    let mut img = RgbaImage::from_pixel(
+        layout.width().ceil() as u32,
+        layout.height().ceil() as u32,
+        background,
+    );
+
+    layout.draw_rgba(foreground, |row, col, color| {
+        if let Some(pixel) = img.get_pixel_mut_checked(col, row) {
+            color::blend(pixel, color);
+        }
+    });
+    img
+}
+
+/// 测量文本排版后的尺寸, 不绘制图片; 排版参数与`render_text`一致
+/// `missing_glyphs`大于0表示有字符不被任何字体支持
+pub fn measure(text: &str, scale: f32, options: &LayoutOptions) -> TextMetrics {
+    let registry = FontRegistry::read_global();
+    Layout::new(text, Scale::uniform(scale), &registry, options).metrics()
+}
+
+/// 文本中每个字素簇使用的字体及没有字体支持的字符, 与`render_text`的字体回退一致
+pub fn coverage(text: &str) -> Coverage {
+    let registry = FontRegistry::read_global();
+    let options = LayoutOptions::default();
+    Layout::new(text, Scale::uniform(32.), &registry, &options).coverage()
+}
+
+/// 渲染文本为SVG, 字形轮廓转换为`<path>`, 排版和字体回退与`render_text_rgba`完全一致
+/// 位图彩色字形没有轮廓, 不会出现在SVG中
+pub fn render_svg(
+    text: &str,
+    scale: f32,
+    options: &LayoutOptions,
+    foreground: Rgba<u8>,
+    background: Rgba<u8>,
+) -> String {
+    let (width, height, paths) = layout_paths(text, scale, options, foreground);
+    path::svg_document(width, height, background, &paths)
+}
+
+/// 渲染文本为单页PDF, 文字为矢量路径, 页面尺寸与`render_text_rgba`的图片一致(1像素对应1pt)
+pub fn render_pdf(
+    text: &str,
+    scale: f32,
+    options: &LayoutOptions,
+    foreground: Rgba<u8>,
+    background: Rgba<u8>,
+) -> Vec<u8> {
+    let (width, height, paths) = layout_paths(text, scale, options, foreground);
+    path::pdf_document(width, height, background, &paths)
+}
+
+/// 排版并返回(宽, 高, 字形轮廓)
+fn layout_paths(
+    text: &str,
+    scale: f32,
+    options: &LayoutOptions,
+    foreground: Rgba<u8>,
+) -> (u32, u32, Vec<GlyphPath>) {
+    let registry = FontRegistry::read_global();
+    let layout = Layout::new(text, Scale::uniform(scale), &registry, options);
+    let (width, height) = (layout.width().ceil(), layout.height().ceil());
+    (width as u32, height as u32, layout.paths(foreground))
+}
+
+#[cfg(all(test, feature = "embedded-fonts"))]
+mod tests {
+    use super::*;
+    use path::PathCommand;
+    use std::path::PathBuf;
+
+    /// 与`tests/golden`下的参考图片比较
+    /// 设置环境变量`GRAM_UPDATE_GOLDEN=1`时重新生成参考图片
+    fn check_golden(name: &str, text: &str) {
+        let img = render_text(text, 32., &LayoutOptions::default());
+        let path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("tests/golden/{name}.png"));
+        if std::env::var_os("GRAM_UPDATE_GOLDEN").is_some() {
+            img.save(&path).unwrap();
+            return;
+        }
+        let expected = image::open(&path)
+            .unwrap_or_else(|e| panic!("{}: {e}", path.display()))
+            .into_luma8();
+        assert_eq!(img.dimensions(), expected.dimensions(), "{name}");
+        // 允许少量抗锯齿像素的差异
+        let diff = img
+            .pixels()
+            .zip(expected.pixels())
+            .filter(|(a, b)| a.0[0].abs_diff(b.0[0]) > 32)
+            .count();
+        assert!(diff * 100 <= img.len(), "{name}: {diff} pixels differ");
+    }
+
+    #[test]
+    fn test_golden_scripts() {
+        check_golden("devanagari", "नमस्ते क्षत्रिय");
+        check_golden("bengali", "বাংলা ভাষা");
+        check_golden("tamil", "தமிழ் ஸ்ரீ");
+        check_golden("thai", "สวัสดี ที่นี่");
+        check_golden("khmer", "ខ្មែរ ស្រី");
+        check_golden("hebrew", "שָׁלוֹם עוֹלָם");
+        check_golden("arabic", "مرحبا بالعالم");
+        check_golden("bidi", "abc שלום 123 def");
+        check_golden("latin", "Wafflé ﬁne");
+        check_golden("baseline", "g.T yÅ");
+        check_golden("kerning", "AVAV To Ty");
+        check_golden("overhang", "jump ƒ");
+    }
+
+    #[test]
+    fn test_rgba() {
+        let red = Rgba([255, 0, 0, 255]);
+        let transparent = Rgba([0, 0, 0, 0]);
+        let img = render_text_rgba("Hi", 32., &LayoutOptions::default(), red, transparent);
+        let gray = render_text("Hi", 32., &LayoutOptions::default());
+        assert_eq!(img.dimensions(), gray.dimensions());
+        assert_eq!(*img.get_pixel(0, 0), transparent);
+        // 与灰度图的墨迹位置一致
+        for (p, g) in img.pixels().zip(gray.pixels()) {
+            assert!(p.0[3].abs_diff(255 - g.0[0]) <= 1);
+            if p.0[3] > 0 {
+                assert_eq!(&p.0[..3], &[255, 0, 0]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_vector() {
+        let options = LayoutOptions::default();
+        let black = Rgba([0, 0, 0, 255]);
+        let white = Rgba([255, 255, 255, 255]);
+        let text = "Hello, 世界\nשלום";
+        let img = render_text(text, 32., &options);
+        let (_, _, paths) = layout_paths(text, 32., &options, black);
+
+        // 轮廓的范围与光栅图片中的墨迹一致
+        let mut ink = (f32::MAX, f32::MAX, 0f32, 0f32);
+        for (x, y, p) in img.enumerate_pixels() {
+            if p.0[0] < 128 {
+                let (x, y) = (x as f32, y as f32);
+                ink = (
+                    ink.0.min(x),
+                    ink.1.min(y),
+                    ink.2.max(x + 1.),
+                    ink.3.max(y + 1.),
+                );
+            }
+        }
+        let mut bounds = (f32::MAX, f32::MAX, 0f32, 0f32);
+        for cmd in paths.iter().flat_map(|p| &p.commands) {
+            let (x, y) = match *cmd {
+                PathCommand::MoveTo(x, y)
+                | PathCommand::LineTo(x, y)
+                | PathCommand::QuadTo(_, _, x, y)
+                | PathCommand::CurveTo(_, _, _, _, x, y) => (x, y),
+                PathCommand::Close => continue,
+            };
+            bounds = (
+                bounds.0.min(x),
+                bounds.1.min(y),
+                bounds.2.max(x),
+                bounds.3.max(y),
+            );
+        }
+        for (a, b) in [
+            (ink.0, bounds.0),
+            (ink.1, bounds.1),
+            (ink.2, bounds.2),
+            (ink.3, bounds.3),
+        ] {
+            assert!((a - b).abs() <= 1., "{ink:?} {bounds:?}");
+        }
+
+        let svg = render_svg(text, 32., &options, black, white);
+        let size = format!(r#"width="{}" height="{}""#, img.width(), img.height());
+        assert!(svg.starts_with("<svg") && svg.contains(&size));
+        assert_eq!(svg.matches("<path").count(), 1);
+
+        let pdf = render_pdf(text, 32., &options, black, white);
+        let pdf = String::from_utf8(pdf).unwrap();
+        let xref = pdf.rsplit("startxref\n").next().unwrap();
+        let xref = xref.lines().next().unwrap().parse::<usize>().unwrap();
+        assert!(pdf[xref..].starts_with("xref"));
+    }
+
+    #[test]
+    fn test_measure() {
+        let options = LayoutOptions {
+            align: layout::Align::Center,
+            ..Default::default()
+        };
+        let text = "Tom \u{0301}\u{E000}\nAlice";
+        let metrics = measure(text, 32., &options);
+        let img = render_text(text, 32., &options);
+        assert_eq!(metrics.width.ceil() as u32, img.width());
+        assert_eq!(metrics.height.ceil() as u32, img.height());
+        assert_eq!(metrics.line_count, 2);
+        assert_eq!(metrics.missing_glyphs, 1);
+        assert!(metrics.ascent > 0. && metrics.descent > 0.);
+
+        // 组合符号与前一个字符属于同一字素簇
+        let graphemes = metrics.graphemes.iter().map(|g| g.text.as_str());
+        let graphemes = graphemes.collect::<Vec<_>>();
+        assert_eq!(
+            graphemes,
+            [
+                "T",
+                "o",
+                "m",
+                " \u{0301}",
+                "\u{E000}",
+                "A",
+                "l",
+                "i",
+                "c",
+                "e"
+            ]
+        );
+        let first = &metrics.graphemes[0];
+        assert!((first.x + first.advance - metrics.graphemes[1].x).abs() < 1e-3);
+        // 居中对齐时较短的第二行向右偏移
+        assert!(metrics.graphemes[5].x > first.x);
+        assert_eq!(metrics.graphemes[5].line, 1);
+    }
+
+    #[test]
+    fn test_coverage() {
+        let coverage = coverage("a\u{E000}\u{200B}\n\u{10FFFD}\u{E000}");
+        assert_eq!(coverage.missing(), ['\u{E000}', '\u{10FFFD}']);
+        assert!(!coverage.is_complete());
+        let graphemes = &coverage.graphemes;
+        assert_eq!(graphemes.len(), 5);
+        assert_eq!(graphemes[0].font, "DejaVu Sans");
+        assert!(graphemes[2].missing.is_empty());
+        assert!(super::coverage("Hello").is_complete());
+    }
+}
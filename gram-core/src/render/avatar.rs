@@ -0,0 +1,171 @@
+use super::color::blend;
+use super::font::FontStyle;
+use super::glyph::{Scale, VecGlyph};
+use super::registry::FontRegistry;
+use image::{Rgba, RgbaImage};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Telegram客户端的头像配色, 为(上, 下)渐变色
+/// 依次为红、橙、紫、绿、青、蓝、粉, 按`peer_color_index`选取
+pub const AVATAR_COLORS: [([u8; 3], [u8; 3]); 7] = [
+    ([0xff, 0x84, 0x5e], [0xd4, 0x52, 0x46]),
+    ([0xfe, 0xbb, 0x5b], [0xf6, 0x81, 0x36]),
+    ([0xb6, 0x94, 0xf9], [0x6c, 0x61, 0xdf]),
+    ([0x9a, 0xd1, 0x64], [0x46, 0xba, 0x43]),
+    ([0x5b, 0xcb, 0xe3], [0x35, 0x9a, 0xd4]),
+    ([0x5c, 0xaf, 0xfa], [0x40, 0x8a, 0xcf]),
+    ([0xff, 0x8a, 0xac], [0xd9, 0x55, 0x74]),
+];
+
+/// Bot API中频道ID的偏移, 频道为`-1000000000000 - id`
+const CHANNEL_ID_OFFSET: i64 = 1_000_000_000_000;
+
+/// 按ID选择头像配色, 与Telegram客户端一致, 返回`AVATAR_COLORS`的下标
+/// `peer_id`可以是MTProto中的ID, 也可以是Bot API格式的ID(群组和频道为负数)
+pub fn peer_color_index(peer_id: i64) -> usize {
+    let bare = match peer_id {
+        id if id < -CHANNEL_ID_OFFSET => -(id + CHANNEL_ID_OFFSET),
+        id => id.saturating_abs(),
+    };
+    (bare % AVATAR_COLORS.len() as i64) as usize
+}
+
+/// 名称的首字母, 取第一个和最后一个单词的首个字素簇并转为大写
+/// 跳过以标点等符号开头的单词, emoji视为字母
+pub fn initials(name: &str) -> String {
+    let letters = name
+        .split_whitespace()
+        .filter_map(|word| {
+            word.graphemes(true)
+                .find(|g| g.chars().next().is_some_and(is_letter))
+        })
+        .collect::<Vec<_>>();
+    let picked = match letters.as_slice() {
+        [] => vec![],
+        [first] => vec![*first],
+        [first, .., last] => vec![*first, *last],
+    };
+    picked
+        .into_iter()
+        .flat_map(str::chars)
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+/// 字母、数字及emoji等非ASCII符号
+fn is_letter(c: char) -> bool {
+    c.is_alphanumeric() || c >= '\u{2100}'
+}
+
+/// 渲染没有头像的用户的占位头像, 即彩色渐变圆形上的白色首字母, 尺寸为`size`×`size`
+/// 圆形以外透明; 使用`FontRegistry::global()`中的字体
+pub fn avatar(name: &str, peer_id: i64, size: u32) -> RgbaImage {
+    let registry = FontRegistry::read_global();
+    avatar_with(name, peer_id, size, &registry)
+}
+
+/// 使用指定字体注册表的`avatar`
+#[tracing::instrument(level = "debug", skip(registry))]
+pub fn avatar_with(name: &str, peer_id: i64, size: u32, registry: &FontRegistry) -> RgbaImage {
+    let (top, bottom) = AVATAR_COLORS[peer_color_index(peer_id)];
+    let mut img = RgbaImage::from_fn(size, size, |_, y| {
+        let t = y as f32 / (size.max(2) - 1) as f32;
+        let channel = |i: usize| (top[i] as f32 + (bottom[i] as f32 - top[i] as f32) * t).round();
+        Rgba([channel(0) as u8, channel(1) as u8, channel(2) as u8, 255])
+    });
+
+    let text = initials(name);
+    if !text.is_empty() {
+        let style = FontStyle {
+            bold: true,
+            ..Default::default()
+        };
+        // 字号与Telegram Desktop一致
+        let scale = Scale::uniform(size as f32 * 13. / 33.);
+        let vg = VecGlyph::with_style(&text, scale, registry, style);
+        let mut glyphs = RgbaImage::new(vg.width().ceil() as u32, vg.height().ceil() as u32);
+        vg.draw_rgba(Rgba([255, 255, 255, 255]), |row, col, color| {
+            if let Some(pixel) = glyphs.get_pixel_mut_checked(col, row) {
+                blend(pixel, color);
+            }
+        });
+        // 按墨迹居中, 不同字体的字身框差异较大
+        if let Some((left, top, right, bottom)) = ink_bounds(&glyphs) {
+            let dx = (size as i32 - (right - left)) / 2 - left;
+            let dy = (size as i32 - (bottom - top)) / 2 - top;
+            for (x, y, color) in glyphs.enumerate_pixels() {
+                let (x, y) = (x as i32 + dx, y as i32 + dy);
+                if x >= 0
+                    && y >= 0
+                    && color.0[3] > 0
+                    && let Some(pixel) = img.get_pixel_mut_checked(x as u32, y as u32)
+                {
+                    blend(pixel, *color);
+                }
+            }
+        }
+    }
+
+    // 圆形遮罩, 边缘按像素中心到圆周的距离抗锯齿
+    let r = size as f32 / 2.;
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let d = ((x as f32 + 0.5 - r).powi(2) + (y as f32 + 0.5 - r).powi(2)).sqrt();
+        let coverage = (r - d + 0.5).clamp(0., 1.);
+        pixel.0[3] = (pixel.0[3] as f32 * coverage).round() as u8;
+    }
+    img
+}
+
+/// 不透明像素的范围, 为(左, 上, 右, 下), 右和下不包含
+fn ink_bounds(img: &RgbaImage) -> Option<(i32, i32, i32, i32)> {
+    let mut ret: Option<(i32, i32, i32, i32)> = None;
+    for (x, y, pixel) in img.enumerate_pixels() {
+        if pixel.0[3] == 0 {
+            continue;
+        }
+        let (x, y) = (x as i32, y as i32);
+        ret = Some(match ret {
+            None => (x, y, x + 1, y + 1),
+            Some((l, t, r, b)) => (l.min(x), t.min(y), r.max(x + 1), b.max(y + 1)),
+        });
+    }
+    ret
+}
+
+#[cfg(all(test, feature = "embedded-fonts"))]
+mod tests {
+    use super::*;
+    use std::sync::LazyLock;
+
+    static REGISTRY: LazyLock<FontRegistry> = LazyLock::new(FontRegistry::embedded);
+
+    #[test]
+    fn test_avatar() {
+        assert_eq!(initials("alice"), "A");
+        assert_eq!(initials("Alice Bob Carol"), "AC");
+        assert_eq!(initials("  (Alice) 🦀 "), "A🦀");
+        assert_eq!(initials("éric ñandú"), "ÉÑ");
+        assert_eq!(initials("e\u{0301}ric"), "E\u{0301}");
+        assert_eq!(initials("-- ."), "");
+
+        assert_eq!(peer_color_index(7), 0);
+        assert_eq!(peer_color_index(8), 1);
+        // Bot API格式的群组和频道ID
+        assert_eq!(peer_color_index(-8), 1);
+        assert_eq!(peer_color_index(-1_000_000_000_008), 1);
+        assert_eq!(peer_color_index(i64::MIN), peer_color_index(i64::MAX));
+
+        let img = avatar_with("Alice Bob", 5, 64, &REGISTRY);
+        assert_eq!(img.dimensions(), (64, 64));
+        // 角落透明, 边缘半透明, 中心有白色文字
+        assert_eq!(img.get_pixel(0, 0).0[3], 0);
+        assert!(img.pixels().any(|p| p.0[3] > 0 && p.0[3] < 255));
+        assert!(img.pixels().any(|p| p.0 == [255, 255, 255, 255]));
+        let top = img.get_pixel(32, 0).0;
+        assert_eq!(top[..3], AVATAR_COLORS[5].0);
+        assert!(top[3] > 128);
+
+        let empty = avatar_with("", 5, 64, &REGISTRY);
+        assert!(empty.pixels().all(|p| p.0[..3] != [255, 255, 255]));
+    }
+}
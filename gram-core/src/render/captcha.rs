@@ -0,0 +1,450 @@
+use super::glyph::{Scale, VecGlyph};
+use super::registry::FontRegistry;
+use crate::error::Error;
+use anyhow::Result;
+use image::{GrayImage, Luma};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::f32::consts::PI;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// 外形相同的拉丁字母和西里尔字母
+const CONFUSABLES: &[(char, char)] = &[
+    ('A', 'А'),
+    ('B', 'В'),
+    ('C', 'С'),
+    ('E', 'Е'),
+    ('H', 'Н'),
+    ('K', 'К'),
+    ('M', 'М'),
+    ('O', 'О'),
+    ('P', 'Р'),
+    ('T', 'Т'),
+    ('X', 'Х'),
+    ('a', 'а'),
+    ('c', 'с'),
+    ('e', 'е'),
+    ('o', 'о'),
+    ('p', 'р'),
+    ('x', 'х'),
+    ('y', 'у'),
+];
+
+/// 验证码参数
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptchaOptions {
+    pub scale: f32,
+    /// 随机生成答案时的长度
+    pub length: usize,
+    /// 随机生成答案时使用的字符, 默认不含容易混淆的`0O1I`等
+    pub alphabet: String,
+    /// 单个字形的最大旋转角度(度)
+    pub max_rotation: f32,
+    /// 字形位置的最大随机偏移, 相对于字体尺寸
+    pub jitter: f32,
+    /// 正弦扭曲的振幅, 相对于字体尺寸
+    pub warp: f32,
+    /// 干扰曲线的数量
+    pub noise_lines: usize,
+    /// 干扰点的数量
+    pub noise_dots: usize,
+    /// 每个字形从支持该字符的字体中随机选择
+    pub mix_fonts: bool,
+    /// 随机将部分拉丁字母替换为外形相同的西里尔字母, 答案仍为原文
+    pub confusables: bool,
+    /// 随机数种子, 相同的种子和参数生成相同的验证码; None时每次随机
+    pub seed: Option<u64>,
+}
+
+impl Default for CaptchaOptions {
+    fn default() -> Self {
+        Self {
+            scale: 48.,
+            length: 5,
+            alphabet: "ABCDEFGHJKMNPQRSTUVWXYZ23456789".to_string(),
+            max_rotation: 25.,
+            jitter: 0.12,
+            warp: 0.06,
+            noise_lines: 3,
+            noise_dots: 200,
+            mix_fonts: true,
+            confusables: false,
+            seed: None,
+        }
+    }
+}
+
+impl CaptchaOptions {
+    /// 检查参数, 尺寸须为正数, 角度、偏移和振幅须为有限值且不为负
+    fn validate(&self) -> Result<()> {
+        let check = |name: &str, value: f32, ok: bool| match ok && value.is_finite() {
+            true => Ok(()),
+            false => Err(Error::Render(format!("invalid captcha {name}: {value}"))),
+        };
+        check("scale", self.scale, self.scale > 0.)?;
+        check("max_rotation", self.max_rotation, self.max_rotation >= 0.)?;
+        check("jitter", self.jitter, self.jitter >= 0.)?;
+        check("warp", self.warp, self.warp >= 0.)?;
+        Ok(())
+    }
+}
+
+/// 生成的验证码
+pub struct Captcha {
+    /// 白底黑字的灰度图
+    pub image: GrayImage,
+    /// 期望的答案
+    pub answer: String,
+    /// 图片中实际绘制的文本, 启用`confusables`时可能含有西里尔字母
+    pub text: String,
+}
+
+/// 随机生成答案并渲染验证码, 使用`FontRegistry::global()`中的字体
+/// 参数无效(如`scale`不为正数或为NaN)时返回`Error::Render`
+pub fn generate(options: &CaptchaOptions) -> Result<Captcha> {
+    let registry = FontRegistry::read_global();
+    generate_with(options, &registry)
+}
+
+/// 使用指定字体注册表的`generate`
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn generate_with(options: &CaptchaOptions, registry: &FontRegistry) -> Result<Captcha> {
+    options.validate()?;
+    let mut rng = new_rng(options);
+    let alphabet = options.alphabet.chars().collect::<Vec<_>>();
+    let answer = match alphabet.is_empty() {
+        true => String::new(),
+        false => (0..options.length)
+            .map(|_| alphabet[rng.gen_range(0..alphabet.len())])
+            .collect(),
+    };
+    Ok(draw(&answer, options, registry, &mut rng))
+}
+
+/// 渲染指定答案的验证码, 使用`FontRegistry::global()`中的字体
+/// 参数无效时返回`Error::Render`
+pub fn render_captcha(answer: &str, options: &CaptchaOptions) -> Result<Captcha> {
+    let registry = FontRegistry::read_global();
+    render_captcha_with(answer, options, &registry)
+}
+
+/// 使用指定字体注册表的`render_captcha`
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn render_captcha_with(
+    answer: &str,
+    options: &CaptchaOptions,
+    registry: &FontRegistry,
+) -> Result<Captcha> {
+    options.validate()?;
+    Ok(draw(answer, options, registry, &mut new_rng(options)))
+}
+
+/// 检查用户的输入, 忽略大小写和空白, 西里尔字母视为外形相同的拉丁字母
+pub fn check_answer(answer: &str, input: &str) -> bool {
+    let normalize = |s: &str| {
+        s.chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| {
+                CONFUSABLES
+                    .iter()
+                    .find(|(_, cyrillic)| *cyrillic == c)
+                    .map_or(c, |(latin, _)| *latin)
+            })
+            .flat_map(char::to_lowercase)
+            .collect::<String>()
+    };
+    normalize(answer) == normalize(input)
+}
+
+fn new_rng(options: &CaptchaOptions) -> ChaCha8Rng {
+    match options.seed {
+        Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+        None => ChaCha8Rng::from_entropy(),
+    }
+}
+
+fn draw(
+    answer: &str,
+    options: &CaptchaOptions,
+    registry: &FontRegistry,
+    rng: &mut ChaCha8Rng,
+) -> Captcha {
+    let scale = options.scale;
+    let text = match options.confusables {
+        true => answer
+            .chars()
+            .map(
+                |c| match CONFUSABLES.iter().find(|(latin, _)| *latin == c) {
+                    Some((_, cyrillic)) if rng.gen_bool(0.5) => *cyrillic,
+                    _ => c,
+                },
+            )
+            .collect::<String>(),
+        false => answer.to_string(),
+    };
+
+    // 逐个字素簇绘制并旋转, 相邻字形略微重叠以增加切分难度
+    let mut glyphs = vec![];
+    let mut pen = scale * 0.3;
+    let mut right = pen;
+    for cluster in text.graphemes(true) {
+        let single = pick_font(cluster, registry, options.mix_fonts, rng);
+        let vg = VecGlyph::new(cluster, Scale::uniform(scale), &single);
+        let advance = vg.advance();
+        let angle = rng.gen_range(-1f32..=1.) * options.max_rotation.to_radians();
+        let glyph = rotate(&Ink::from_glyph(vg), angle);
+        let jitter = scale * options.jitter;
+        let center = (
+            pen + advance / 2. + rng.gen_range(-1f32..=1.) * jitter,
+            rng.gen_range(-1f32..=1.) * jitter,
+        );
+        glyphs.push((glyph, center));
+        right = right.max(pen + advance);
+        pen += advance * rng.gen_range(0.85..=1.);
+    }
+
+    let width = (right + scale * (0.3 + options.jitter)).ceil() as u32;
+    let height = (scale * (1.5 + options.jitter * 2.)).ceil() as u32;
+    let mut canvas = Ink::new(width, height);
+    for (glyph, (x, y)) in glyphs {
+        let left = x - glyph.width as f32 / 2.;
+        let top = height as f32 / 2. + y - glyph.height as f32 / 2.;
+        canvas.composite(&glyph, left.round() as i32, top.round() as i32);
+    }
+
+    let mut canvas = warp(&canvas, scale * options.warp, rng);
+    let thickness = (scale / 24.).max(1.);
+    for _ in 0..options.noise_lines {
+        let (w, h) = (width as f32, height as f32);
+        let points = [
+            (rng.gen_range(0. ..w / 4.), rng.gen_range(0. ..h)),
+            (rng.gen_range(0. ..w), rng.gen_range(0. ..h)),
+            (rng.gen_range(w * 3. / 4. ..w), rng.gen_range(0. ..h)),
+        ];
+        canvas.curve(points, thickness);
+    }
+    for _ in 0..options.noise_dots {
+        let x = rng.gen_range(0..width.max(1));
+        let y = rng.gen_range(0..height.max(1));
+        canvas.put(x as i32, y as i32, rng.gen_range(0.3..=1.));
+    }
+
+    Captcha {
+        image: canvas.to_image(),
+        answer: answer.to_string(),
+        text,
+    }
+}
+
+/// 为字素簇选择字体, 返回只含该字体的注册表
+/// 启用`mix_fonts`时从支持簇中全部字符的非emoji字体里随机选择, 否则使用原注册表的回退顺序
+fn pick_font(
+    cluster: &str,
+    registry: &FontRegistry,
+    mix_fonts: bool,
+    rng: &mut ChaCha8Rng,
+) -> FontRegistry {
+    let candidates = registry
+        .fonts()
+        .iter()
+        .filter(|f| !f.is_emoji() && cluster.chars().all(|c| f.has_glyph(c)))
+        .collect::<Vec<_>>();
+    if !mix_fonts || candidates.is_empty() {
+        return registry.clone();
+    }
+    let mut ret = FontRegistry::new();
+    ret.add(candidates[rng.gen_range(0..candidates.len())]);
+    ret
+}
+
+/// 墨迹覆盖率, 0为空白, 1为全黑
+struct Ink {
+    width: u32,
+    height: u32,
+    data: Vec<f32>,
+}
+
+impl Ink {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![0.; (width * height) as usize],
+        }
+    }
+
+    /// 绘制字形, 四周留出旋转所需的空间
+    fn from_glyph(vg: VecGlyph) -> Self {
+        let (w, h) = (vg.width().ceil(), vg.height().ceil());
+        let size = (w * w + h * h).sqrt().ceil() as u32 + 2;
+        let mut ret = Self::new(size, size);
+        let left = ((size as f32 - w) / 2.) as i32;
+        let top = ((size as f32 - h) / 2.) as i32;
+        vg.draw(|row, col, v| ret.put(left + col as i32, top + row as i32, 1. - v));
+        ret
+    }
+
+    fn get(&self, x: i32, y: i32) -> f32 {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return 0.;
+        }
+        self.data[(y as u32 * self.width + x as u32) as usize]
+    }
+
+    /// 取较深的值
+    fn put(&mut self, x: i32, y: i32, v: f32) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let pixel = &mut self.data[(y as u32 * self.width + x as u32) as usize];
+        *pixel = pixel.max(v);
+    }
+
+    /// 双线性插值采样
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i32, y0 as i32);
+        let top = self.get(x0, y0) * (1. - fx) + self.get(x0 + 1, y0) * fx;
+        let bottom = self.get(x0, y0 + 1) * (1. - fx) + self.get(x0 + 1, y0 + 1) * fx;
+        top * (1. - fy) + bottom * fy
+    }
+
+    fn composite(&mut self, other: &Ink, left: i32, top: i32) {
+        for y in 0..other.height as i32 {
+            for x in 0..other.width as i32 {
+                self.put(left + x, top + y, other.get(x, y));
+            }
+        }
+    }
+
+    /// 沿二次贝塞尔曲线绘制指定粗细的线
+    fn curve(&mut self, [p0, p1, p2]: [(f32, f32); 3], thickness: f32) {
+        let length = (p2.0 - p0.0).abs() + (p2.1 - p0.1).abs() + 1.;
+        let steps = (length * 2.) as usize;
+        let r = thickness / 2.;
+        for i in 0..=steps {
+            let t = i as f32 / steps as f32;
+            let x = (1. - t).powi(2) * p0.0 + 2. * (1. - t) * t * p1.0 + t * t * p2.0;
+            let y = (1. - t).powi(2) * p0.1 + 2. * (1. - t) * t * p1.1 + t * t * p2.1;
+            for dy in (y - r).floor() as i32..=(y + r).ceil() as i32 {
+                for dx in (x - r).floor() as i32..=(x + r).ceil() as i32 {
+                    let d = ((dx as f32 - x).powi(2) + (dy as f32 - y).powi(2)).sqrt();
+                    self.put(dx, dy, (r + 0.5 - d).clamp(0., 1.));
+                }
+            }
+        }
+    }
+
+    fn to_image(&self) -> GrayImage {
+        GrayImage::from_fn(self.width, self.height, |x, y| {
+            let v = self.get(x as i32, y as i32);
+            Luma([((1. - v) * 255.).round() as u8])
+        })
+    }
+}
+
+/// 绕中心旋转, 尺寸不变
+fn rotate(ink: &Ink, angle: f32) -> Ink {
+    let mut ret = Ink::new(ink.width, ink.height);
+    let (cx, cy) = (ink.width as f32 / 2., ink.height as f32 / 2.);
+    let (sin, cos) = angle.sin_cos();
+    for y in 0..ink.height {
+        for x in 0..ink.width {
+            // 逆映射到原图
+            let (dx, dy) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+            let sx = cos * dx + sin * dy + cx - 0.5;
+            let sy = -sin * dx + cos * dy + cy - 0.5;
+            ret.data[(y * ink.width + x) as usize] = ink.sample(sx, sy);
+        }
+    }
+    ret
+}
+
+/// 水平和竖直方向的正弦扭曲, `amplitude`为像素
+fn warp(ink: &Ink, amplitude: f32, rng: &mut ChaCha8Rng) -> Ink {
+    let mut ret = Ink::new(ink.width, ink.height);
+    let phase = (rng.gen_range(0. ..2. * PI), rng.gen_range(0. ..2. * PI));
+    let period = (
+        ink.height as f32 * rng.gen_range(1.5..2.5),
+        ink.width as f32 * rng.gen_range(0.3..0.6),
+    );
+    for y in 0..ink.height {
+        for x in 0..ink.width {
+            let (fx, fy) = (x as f32, y as f32);
+            let sx = fx + amplitude * (2. * PI * fy / period.0 + phase.0).sin();
+            let sy = fy + amplitude * (2. * PI * fx / period.1 + phase.1).sin();
+            ret.data[(y * ink.width + x) as usize] = ink.sample(sx, sy);
+        }
+    }
+    ret
+}
+
+#[cfg(all(test, feature = "embedded-fonts"))]
+mod tests {
+    use super::*;
+    use std::sync::LazyLock;
+
+    static REGISTRY: LazyLock<FontRegistry> = LazyLock::new(FontRegistry::embedded);
+
+    #[test]
+    fn test_captcha() {
+        let options = CaptchaOptions {
+            seed: Some(42),
+            ..Default::default()
+        };
+        let a = generate_with(&options, &REGISTRY).unwrap();
+        let b = generate_with(&options, &REGISTRY).unwrap();
+        assert_eq!(a.answer, b.answer);
+        assert_eq!(a.image, b.image);
+        assert_eq!(a.answer.chars().count(), 5);
+        assert!(a.answer.chars().all(|c| options.alphabet.contains(c)));
+        assert!(a.image.pixels().any(|p| p.0[0] < 128));
+
+        let c = generate_with(
+            &CaptchaOptions {
+                seed: Some(43),
+                ..options.clone()
+            },
+            &REGISTRY,
+        )
+        .unwrap();
+        assert_ne!(a.image, c.image);
+
+        // 替换为西里尔字母后答案不变
+        let options = CaptchaOptions {
+            confusables: true,
+            ..options
+        };
+        let captcha = render_captcha_with("CAPE TOKEN", &options, &REGISTRY).unwrap();
+        assert_eq!(captcha.answer, "CAPE TOKEN");
+        assert_ne!(captcha.text, captcha.answer);
+        assert!(check_answer(&captcha.answer, &captcha.text));
+        assert!(check_answer(&captcha.answer, "capetoken"));
+        assert!(!check_answer(&captcha.answer, "capetoke"));
+
+        // 无效的参数报错而不是panic或生成空图片
+        for bad in [
+            CaptchaOptions {
+                scale: 0.,
+                ..Default::default()
+            },
+            CaptchaOptions {
+                scale: f32::NAN,
+                ..Default::default()
+            },
+            CaptchaOptions {
+                jitter: -0.1,
+                ..Default::default()
+            },
+            CaptchaOptions {
+                max_rotation: f32::INFINITY,
+                ..Default::default()
+            },
+        ] {
+            let err = render_captcha_with("A", &bad, &REGISTRY).err().unwrap();
+            assert!(matches!(err.downcast_ref(), Some(Error::Render(_))));
+            assert!(generate_with(&bad, &REGISTRY).is_err());
+        }
+    }
+}
@@ -0,0 +1,1143 @@
+use super::font::Font;
+use crate::error::Error;
+use ab_glyph_rasterizer::{Rasterizer, point as raster_point};
+use anyhow::Result;
+use image::imageops::FilterType;
+use image::{ImageFormat, Rgba, RgbaImage};
+use rusttype::{GlyphId, Point, ScaledGlyph};
+use rustybuzz::ttf_parser::colr::{ClipBox, CompositeMode, GradientExtend, Paint, Painter};
+use rustybuzz::ttf_parser::{
+    self, OutlineBuilder, RasterGlyphImage, RasterImageFormat, RectF, RgbaColor, Transform,
+};
+
+/// 解析颜色, 支持`#rgb`、`#rgba`、`#rrggbb`、`#rrggbbaa`以及`black`、`white`、`transparent`
+pub fn parse_color(s: &str) -> Result<Rgba<u8>> {
+    let s = s.trim();
+    match s.to_lowercase().as_str() {
+        "black" => return Ok(Rgba([0, 0, 0, 255])),
+        "white" => return Ok(Rgba([255, 255, 255, 255])),
+        "transparent" => return Ok(Rgba([0, 0, 0, 0])),
+        _ => {}
+    }
+    let hex = s
+        .strip_prefix('#')
+        .filter(|x| x.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| Error::Render(format!("invalid color: {s}")))?;
+    let digits = hex
+        .chars()
+        .map(|c| c.to_digit(16).unwrap() as u8)
+        .collect::<Vec<_>>();
+    let channels = match digits.len() {
+        3 | 4 => digits.iter().map(|x| x * 17).collect::<Vec<_>>(),
+        6 | 8 => digits.chunks(2).map(|x| x[0] * 16 + x[1]).collect(),
+        _ => return Err(Error::Render(format!("invalid color: {s}")).into()),
+    };
+    let alpha = channels.get(3).copied().unwrap_or(255);
+    Ok(Rgba([channels[0], channels[1], channels[2], alpha]))
+}
+
+/// 按source-over将`src`叠加到`dst`上, 颜色均为非预乘alpha
+pub fn blend(dst: &mut Rgba<u8>, src: Rgba<u8>) {
+    let sa = src.0[3] as f32 / 255.;
+    let da = dst.0[3] as f32 / 255.;
+    let a = sa + da * (1. - sa);
+    if a <= 0. {
+        *dst = Rgba([0, 0, 0, 0]);
+        return;
+    }
+    for i in 0..3 {
+        let c = (src.0[i] as f32 * sa + dst.0[i] as f32 * da * (1. - sa)) / a;
+        dst.0[i] = c.round() as u8;
+    }
+    dst.0[3] = (a * 255.).round() as u8;
+}
+
+/// 将颜色的alpha乘以覆盖率
+pub(super) fn with_coverage(color: Rgba<u8>, coverage: f32) -> Rgba<u8> {
+    let mut ret = color;
+    ret.0[3] = (color.0[3] as f32 * coverage).round() as u8;
+    ret
+}
+
+/// 光栅化后的彩色字形, 颜色为非预乘alpha, (`left`, `top`)为图片左上角的像素坐标
+pub(super) struct ColorBitmap {
+    pub left: i32,
+    pub top: i32,
+    pub image: RgbaImage,
+}
+
+/// 像素范围, 为(左, 上, 宽, 高)
+type PixelRect = (i32, i32, u32, u32);
+
+/// 彩色字形画布的像素数上限, 超出时视为没有彩色数据
+const MAX_CANVAS_PIXELS: u64 = 1 << 24;
+
+/// 彩色字形的数据
+enum ColorGlyph<'a> {
+    /// COLR字形, 变换为字体单位到像素
+    Colr(Transform),
+    /// CBDT/sbix中的PNG位图
+    Bitmap(RasterGlyphImage<'a>),
+}
+
+/// 查找彩色字形的数据并计算墨迹的像素范围, 不是彩色字形时返回None
+fn color_glyph<'f>(
+    font: &'f Font,
+    glyph: &ScaledGlyph,
+    position: Point<f32>,
+) -> Option<(ColorGlyph<'f>, PixelRect)> {
+    let face = font.face();
+    let id = ttf_parser::GlyphId(glyph.id().0);
+    let vm = font.v_metrics_unscaled();
+    let scale = glyph.scale();
+    // 字体单位到像素的比例, 与rusttype一致
+    let factor = scale.y / (vm.ascent - vm.descent);
+    let (glyph, rect) = if face.is_color_glyph(id) {
+        let sx = factor * scale.x / scale.y;
+        let transform = Transform::new(sx, 0., 0., -factor, position.x, position.y);
+        let mut bounds = Bounds::new(face, transform);
+        face.paint_color_glyph(id, 0, RgbaColor::new(0, 0, 0, 255), &mut bounds)?;
+        let empty = (position.x.round() as i32, position.y.round() as i32, 0, 0);
+        (
+            ColorGlyph::Colr(transform),
+            bounds.ink.map_or(empty, pixel_rect),
+        )
+    } else {
+        // 位图字形, 按字体单位换算目标的每em像素数后选择最接近的尺寸并缩放
+        let ppem = factor * font.units_per_em() as f32;
+        let raster = face.glyph_raster_image(id, ppem.round() as u16)?;
+        if raster.format != RasterImageFormat::PNG {
+            return None;
+        }
+        let factor = ppem / raster.pixels_per_em as f32;
+        let rect = (
+            (position.x + raster.x as f32 * factor).round() as i32,
+            (position.y - (raster.y as f32 + raster.height as f32) * factor).round() as i32,
+            ((raster.width as f32 * factor).round() as u32).max(1),
+            ((raster.height as f32 * factor).round() as u32).max(1),
+        );
+        (ColorGlyph::Bitmap(raster), rect)
+    };
+    if rect.2 as u64 * rect.3 as u64 > MAX_CANVAS_PIXELS {
+        return None;
+    }
+    Some((glyph, rect))
+}
+
+/// 彩色字形墨迹的像素范围(左, 上, 宽, 高), 与`rasterize_color_glyph`的结果一致
+/// 不是彩色字形时返回None
+pub(super) fn color_glyph_bounds(
+    font: &Font,
+    glyph: &ScaledGlyph,
+    position: Point<f32>,
+) -> Option<PixelRect> {
+    color_glyph(font, glyph, position).map(|(_, rect)| rect)
+}
+
+/// 光栅化彩色字形, `position`为基线上的起笔点
+/// 支持COLR(包括COLRv1的变换、裁剪、渐变和混合模式)和CBDT/sbix中的PNG位图;
+/// 字体中没有该字形的彩色数据时返回None
+pub(super) fn rasterize_color_glyph(
+    font: &Font,
+    glyph: &ScaledGlyph,
+    position: Point<f32>,
+    foreground: Rgba<u8>,
+) -> Option<ColorBitmap> {
+    let (data, (left, top, width, height)) = color_glyph(font, glyph, position)?;
+    let image = match data {
+        _ if width == 0 || height == 0 => RgbaImage::new(width, height),
+        ColorGlyph::Colr(transform) => {
+            let offset = Transform::new_translate(-left as f32, -top as f32);
+            let transform = Transform::combine(offset, transform);
+            let mut canvas = Canvas::new(font.face(), width, height, transform);
+            let [red, green, blue, alpha] = foreground.0;
+            let foreground = RgbaColor::new(red, green, blue, alpha);
+            let id = ttf_parser::GlyphId(glyph.id().0);
+            font.face()
+                .paint_color_glyph(id, 0, foreground, &mut canvas)?;
+            canvas.finish()
+        }
+        ColorGlyph::Bitmap(raster) => {
+            let img = image::load_from_memory_with_format(raster.data, ImageFormat::Png).ok()?;
+            image::imageops::resize(&img.to_rgba8(), width, height, FilterType::Triangle)
+        }
+    };
+    Some(ColorBitmap { left, top, image })
+}
+
+/// 绘制彩色字形, 按(列, 行, 颜色)回调每个像素, 坐标可能为负
+/// 字体中没有该字形的彩色数据时返回false
+pub(super) fn draw_color_glyph(
+    font: &Font,
+    glyph: &ScaledGlyph,
+    position: Point<f32>,
+    foreground: Rgba<u8>,
+    drawable: &mut impl FnMut(i32, i32, Rgba<u8>),
+) -> bool {
+    let Some(bitmap) = rasterize_color_glyph(font, glyph, position, foreground) else {
+        return false;
+    };
+    for (x, y, pixel) in bitmap.image.enumerate_pixels() {
+        drawable(bitmap.left + x as i32, bitmap.top + y as i32, *pixel);
+    }
+    true
+}
+
+/// COLR字形的图层, 为(字形, 颜色), 按绘制顺序排列
+/// 不是COLR字形, 或用到了纯色图层无法表示的渐变、变换、嵌套裁剪和混合模式时返回None
+pub(super) fn color_layers(
+    font: &Font,
+    glyph: &ScaledGlyph,
+    foreground: Rgba<u8>,
+) -> Option<Vec<(GlyphId, Rgba<u8>)>> {
+    let id = ttf_parser::GlyphId(glyph.id().0);
+    if !font.face().is_color_glyph(id) {
+        return None;
+    }
+    let mut layers = Layers {
+        face: font.face(),
+        clips: vec![],
+        clip_box: None,
+        current: None,
+        layers: vec![],
+        supported: true,
+    };
+    let [red, green, blue, alpha] = foreground.0;
+    let foreground = RgbaColor::new(red, green, blue, alpha);
+    font.face()
+        .paint_color_glyph(id, 0, foreground, &mut layers)?;
+    if !layers.supported {
+        return None;
+    }
+    let layers = layers.layers.into_iter();
+    Some(layers.map(|(id, color)| (GlyphId(id), color)).collect())
+}
+
+/// 收集COLR字形的纯色图层, 为(字形, 颜色)
+/// 裁剪框只在不裁切任何图层时接受; 其余COLRv1特性无法表示为纯色图层, 记为不支持
+struct Layers<'f, 'a> {
+    face: &'f ttf_parser::Face<'a>,
+    /// 裁剪栈, true为字形轮廓, false为裁剪框
+    clips: Vec<bool>,
+    clip_box: Option<ClipBox>,
+    current: Option<ttf_parser::GlyphId>,
+    layers: Vec<(u16, Rgba<u8>)>,
+    supported: bool,
+}
+
+impl<'a> Painter<'a> for Layers<'_, 'a> {
+    fn outline_glyph(&mut self, glyph_id: ttf_parser::GlyphId) {
+        // 字形轮廓内再绘制其他字形, 需要两层裁剪
+        if self.clips.contains(&true) {
+            self.supported = false;
+        }
+        self.current = Some(glyph_id);
+    }
+
+    fn paint(&mut self, paint: Paint<'a>) {
+        let (Paint::Solid(color), Some(glyph)) = (paint, self.current) else {
+            self.supported = false;
+            return;
+        };
+        if let Some(clip) = self.clip_box {
+            let inside = self.face.glyph_bounding_box(glyph).is_none_or(|r| {
+                r.x_min as f32 >= clip.x_min
+                    && r.y_min as f32 >= clip.y_min
+                    && r.x_max as f32 <= clip.x_max
+                    && r.y_max as f32 <= clip.y_max
+            });
+            self.supported &= inside;
+        }
+        let color = Rgba([color.red, color.green, color.blue, color.alpha]);
+        self.layers.push((glyph.0, color));
+    }
+
+    fn push_clip(&mut self) {
+        if self.clips.contains(&true) {
+            self.supported = false;
+        }
+        self.clips.push(true);
+    }
+
+    fn push_clip_box(&mut self, clipbox: ClipBox) {
+        if self.clip_box.is_some() {
+            self.supported = false;
+        }
+        self.clip_box = Some(clipbox);
+        self.clips.push(false);
+    }
+
+    fn pop_clip(&mut self) {
+        match self.clips.pop() {
+            Some(true) => self.current = None,
+            Some(false) => self.clip_box = None,
+            None => {}
+        }
+    }
+
+    fn push_layer(&mut self, mode: CompositeMode) {
+        if mode != CompositeMode::SourceOver {
+            self.supported = false;
+        }
+    }
+
+    fn pop_layer(&mut self) {}
+
+    fn push_transform(&mut self, transform: Transform) {
+        if !transform.is_default() {
+            self.supported = false;
+        }
+    }
+
+    fn pop_transform(&mut self) {}
+}
+
+/// 空矩形, 与任何矩形的交集仍为空
+const EMPTY: RectF = RectF {
+    x_min: 0.,
+    y_min: 0.,
+    x_max: 0.,
+    y_max: 0.,
+};
+
+fn is_empty(rect: &RectF) -> bool {
+    !(rect.x_min < rect.x_max && rect.y_min < rect.y_max)
+}
+
+fn intersect(a: RectF, b: RectF) -> RectF {
+    RectF {
+        x_min: a.x_min.max(b.x_min),
+        y_min: a.y_min.max(b.y_min),
+        x_max: a.x_max.min(b.x_max),
+        y_max: a.y_max.min(b.y_max),
+    }
+}
+
+fn union(a: RectF, b: RectF) -> RectF {
+    RectF {
+        x_min: a.x_min.min(b.x_min),
+        y_min: a.y_min.min(b.y_min),
+        x_max: a.x_max.max(b.x_max),
+        y_max: a.y_max.max(b.y_max),
+    }
+}
+
+fn apply(transform: &Transform, x: f32, y: f32) -> (f32, f32) {
+    let t = transform;
+    (t.a * x + t.c * y + t.e, t.b * x + t.d * y + t.f)
+}
+
+/// 逆变换, 不可逆时返回None
+fn invert(transform: &Transform) -> Option<Transform> {
+    let t = transform;
+    let det = t.a * t.d - t.b * t.c;
+    if det == 0. || !det.is_finite() {
+        return None;
+    }
+    let (a, b, c, d) = (t.d / det, -t.b / det, -t.c / det, t.a / det);
+    let (e, f) = (-(a * t.e + c * t.f), -(b * t.e + d * t.f));
+    Some(Transform::new(a, b, c, d, e, f))
+}
+
+/// 变换后矩形的外接矩形
+fn transform_rect(transform: &Transform, rect: RectF) -> RectF {
+    let corners = [
+        (rect.x_min, rect.y_min),
+        (rect.x_max, rect.y_min),
+        (rect.x_min, rect.y_max),
+        (rect.x_max, rect.y_max),
+    ]
+    .map(|(x, y)| apply(transform, x, y));
+    let (xs, ys) = (corners.map(|p| p.0), corners.map(|p| p.1));
+    RectF {
+        x_min: xs.into_iter().fold(f32::INFINITY, f32::min),
+        y_min: ys.into_iter().fold(f32::INFINITY, f32::min),
+        x_max: xs.into_iter().fold(f32::NEG_INFINITY, f32::max),
+        y_max: ys.into_iter().fold(f32::NEG_INFINITY, f32::max),
+    }
+}
+
+/// 包含矩形的像素范围
+fn pixel_rect(rect: RectF) -> PixelRect {
+    let (left, top) = (rect.x_min.floor(), rect.y_min.floor());
+    let width = rect.x_max.ceil() - left;
+    let height = rect.y_max.ceil() - top;
+    (left as i32, top as i32, width as u32, height as u32)
+}
+
+/// 计算COLR字形墨迹的外接矩形, 坐标为像素
+/// 填充的范围为当前裁剪与轮廓的交集, 均以外接矩形近似
+struct Bounds<'f, 'a> {
+    face: &'f ttf_parser::Face<'a>,
+    transforms: Vec<Transform>,
+    /// 各层裁剪的范围, 已与外层取交集
+    clips: Vec<RectF>,
+    /// 尚未用于裁剪的轮廓, COLRv0的图层直接填充轮廓
+    outline: Option<RectF>,
+    ink: Option<RectF>,
+}
+
+impl<'f, 'a> Bounds<'f, 'a> {
+    fn new(face: &'f ttf_parser::Face<'a>, transform: Transform) -> Self {
+        Self {
+            face,
+            transforms: vec![transform],
+            clips: vec![],
+            outline: None,
+            ink: None,
+        }
+    }
+
+    fn transform(&self) -> &Transform {
+        self.transforms.last().unwrap()
+    }
+
+    fn push_region(&mut self, region: RectF) {
+        let region = match self.clips.last() {
+            Some(&clip) => intersect(clip, region),
+            None => region,
+        };
+        self.clips.push(region);
+    }
+}
+
+impl<'a> Painter<'a> for Bounds<'_, 'a> {
+    fn outline_glyph(&mut self, glyph_id: ttf_parser::GlyphId) {
+        let rect = self.face.glyph_bounding_box(glyph_id).map(|r| RectF {
+            x_min: r.x_min as f32,
+            y_min: r.y_min as f32,
+            x_max: r.x_max as f32,
+            y_max: r.y_max as f32,
+        });
+        self.outline = Some(rect.map_or(EMPTY, |r| transform_rect(self.transform(), r)));
+    }
+
+    fn paint(&mut self, _paint: Paint<'a>) {
+        // 既没有裁剪也没有轮廓时填充整个平面, 无法确定范围, 不计入墨迹
+        let region = match (self.clips.last(), self.outline) {
+            (Some(&clip), Some(outline)) => intersect(clip, outline),
+            (Some(&clip), None) => clip,
+            (None, Some(outline)) => outline,
+            (None, None) => return,
+        };
+        if !is_empty(&region) {
+            self.ink = Some(self.ink.map_or(region, |ink| union(ink, region)));
+        }
+    }
+
+    fn push_clip(&mut self) {
+        let outline = self.outline.take().unwrap_or(EMPTY);
+        self.push_region(outline);
+    }
+
+    fn push_clip_box(&mut self, clipbox: ClipBox) {
+        let region = transform_rect(self.transform(), clipbox);
+        self.push_region(region);
+    }
+
+    fn pop_clip(&mut self) {
+        self.clips.pop();
+    }
+
+    fn push_layer(&mut self, _mode: CompositeMode) {}
+
+    fn pop_layer(&mut self) {}
+
+    fn push_transform(&mut self, transform: Transform) {
+        let transform = Transform::combine(*self.transform(), transform);
+        self.transforms.push(transform);
+    }
+
+    fn pop_transform(&mut self) {
+        if self.transforms.len() > 1 {
+            self.transforms.pop();
+        }
+    }
+}
+
+/// 预乘alpha的颜色, 各分量取值0到1
+type Premultiplied = [f32; 4];
+
+fn premultiply(color: RgbaColor) -> Premultiplied {
+    let alpha = color.alpha as f32 / 255.;
+    let channel = |c: u8| c as f32 / 255. * alpha;
+    [
+        channel(color.red),
+        channel(color.green),
+        channel(color.blue),
+        alpha,
+    ]
+}
+
+/// 将COLR字形绘制到画布上
+/// 裁剪和轮廓以每个像素的覆盖率表示, 图层按混合模式合成到下一层
+struct Canvas<'f, 'a> {
+    face: &'f ttf_parser::Face<'a>,
+    width: usize,
+    height: usize,
+    transforms: Vec<Transform>,
+    /// 各层裁剪的覆盖率, 已与外层取交集
+    clips: Vec<Vec<f32>>,
+    /// 尚未用于裁剪的轮廓, COLRv0的图层直接填充轮廓
+    outline: Option<Vec<f32>>,
+    /// 图层及其合成到下一层的混合模式, 最底层为结果
+    layers: Vec<(Vec<Premultiplied>, CompositeMode)>,
+}
+
+impl<'f, 'a> Canvas<'f, 'a> {
+    fn new(face: &'f ttf_parser::Face<'a>, width: u32, height: u32, transform: Transform) -> Self {
+        let (width, height) = (width as usize, height as usize);
+        Self {
+            face,
+            width,
+            height,
+            transforms: vec![transform],
+            clips: vec![],
+            outline: None,
+            layers: vec![(vec![[0.; 4]; width * height], CompositeMode::SourceOver)],
+        }
+    }
+
+    fn transform(&self) -> &Transform {
+        self.transforms.last().unwrap()
+    }
+
+    /// 按当前变换光栅化字体单位的路径, 返回每个像素的覆盖率
+    fn fill(&self, build: impl FnOnce(&mut PathRaster)) -> Vec<f32> {
+        let mut path = PathRaster::new(self.width, self.height, *self.transform());
+        build(&mut path);
+        // 光栅化器右侧多出一列, 存放画布右侧之外的路径
+        let stride = self.width + 1;
+        let mut ret = vec![0.; self.width * self.height];
+        path.rasterizer.for_each_pixel(|i, v| {
+            if i % stride < self.width {
+                ret[i / stride * self.width + i % stride] = v.min(1.);
+            }
+        });
+        ret
+    }
+
+    fn push_region(&mut self, region: Vec<f32>) {
+        let region = match self.clips.last() {
+            Some(clip) => clip.iter().zip(region).map(|(a, b)| a * b).collect(),
+            None => region,
+        };
+        self.clips.push(region);
+    }
+
+    /// 转换为非预乘alpha的图片
+    fn finish(self) -> RgbaImage {
+        let mut ret = RgbaImage::new(self.width as u32, self.height as u32);
+        for (pixel, &[r, g, b, a]) in ret.pixels_mut().zip(&self.layers[0].0) {
+            if a > 0. {
+                let channel = |c: f32| (c * 255.).round().clamp(0., 255.) as u8;
+                *pixel = Rgba([channel(r / a), channel(g / a), channel(b / a), channel(a)]);
+            }
+        }
+        ret
+    }
+}
+
+impl<'a> Painter<'a> for Canvas<'_, 'a> {
+    fn outline_glyph(&mut self, glyph_id: ttf_parser::GlyphId) {
+        let face = self.face;
+        self.outline = Some(self.fill(|path| {
+            face.outline_glyph(glyph_id, path);
+        }));
+    }
+
+    fn paint(&mut self, paint: Paint<'a>) {
+        // 像素中心反变换到绘制坐标系后计算颜色
+        let Some(inverse) = invert(self.transform()) else {
+            return;
+        };
+        let shader = Shader::new(&paint);
+        let width = self.width;
+        let (clip, outline) = (self.clips.last(), self.outline.as_ref());
+        let (layer, _) = self.layers.last_mut().unwrap();
+        for (i, dst) in layer.iter_mut().enumerate() {
+            let coverage = clip.map_or(1., |x| x[i]) * outline.map_or(1., |x| x[i]);
+            if coverage <= 0. {
+                continue;
+            }
+            let (x, y) = ((i % width) as f32 + 0.5, (i / width) as f32 + 0.5);
+            let (x, y) = apply(&inverse, x, y);
+            let src = shader.color(x, y).map(|c| c * coverage);
+            *dst = composite(src, *dst, CompositeMode::SourceOver);
+        }
+    }
+
+    fn push_clip(&mut self) {
+        let outline = self.outline.take();
+        let outline = outline.unwrap_or_else(|| vec![0.; self.width * self.height]);
+        self.push_region(outline);
+    }
+
+    fn push_clip_box(&mut self, clipbox: ClipBox) {
+        let region = self.fill(|path| {
+            path.move_to(clipbox.x_min, clipbox.y_min);
+            path.line_to(clipbox.x_max, clipbox.y_min);
+            path.line_to(clipbox.x_max, clipbox.y_max);
+            path.line_to(clipbox.x_min, clipbox.y_max);
+            path.close();
+        });
+        self.push_region(region);
+    }
+
+    fn pop_clip(&mut self) {
+        self.clips.pop();
+    }
+
+    fn push_layer(&mut self, mode: CompositeMode) {
+        let layer = vec![[0.; 4]; self.width * self.height];
+        self.layers.push((layer, mode));
+    }
+
+    fn pop_layer(&mut self) {
+        if self.layers.len() < 2 {
+            return;
+        }
+        let (src, mode) = self.layers.pop().unwrap();
+        let (dst, _) = self.layers.last_mut().unwrap();
+        for (d, s) in dst.iter_mut().zip(src) {
+            *d = composite(s, *d, mode);
+        }
+    }
+
+    fn push_transform(&mut self, transform: Transform) {
+        let transform = Transform::combine(*self.transform(), transform);
+        self.transforms.push(transform);
+    }
+
+    fn pop_transform(&mut self) {
+        if self.transforms.len() > 1 {
+            self.transforms.pop();
+        }
+    }
+}
+
+/// 将字体单位的路径按变换光栅化
+/// 曲线展开为线段, 线段在画布左右边界处截断, 边界之外的部分投影到边界上
+struct PathRaster {
+    rasterizer: Rasterizer,
+    transform: Transform,
+    width: f32,
+    start: (f32, f32),
+    last: (f32, f32),
+}
+
+impl PathRaster {
+    fn new(width: usize, height: usize, transform: Transform) -> Self {
+        Self {
+            rasterizer: Rasterizer::new(width + 1, height),
+            transform,
+            width: width as f32,
+            start: (0., 0.),
+            last: (0., 0.),
+        }
+    }
+
+    fn line(&mut self, to: (f32, f32)) {
+        let from = std::mem::replace(&mut self.last, to);
+        let mut ts = [0., 1., 1., 1.];
+        for (i, bound) in [0., self.width].into_iter().enumerate() {
+            if (from.0 - bound) * (to.0 - bound) < 0. {
+                ts[i + 1] = (bound - from.0) / (to.0 - from.0);
+            }
+        }
+        ts.sort_by(f32::total_cmp);
+        let at = |t: f32| {
+            let x = from.0 + (to.0 - from.0) * t;
+            let y = from.1 + (to.1 - from.1) * t;
+            raster_point(x.clamp(0., self.width), y)
+        };
+        for pair in ts.windows(2) {
+            if pair[0] < pair[1] {
+                self.rasterizer.draw_line(at(pair[0]), at(pair[1]));
+            }
+        }
+    }
+
+    /// 按控制点连线的长度展开曲线, 约每2像素一段
+    fn curve(&mut self, points: &[(f32, f32)], eval: impl Fn(f32) -> (f32, f32)) {
+        let mut length = 0.;
+        let mut prev = self.last;
+        for &p in points {
+            length += (p.0 - prev.0).hypot(p.1 - prev.1);
+            prev = p;
+        }
+        let steps = (length / 2.).ceil().clamp(1., 64.) as usize;
+        for i in 1..=steps {
+            self.line(eval(i as f32 / steps as f32));
+        }
+    }
+}
+
+impl OutlineBuilder for PathRaster {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.start = apply(&self.transform, x, y);
+        self.last = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.line(apply(&self.transform, x, y));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let p0 = self.last;
+        let p1 = apply(&self.transform, x1, y1);
+        let p2 = apply(&self.transform, x, y);
+        self.curve(&[p1, p2], |t| {
+            let s = 1. - t;
+            let f = |a: f32, b: f32, c: f32| s * s * a + 2. * s * t * b + t * t * c;
+            (f(p0.0, p1.0, p2.0), f(p0.1, p1.1, p2.1))
+        });
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let p0 = self.last;
+        let p1 = apply(&self.transform, x1, y1);
+        let p2 = apply(&self.transform, x2, y2);
+        let p3 = apply(&self.transform, x, y);
+        self.curve(&[p1, p2, p3], |t| {
+            let s = 1. - t;
+            let f = |a: f32, b: f32, c: f32, d: f32| {
+                s * s * s * a + 3. * s * s * t * b + 3. * s * t * t * c + t * t * t * d
+            };
+            (f(p0.0, p1.0, p2.0, p3.0), f(p0.1, p1.1, p2.1, p3.1))
+        });
+    }
+
+    fn close(&mut self) {
+        self.line(self.start);
+    }
+}
+
+/// COLR的填充
+enum Shader {
+    Solid(Premultiplied),
+    Gradient {
+        geometry: Geometry,
+        /// 按位置排序的色标
+        stops: Vec<(f32, Premultiplied)>,
+        extend: GradientExtend,
+    },
+}
+
+/// 渐变的几何形状, 坐标为绘制坐标系中的字体单位
+enum Geometry {
+    /// 起点及色标方向, 方向向量已除以其长度的平方
+    Linear { x0: f32, y0: f32, dx: f32, dy: f32 },
+    /// 两点锥形渐变, 为起始圆及圆心、半径的变化量
+    Radial {
+        x0: f32,
+        y0: f32,
+        r0: f32,
+        dx: f32,
+        dy: f32,
+        dr: f32,
+    },
+    /// 圆心及起止角度, 角度为逆时针方向的度数
+    Sweep {
+        x: f32,
+        y: f32,
+        start: f32,
+        end: f32,
+    },
+}
+
+impl Shader {
+    fn new(paint: &Paint) -> Self {
+        let (geometry, stops, extend) = match paint {
+            Paint::Solid(color) => return Self::Solid(premultiply(*color)),
+            Paint::LinearGradient(g) => {
+                // 色标方向为p0p1在p0p2的法线上的投影
+                let (nx, ny) = (g.y0 - g.y2, g.x2 - g.x0);
+                let (mut dx, mut dy) = (g.x1 - g.x0, g.y1 - g.y0);
+                let n = nx * nx + ny * ny;
+                if n > 0. {
+                    let k = (dx * nx + dy * ny) / n;
+                    (dx, dy) = (nx * k, ny * k);
+                }
+                let length = dx * dx + dy * dy;
+                if length.is_nan() || length <= 0. {
+                    return Self::Solid([0.; 4]);
+                }
+                let (dx, dy) = (dx / length, dy / length);
+                let geometry = Geometry::Linear {
+                    x0: g.x0,
+                    y0: g.y0,
+                    dx,
+                    dy,
+                };
+                (geometry, g.stops(0, &[]).collect::<Vec<_>>(), g.extend)
+            }
+            Paint::RadialGradient(g) => {
+                let geometry = Geometry::Radial {
+                    x0: g.x0,
+                    y0: g.y0,
+                    r0: g.r0,
+                    dx: g.x1 - g.x0,
+                    dy: g.y1 - g.y0,
+                    dr: g.r1 - g.r0,
+                };
+                (geometry, g.stops(0, &[]).collect(), g.extend)
+            }
+            Paint::SweepGradient(g) => {
+                // 角度以180度为单位, 且带有1.0的偏移
+                let geometry = Geometry::Sweep {
+                    x: g.center_x,
+                    y: g.center_y,
+                    start: (g.start_angle + 1.) * 180.,
+                    end: (g.end_angle + 1.) * 180.,
+                };
+                (geometry, g.stops(0, &[]).collect(), g.extend)
+            }
+        };
+        let mut stops = stops
+            .into_iter()
+            .map(|s| (s.stop_offset, premultiply(s.color)))
+            .collect::<Vec<_>>();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self::Gradient {
+            geometry,
+            stops,
+            extend,
+        }
+    }
+
+    /// 绘制坐标系中一点的颜色
+    fn color(&self, x: f32, y: f32) -> Premultiplied {
+        match self {
+            Self::Solid(color) => *color,
+            Self::Gradient {
+                geometry,
+                stops,
+                extend,
+            } => match geometry.offset(x, y) {
+                Some(t) => color_at(stops, t, *extend),
+                None => [0.; 4],
+            },
+        }
+    }
+}
+
+impl Geometry {
+    /// 点在色标上的位置, 锥形渐变之外的点返回None
+    fn offset(&self, x: f32, y: f32) -> Option<f32> {
+        match *self {
+            Self::Linear { x0, y0, dx, dy } => Some((x - x0) * dx + (y - y0) * dy),
+            Self::Radial {
+                x0,
+                y0,
+                r0,
+                dx,
+                dy,
+                dr,
+            } => {
+                // 求最大的t, 使点位于圆心为(x0, y0) + t(dx, dy)、半径为r0 + t·dr的圆上, 且半径非负
+                let (px, py) = (x - x0, y - y0);
+                let a = dx * dx + dy * dy - dr * dr;
+                let b = px * dx + py * dy + r0 * dr;
+                let c = px * px + py * py - r0 * r0;
+                let valid = |t: f32| r0 + t * dr >= 0.;
+                if a == 0. {
+                    let t = c / (2. * b);
+                    return (t.is_finite() && valid(t)).then_some(t);
+                }
+                let discriminant = b * b - a * c;
+                if discriminant < 0. {
+                    return None;
+                }
+                let root = discriminant.sqrt();
+                let (t1, t2) = ((b + root) / a, (b - root) / a);
+                [t1.max(t2), t1.min(t2)].into_iter().find(|&t| valid(t))
+            }
+            Self::Sweep {
+                x: cx,
+                y: cy,
+                start,
+                end,
+            } => {
+                let angle = (y - cy).atan2(x - cx).to_degrees().rem_euclid(360.);
+                Some((angle - start) / (end - start))
+            }
+        }
+    }
+}
+
+/// 色标上`t`处的颜色, 首尾色标之外的部分按`extend`延伸
+fn color_at(stops: &[(f32, Premultiplied)], t: f32, extend: GradientExtend) -> Premultiplied {
+    let (Some(first), Some(last)) = (stops.first(), stops.last()) else {
+        return [0.; 4];
+    };
+    let span = last.0 - first.0;
+    let t = if span > 0. && t.is_finite() {
+        let u = (t - first.0) / span;
+        let u = match extend {
+            GradientExtend::Pad => u.clamp(0., 1.),
+            GradientExtend::Repeat => u - u.floor(),
+            GradientExtend::Reflect => 1. - (u.rem_euclid(2.) - 1.).abs(),
+        };
+        first.0 + u * span
+    } else {
+        t
+    };
+    let i = stops.partition_point(|s| s.0 <= t);
+    if i == 0 {
+        return first.1;
+    }
+    if i == stops.len() {
+        return last.1;
+    }
+    let ((o0, c0), (o1, c1)) = (stops[i - 1], stops[i]);
+    let k = (t - o0) / (o1 - o0);
+    std::array::from_fn(|j| c0[j] + (c1[j] - c0[j]) * k)
+}
+
+/// 按混合模式将`src`合成到`dst`上
+fn composite(src: Premultiplied, dst: Premultiplied, mode: CompositeMode) -> Premultiplied {
+    use CompositeMode::*;
+    let (sa, da) = (src[3], dst[3]);
+    // Porter-Duff模式, 为(源的系数, 目标的系数)
+    let factors = match mode {
+        Clear => Some((0., 0.)),
+        Source => Some((1., 0.)),
+        Destination => Some((0., 1.)),
+        SourceOver => Some((1., 1. - sa)),
+        DestinationOver => Some((1. - da, 1.)),
+        SourceIn => Some((da, 0.)),
+        DestinationIn => Some((0., sa)),
+        SourceOut => Some((1. - da, 0.)),
+        DestinationOut => Some((0., 1. - sa)),
+        SourceAtop => Some((da, 1. - sa)),
+        DestinationAtop => Some((1. - da, sa)),
+        Xor => Some((1. - da, 1. - sa)),
+        Plus => Some((1., 1.)),
+        _ => None,
+    };
+    if let Some((fs, fd)) = factors {
+        return std::array::from_fn(|i| (src[i] * fs + dst[i] * fd).min(1.));
+    }
+    // 其余为W3C Compositing and Blending定义的混合模式, 在非预乘的颜色上计算
+    let unpremultiply = |c: Premultiplied| match c[3] > 0. {
+        true => [c[0] / c[3], c[1] / c[3], c[2] / c[3]],
+        false => [0.; 3],
+    };
+    let mixed = blend_mode(unpremultiply(src), unpremultiply(dst), mode);
+    let mut ret = [0.; 4];
+    for i in 0..3 {
+        ret[i] = src[i] * (1. - da) + dst[i] * (1. - sa) + sa * da * mixed[i];
+    }
+    ret[3] = sa + da - sa * da;
+    ret
+}
+
+/// 源颜色`cs`与背景色`cd`按混合模式混合后的颜色
+fn blend_mode(cs: [f32; 3], cd: [f32; 3], mode: CompositeMode) -> [f32; 3] {
+    use CompositeMode::*;
+    let separable = |f: fn(f32, f32) -> f32| std::array::from_fn(|i| f(cs[i], cd[i]));
+    match mode {
+        Multiply => separable(|s, d| s * d),
+        Screen => separable(screen),
+        Overlay => separable(|s, d| hard_light(d, s)),
+        Darken => separable(f32::min),
+        Lighten => separable(f32::max),
+        ColorDodge => separable(|s, d| match (d, s) {
+            (d, _) if d <= 0. => 0.,
+            (_, s) if s >= 1. => 1.,
+            (d, s) => (d / (1. - s)).min(1.),
+        }),
+        ColorBurn => separable(|s, d| match (d, s) {
+            (d, _) if d >= 1. => 1.,
+            (_, s) if s <= 0. => 0.,
+            (d, s) => 1. - ((1. - d) / s).min(1.),
+        }),
+        HardLight => separable(hard_light),
+        SoftLight => separable(soft_light),
+        Difference => separable(|s, d| (s - d).abs()),
+        Exclusion => separable(|s, d| s + d - 2. * s * d),
+        Hue => set_lum(set_sat(cs, sat(cd)), lum(cd)),
+        Saturation => set_lum(set_sat(cd, sat(cs)), lum(cd)),
+        Color => set_lum(cs, lum(cd)),
+        Luminosity => set_lum(cd, lum(cs)),
+        // Porter-Duff模式不经过此处
+        _ => cs,
+    }
+}
+
+fn screen(s: f32, d: f32) -> f32 {
+    s + d - s * d
+}
+
+fn hard_light(s: f32, d: f32) -> f32 {
+    match s <= 0.5 {
+        true => d * 2. * s,
+        false => screen(2. * s - 1., d),
+    }
+}
+
+fn soft_light(s: f32, d: f32) -> f32 {
+    if s <= 0.5 {
+        return d - (1. - 2. * s) * d * (1. - d);
+    }
+    let k = match d <= 0.25 {
+        true => ((16. * d - 12.) * d + 4.) * d,
+        false => d.sqrt(),
+    };
+    d + (2. * s - 1.) * (k - d)
+}
+
+fn lum(c: [f32; 3]) -> f32 {
+    0.3 * c[0] + 0.59 * c[1] + 0.11 * c[2]
+}
+
+fn sat(c: [f32; 3]) -> f32 {
+    c.into_iter().fold(f32::MIN, f32::max) - c.into_iter().fold(f32::MAX, f32::min)
+}
+
+fn set_lum(c: [f32; 3], l: f32) -> [f32; 3] {
+    let c = c.map(|x| x + l - lum(c));
+    let l = lum(c);
+    let min = c.into_iter().fold(f32::MAX, f32::min);
+    let max = c.into_iter().fold(f32::MIN, f32::max);
+    c.map(|mut x| {
+        if min < 0. {
+            x = l + (x - l) * l / (l - min);
+        }
+        if max > 1. {
+            x = l + (x - l) * (1. - l) / (max - l);
+        }
+        x
+    })
+}
+
+fn set_sat(c: [f32; 3], s: f32) -> [f32; 3] {
+    let min = c.into_iter().fold(f32::MAX, f32::min);
+    let max = c.into_iter().fold(f32::MIN, f32::max);
+    match max > min {
+        true => c.map(|x| (x - min) * s / (max - min)),
+        false => [0.; 3],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusttype::{Scale, point};
+
+    /// 读取`tests/fonts`下的测试字体
+    fn fixture(name: &str) -> Font<'static> {
+        let path = format!("{}/tests/fonts/{name}", env!("CARGO_MANIFEST_DIR"));
+        Font::try_from_bytes(std::fs::read(path).unwrap().leak()).unwrap()
+    }
+
+    fn assert_near(actual: Rgba<u8>, expected: [u8; 4], tolerance: u8) {
+        let near = actual
+            .0
+            .iter()
+            .zip(expected)
+            .all(|(a, e)| a.abs_diff(e) <= tolerance);
+        assert!(near, "{actual:?} != {expected:?}");
+    }
+
+    #[test]
+    fn test_color() {
+        assert_eq!(parse_color("#f00").unwrap(), Rgba([255, 0, 0, 255]));
+        assert_eq!(
+            parse_color("#11223380").unwrap(),
+            Rgba([0x11, 0x22, 0x33, 0x80])
+        );
+        assert_eq!(parse_color("transparent").unwrap().0[3], 0);
+        assert!(parse_color("#12345").is_err());
+
+        let mut dst = Rgba([0, 0, 0, 0]);
+        blend(&mut dst, Rgba([255, 0, 0, 128]));
+        assert_eq!(dst, Rgba([255, 0, 0, 128]));
+        blend(&mut dst, Rgba([0, 0, 255, 255]));
+        assert_eq!(dst, Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn test_colr() {
+        // 测试字体的em为1000、上行950、下行0, 按95像素绘制时1像素为10个字体单位
+        let font = fixture("test_glyphs-glyf_colr_1.ttf");
+        let glyph = |c: char| font.glyph(c).scaled(Scale::uniform(95.));
+        let render = |c: char, foreground: Rgba<u8>| {
+            rasterize_color_glyph(&font, &glyph(c), point(0., 100.), foreground).unwrap()
+        };
+        // 字体坐标处的像素
+        let at = |bitmap: &ColorBitmap, x: f32, y: f32| {
+            let col = (x / 10.).floor() as i32 - bitmap.left;
+            let row = (100. - y / 10.).floor() as i32 - bitmap.top;
+            let pixel = bitmap.image.get_pixel_checked(col as u32, row as u32);
+            pixel.copied().unwrap_or(Rgba([0; 4]))
+        };
+        let black = Rgba([0, 0, 0, 255]);
+
+        // COLRv0的同心圆由外向内依次为红、橙、黄、绿、蓝、靛、紫
+        let circles = render('\u{F0E00}', black);
+        assert_near(at(&circles, 500., 600.), [238, 130, 238, 255], 0);
+        assert_near(at(&circles, 500., 925.), [255, 0, 0, 255], 0);
+        assert_eq!(at(&circles, 500., 980.).0[3], 0);
+        let layers = color_layers(&font, &glyph('\u{F0E00}'), black).unwrap();
+        assert_eq!(layers.len(), 8);
+
+        // 线性渐变的绿、白、红色标位于x = 0、153.5、307, 之后延伸为红色
+        let linear = render('\u{F0500}', black);
+        assert_near(at(&linear, 5., 500.), [0, 128, 0, 255], 12);
+        assert_near(at(&linear, 155., 500.), [255, 255, 255, 255], 4);
+        assert_near(at(&linear, 800., 500.), [255, 0, 0, 255], 0);
+        assert!(color_layers(&font, &glyph('\u{F0500}'), black).is_none());
+
+        // 扫描渐变以(500, 600)为中心, 从0度开始逆时针方向
+        let sweep = render('\u{F0200}', black);
+        assert_near(at(&sweep, 800., 610.), [250, 240, 230, 255], 2);
+        assert_near(at(&sweep, 288., 812.), [62, 60, 249, 255], 6);
+        assert_near(at(&sweep, 200., 600.), [128, 0, 128, 255], 6);
+        assert_near(at(&sweep, 500., 300.), [47, 79, 79, 255], 2);
+
+        // 裁剪框只保留左上角的四分之一
+        let clipped = render('\u{F0C00}', black);
+        assert_eq!(
+            (clipped.left, clipped.top, clipped.image.dimensions()),
+            (0, 0, (50, 50))
+        );
+
+        // 橙色十字右移100单位, 绘制在半透明的蓝色十字之下
+        let translated = render('\u{F0903}', black);
+        assert_near(at(&translated, 300., 500.), [0, 0, 255, 127], 1);
+        assert_near(at(&translated, 800., 500.), [255, 165, 0, 178], 1);
+
+        // 黄色和蓝色方块缩小后互不相交, SourceIn的结果为空
+        let over = render('\u{F0A03}', black);
+        assert_near(at(&over, 300., 800.), [255, 220, 1, 255], 0);
+        assert_near(at(&over, 700., 200.), [104, 199, 232, 255], 0);
+        let source_in = render('\u{F0A05}', black);
+        assert_eq!(at(&source_in, 300., 800.).0[3], 0);
+        assert_eq!(at(&source_in, 700., 200.).0[3], 0);
+
+        // 调色板索引0xFFFF使用前景色
+        let foreground = render('\u{F0B06}', Rgba([0, 0, 255, 255]));
+        assert_near(at(&foreground, 500., 600.), [0, 0, 255, 255], 0);
+    }
+
+    #[test]
+    fn test_bitmap() {
+        // em为1000, 上行800、下行-200, 按128像素绘制时使用128ppem的位图, 不缩放
+        let font = fixture("cbdt.ttf");
+        let glyph = font.glyph('\u{1F600}').scaled(Scale::uniform(128.));
+        let position = point(0., 100.);
+        let bitmap = rasterize_color_glyph(&font, &glyph, position, Rgba([0, 0, 0, 255])).unwrap();
+        assert_eq!(
+            (bitmap.left, bitmap.top, bitmap.image.dimensions()),
+            (0, -3, (103, 103))
+        );
+        assert_eq!(
+            color_glyph_bounds(&font, &glyph, position),
+            Some((0, -3, 103, 103))
+        );
+        assert_near(*bitmap.image.get_pixel(51, 51), [255, 204, 0, 255], 2);
+        assert!(color_layers(&font, &glyph, Rgba([0, 0, 0, 255])).is_none());
+    }
+}
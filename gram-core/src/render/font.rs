@@ -1,7 +1,185 @@
-use rusttype::Font;
-use std::sync::LazyLock;
+use rusttype::{GlyphId, PositionedGlyph, Rect, Scale, point};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{Arc, RwLock};
 
-pub const FONTS: LazyLock<[Font; 59]> = LazyLock::new(|| {
+/// 每个字体缓存的字形数量上限, 超出后清空
+const CACHE_CAPACITY: usize = 4096;
+
+/// 水平方向的亚像素档位数, 字形按1/4像素定位
+pub const SUBPIXEL_STEPS: u8 = 4;
+
+/// 光栅化后的字形, 坐标相对于基线上的起笔点
+pub struct GlyphBitmap {
+    pub left: i32,
+    pub top: i32,
+    pub width: u32,
+    /// 按行排列的覆盖率, 0为透明
+    pub coverage: Vec<f32>,
+}
+
+impl GlyphBitmap {
+    pub fn height(&self) -> u32 {
+        match self.width {
+            0 => 0,
+            w => self.coverage.len() as u32 / w,
+        }
+    }
+}
+
+/// 字体样式, 用于描述字体本身的样式, 也用于描述排版时要求的样式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct FontStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub monospace: bool,
+}
+
+/// 字形缓存的键, 为(字形, 水平尺寸, 竖直尺寸, 亚像素档位)
+type CacheKey = (u16, u32, u32, u8);
+
+/// 字体, 同时持有用于光栅化的rusttype字体和用于整形的rustybuzz字体
+#[derive(Clone)]
+pub struct Font<'a> {
+    inner: rusttype::Font<'a>,
+    // Face体积较大, 共享以便廉价地克隆
+    face: Arc<rustybuzz::Face<'a>>,
+    family: String,
+    /// 小写的字体族名, 用于匹配注册表中的优先字体族
+    family_key: String,
+    emoji: bool,
+    style: FontStyle,
+    cache: Arc<RwLock<HashMap<CacheKey, Arc<GlyphBitmap>>>>,
+}
+
+impl<'a> Font<'a> {
+    pub fn try_from_bytes(data: &'a [u8]) -> Option<Font<'a>> {
+        Self::try_from_bytes_and_index(data, 0)
+    }
+
+    pub fn try_from_bytes_and_index(data: &'a [u8], index: u32) -> Option<Font<'a>> {
+        let face = rustybuzz::Face::from_slice(data, index)?;
+        let family = face
+            .names()
+            .into_iter()
+            .filter(|name| name.name_id == rustybuzz::ttf_parser::name_id::FAMILY)
+            .find_map(|name| name.to_string())
+            .unwrap_or_default();
+        let tables = face.tables();
+        let emoji = tables.cbdt.is_some()
+            || tables.colr.is_some()
+            || tables.sbix.is_some()
+            || family.contains("Emoji");
+        let style = FontStyle {
+            bold: face.is_bold() || face.weight().to_number() >= 600,
+            italic: face.is_italic() || face.is_oblique(),
+            // emoji字体通常也标记为等宽, 但不适合显示代码
+            monospace: face.is_monospaced() && !emoji,
+        };
+        Some(Self {
+            inner: rusttype::Font::try_from_bytes_and_index(data, index)?,
+            face: Arc::new(face),
+            family_key: family.to_lowercase(),
+            family,
+            emoji,
+            style,
+            cache: Default::default(),
+        })
+    }
+
+    /// 用于文本整形的字体
+    pub fn face(&self) -> &rustybuzz::Face<'a> {
+        &self.face
+    }
+
+    /// 字体族名, 如`Noto Sans JP`
+    pub fn family(&self) -> &str {
+        &self.family
+    }
+
+    pub(super) fn family_key(&self) -> &str {
+        &self.family_key
+    }
+
+    /// 字体是否包含该字符的字形
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.inner.glyph(c).id().0 != 0
+    }
+
+    /// 是否为emoji字体, 即包含彩色字形表或字体族名含有`Emoji`
+    pub fn is_emoji(&self) -> bool {
+        self.emoji
+    }
+
+    /// 字体自身的样式, 取自OS/2和post表
+    pub fn style(&self) -> FontStyle {
+        self.style
+    }
+
+    /// 光栅化字形, 起笔点向右偏移`subpixel / SUBPIXEL_STEPS`像素, 结果按(字形, 尺寸, 偏移)缓存
+    pub fn rasterize(&self, id: GlyphId, scale: Scale, subpixel: u8) -> Arc<GlyphBitmap> {
+        let key = (id.0, scale.x.to_bits(), scale.y.to_bits(), subpixel);
+        if let Some(bitmap) = self.cache.read().unwrap().get(&key) {
+            return bitmap.clone();
+        }
+
+        let pg = self.positioned(id, scale, subpixel);
+        let bitmap = match pg.pixel_bounding_box() {
+            Some(bb) => {
+                let width = bb.width() as u32;
+                let mut coverage = vec![0.; (width * bb.height() as u32) as usize];
+                pg.draw(|x, y, v| coverage[(y * width + x) as usize] = v);
+                GlyphBitmap {
+                    left: bb.min.x,
+                    top: bb.min.y,
+                    width,
+                    coverage,
+                }
+            }
+            None => GlyphBitmap {
+                left: 0,
+                top: 0,
+                width: 0,
+                coverage: vec![],
+            },
+        };
+        let bitmap = Arc::new(bitmap);
+        let mut cache = self.cache.write().unwrap();
+        if cache.len() >= CACHE_CAPACITY {
+            cache.clear();
+        }
+        cache.insert(key, bitmap.clone());
+        bitmap
+    }
+
+    /// `rasterize`得到的位图的范围, 由轮廓计算, 不光栅化也不写入缓存; 没有轮廓时返回None
+    pub fn pixel_bounds(&self, id: GlyphId, scale: Scale, subpixel: u8) -> Option<Rect<i32>> {
+        self.positioned(id, scale, subpixel).pixel_bounding_box()
+    }
+
+    fn positioned(&self, id: GlyphId, scale: Scale, subpixel: u8) -> PositionedGlyph<'a> {
+        let offset = subpixel as f32 / SUBPIXEL_STEPS as f32;
+        self.glyph(id).scaled(scale).positioned(point(offset, 0.))
+    }
+}
+
+impl<'a> Deref for Font<'a> {
+    type Target = rusttype::Font<'a>;
+
+    fn deref(&self) -> &rusttype::Font<'a> {
+        &self.inner
+    }
+}
+
+/// 编译进库中的字体, 首次使用时解析
+/// 其中的Noto Emoji为单色字体; 彩色emoji需另行加载CBDT、sbix或COLR格式的字体, 如Noto Color Emoji
+#[cfg(feature = "embedded-fonts")]
+pub fn embedded_fonts() -> &'static [Font<'static>] {
+    &*FONTS
+}
+
+#[cfg(feature = "embedded-fonts")]
+static FONTS: std::sync::LazyLock<[Font<'static>; 64]> = std::sync::LazyLock::new(|| {
     [
         // core noto fonts
         Font::try_from_bytes(include_bytes!("../../fonts/DejaVuSans.ttf")).unwrap(),
@@ -106,5 +284,85 @@ pub const FONTS: LazyLock<[Font; 59]> = LazyLock::new(|| {
         Font::try_from_bytes(include_bytes!("../../fonts/NotoSansThaiUI-Regular.ttf")).unwrap(),
         Font::try_from_bytes(include_bytes!("../../fonts/NotoSansUgaritic-Regular.ttf")).unwrap(),
         Font::try_from_bytes(include_bytes!("../../fonts/NotoSansVai-Regular.ttf")).unwrap(),
+        // 富文本使用的等宽、粗体和斜体字体, 样式不符时排在其他字体之后
+        Font::try_from_bytes(include_bytes!("../../fonts/DejaVuSansMono.ttf")).unwrap(),
+        Font::try_from_bytes(include_bytes!("../../fonts/DejaVuSansMono-Bold.ttf")).unwrap(),
+        Font::try_from_bytes(include_bytes!("../../fonts/DejaVuSans-Bold.ttf")).unwrap(),
+        Font::try_from_bytes(include_bytes!("../../fonts/DejaVuSans-Oblique.ttf")).unwrap(),
+        Font::try_from_bytes(include_bytes!("../../fonts/DejaVuSans-BoldOblique.ttf")).unwrap(),
     ]
 });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::glyph::VecGlyph;
+    use crate::render::registry::FontRegistry;
+
+    fn dejavu() -> Font<'static> {
+        Font::try_from_bytes(include_bytes!("../../fonts/DejaVuSans.ttf")).unwrap()
+    }
+
+    #[test]
+    fn test_rasterize_cache() {
+        let font = dejavu();
+        let id = font.glyph('g').id();
+        let scale = Scale::uniform(32.);
+
+        // 相同的键命中缓存, 克隆的字体共享缓存
+        let bitmap = font.rasterize(id, scale, 1);
+        assert!(Arc::ptr_eq(&bitmap, &font.rasterize(id, scale, 1)));
+        assert!(Arc::ptr_eq(&bitmap, &font.clone().rasterize(id, scale, 1)));
+        assert!(!Arc::ptr_eq(&bitmap, &font.rasterize(id, scale, 2)));
+        assert_eq!(font.cache.read().unwrap().len(), 2);
+
+        // 缓存的结果与未缓存的字体光栅化的结果一致
+        let uncached = dejavu().rasterize(id, scale, 1);
+        assert!(bitmap.width > 0 && bitmap.height() > 0);
+        assert_eq!(
+            (bitmap.left, bitmap.top, bitmap.width),
+            (uncached.left, uncached.top, uncached.width)
+        );
+        assert_eq!(bitmap.coverage, uncached.coverage);
+
+        // 达到上限后清空缓存
+        let mut size = 1;
+        while font.cache.read().unwrap().len() < CACHE_CAPACITY {
+            font.rasterize(id, Scale::uniform(1. + size as f32 / 8192.), 0);
+            size += 1;
+        }
+        assert!(Arc::ptr_eq(&bitmap, &font.rasterize(id, scale, 1)));
+        font.rasterize(id, Scale::uniform(1. + size as f32 / 8192.), 0);
+        assert_eq!(font.cache.read().unwrap().len(), 1);
+        let evicted = font.rasterize(id, scale, 1);
+        assert!(!Arc::ptr_eq(&bitmap, &evicted));
+        assert_eq!(bitmap.coverage, evicted.coverage);
+    }
+
+    #[test]
+    fn test_measure_uncached() {
+        // 测量只按轮廓计算范围, 不光栅化字形, 缓存保持为空
+        let font: &'static Font = Box::leak(Box::new(dejavu()));
+        let mut registry = FontRegistry::new();
+        registry.add(font);
+        let style = FontStyle {
+            bold: true,
+            italic: true,
+            monospace: false,
+        };
+        let vg = VecGlyph::with_style("Jiggly fox", Scale::uniform(32.), &registry, style);
+        assert!(font.cache.read().unwrap().is_empty());
+
+        // 绘制时才光栅化, 伪粗体、伪斜体的墨迹恰好落在测量的范围内
+        let (width, height) = (vg.width(), vg.height());
+        let (mut right, mut bottom) = (0, 0);
+        vg.draw(|row, col, v| {
+            assert!((col as f32) < width && (row as f32) < height);
+            if v < 1. {
+                (right, bottom) = (right.max(col + 1), bottom.max(row + 1));
+            }
+        });
+        assert!(!font.cache.read().unwrap().is_empty());
+        assert_eq!(right as f32, width);
+    }
+}
@@ -1,86 +1,626 @@
+use super::color::{color_glyph_bounds, color_layers, draw_color_glyph, with_coverage};
+use super::font::{Font, FontStyle, GlyphBitmap, SUBPIXEL_STEPS};
+use super::path::{GlyphPath, PathBuilder};
+use super::registry::FontRegistry;
+use crate::unicode::{is_invisible, is_variation_selector};
+use image::Rgba;
 pub use rusttype::Scale;
-use rusttype::{Font, ScaledGlyph, point};
+use rusttype::{GlyphId, ScaledGlyph, point};
+use rustybuzz::{Direction, UnicodeBuffer};
+use std::borrow::Cow;
+use std::ops::Range;
+use unicode_bidi::BidiInfo;
+use unicode_segmentation::UnicodeSegmentation;
 
-pub struct VecGlyph<'fonts>(Vec<ScaledGlyph<'fonts>>);
-impl<'fonts> VecGlyph<'fonts> {
-    pub fn new(
+/// 伪斜体的水平错切比例, 约11度
+const OBLIQUE_SHEAR: f32 = 0.2;
+
+/// 整形后的字形, 位置相对于行首的基线
+struct PlacedGlyph {
+    font: &'static Font<'static>,
+    glyph: ScaledGlyph<'static>,
+    x: f32,
+    y: f32,
+    synthetic: Synthetic,
+}
+
+/// 字体缺少要求的样式时模拟的效果
+#[derive(Debug, Clone, Copy, Default)]
+struct Synthetic {
+    /// 伪粗体加粗的像素数, 字形向右重复绘制
+    embolden: i32,
+    /// 伪斜体, 按到基线的距离水平错切
+    oblique: bool,
+}
+
+impl Synthetic {
+    fn new(font: &Font, style: FontStyle, scale: Scale) -> Self {
+        let bold = style.bold && !font.style().bold;
+        Self {
+            embolden: if bold {
+                (scale.y / 24.).round().max(1.) as i32
+            } else {
+                0
+            },
+            oblique: style.italic && !font.style().italic,
+        }
+    }
+
+    /// 位图中第`row`行(相对于基线)的水平偏移
+    fn shift(&self, row: i32) -> i32 {
+        match self.oblique {
+            true => (-row as f32 * OBLIQUE_SHEAR).round() as i32,
+            false => 0,
+        }
+    }
+}
+
+/// 一行整形后的文本
+/// 按字体切分为若干段后交给rustybuzz整形, 处理连字、组合符号和阿拉伯文连写;
+/// 段的排列顺序由Unicode双向算法决定
+pub struct VecGlyph {
+    glyphs: Vec<PlacedGlyph>,
+    /// 按逻辑顺序, 每个字符的步进宽度; 同一字形簇的宽度记在簇的首字符上
+    advances: Vec<f32>,
+    /// 步进宽度之和
+    advance: f32,
+    /// 墨迹超出行首或行顶的距离, 绘制时整体右移和下移
+    overhang: (f32, f32),
+    width: f32,
+    height: f32,
+    /// 没有任何字体支持、绘制为'?'的字形簇数量
+    missing: usize,
+    text: String,
+    /// 每个字素簇的字节区间及使用的字体
+    clusters: Vec<(Range<usize>, &'static Font<'static>)>,
+}
+
+/// 字素簇的字体覆盖情况
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphemeCoverage {
+    pub text: String,
+    /// 使用的字体族名; 没有字体支持时为回退使用的默认字体
+    pub font: String,
+    /// 字体不支持、绘制为'?'的字符, 不含零宽字符和变体选择符等不需要字形的字符
+    pub missing: Vec<char>,
+}
+
+/// 文本的字体覆盖情况
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Coverage {
+    /// 按逻辑顺序排列的字素簇
+    pub graphemes: Vec<GraphemeCoverage>,
+}
+
+impl Coverage {
+    /// 没有字体支持的字符, 去重后按首次出现的顺序排列
+    pub fn missing(&self) -> Vec<char> {
+        let mut ret = vec![];
+        for &c in self.graphemes.iter().flat_map(|g| &g.missing) {
+            if !ret.contains(&c) {
+                ret.push(c);
+            }
+        }
+        ret
+    }
+
+    /// 全部字符都有字形
+    pub fn is_complete(&self) -> bool {
+        self.graphemes.iter().all(|g| g.missing.is_empty())
+    }
+
+    /// 用到的字体族名, 去重后按首次出现的顺序排列
+    pub fn fonts(&self) -> Vec<&str> {
+        let mut ret = vec![];
+        for g in &self.graphemes {
+            if !ret.contains(&g.font.as_str()) {
+                ret.push(g.font.as_str());
+            }
+        }
+        ret
+    }
+}
+
+impl VecGlyph {
+    pub fn new(text: &str, scale: Scale, registry: &FontRegistry) -> VecGlyph {
+        Self::with_style(text, scale, registry, FontStyle::default())
+    }
+
+    /// 按样式选择字体整形, 字体本身没有粗体或斜体时以伪粗体、伪斜体绘制
+    pub fn with_style(
         text: &str,
         scale: Scale,
-        fonts: impl IntoIterator<Item = Font<'fonts>> + Clone,
-    ) -> VecGlyph<'fonts> {
-        let chars = text.chars().collect::<Vec<_>>();
-
-        let mut fonts = fonts.into_iter().peekable();
-        let default_f = fonts.peek().expect("未提供字体").clone();
-
-        let mut found_map = vec![false; chars.len()];
-        let mut ret = vec![default_f.glyph('?'); chars.len()];
-
-        fonts.into_iter().for_each(|f| {
-            f.glyphs_for(chars.iter().cloned())
-                .enumerate()
-                .for_each(|(i, g)| {
-                    if g.id().0 == 0 {
-                        return;
+        registry: &FontRegistry,
+        style: FontStyle,
+    ) -> VecGlyph {
+        let char_starts = text.char_indices().map(|(i, _)| i).collect::<Vec<_>>();
+        let char_idx = |byte: usize| char_starts.partition_point(|&x| x < byte);
+
+        let mut ret = Self {
+            glyphs: vec![],
+            advances: vec![0.; char_starts.len()],
+            advance: 0.,
+            overhang: (0., 0.),
+            width: 0.,
+            height: 0.,
+            missing: 0,
+            text: text.to_string(),
+            clusters: vec![],
+        };
+        // 没有可用字体时不绘制任何内容
+        let fonts = registry.fonts();
+        let Some(default_f) = fonts.first() else {
+            return ret;
+        };
+
+        // 按扩展字素簇选择字体, 使emoji序列、组合符号等落在同一字体中
+        let mut font_of = vec![0; char_starts.len()];
+        let mut prev = 0;
+        for (start, cluster) in text.grapheme_indices(true) {
+            let font = select_font(cluster, registry, style).unwrap_or(prev);
+            let first = char_idx(start);
+            font_of[first..first + cluster.chars().count()].fill(font);
+            prev = font;
+            ret.clusters
+                .push((start..start + cluster.len(), fonts[font]));
+        }
+
+        let bidi = BidiInfo::new(text, None);
+        for para in &bidi.paragraphs {
+            let (levels, runs) = bidi.visual_runs(para, para.range.clone());
+            for run in runs {
+                let rtl = levels[run.start].is_rtl();
+                // 同一方向的片段再按字体切分
+                let mut segments: Vec<(Range<usize>, usize)> = vec![];
+                for (i, c) in text[run.clone()].char_indices() {
+                    let (start, end) = (run.start + i, run.start + i + c.len_utf8());
+                    let font = font_of[char_idx(start)];
+                    match segments.last_mut() {
+                        Some((range, f)) if *f == font => range.end = end,
+                        _ => segments.push((start..end, font)),
                     }
-                    if !found_map[i] {
-                        ret[i] = g;
-                        found_map[i] = true;
-                    } else {
-                        // println!("未找到字符:{:?}", i);
+                }
+                if rtl {
+                    segments.reverse();
+                }
+                for (range, font) in segments {
+                    let shaped = shape(
+                        &text[range.clone()],
+                        rtl,
+                        fonts[font],
+                        default_f,
+                        scale,
+                        style,
+                        &mut ret.missing,
+                    );
+                    for (mut g, advance, cluster) in shaped {
+                        g.x += ret.advance;
+                        ret.glyphs.push(g);
+                        ret.advance += advance;
+                        ret.advances[char_idx(range.start + cluster)] += advance;
                     }
-                })
-        });
-
-        let ret = ret.into_iter().map(|g| g.scaled(scale)).collect();
+                }
+            }
+        }
+        ret.measure();
+        // 统计缺字的字符开销较大, 只在启用DEBUG日志时进行
+        if ret.missing > 0 && tracing::enabled!(tracing::Level::DEBUG) {
+            let coverage = ret.coverage();
+            tracing::debug!(
+                text,
+                missing = ?coverage.missing(),
+                "no font covers {} glyph cluster(s)",
+                ret.missing
+            );
+        }
+        ret
+    }
 
-        Self(ret)
+    /// 每个字素簇使用的字体及缺字的字符
+    pub fn coverage(&self) -> Coverage {
+        let graphemes = self
+            .clusters
+            .iter()
+            .map(|(range, font)| {
+                let text = &self.text[range.clone()];
+                GraphemeCoverage {
+                    text: text.to_string(),
+                    font: font.family().to_string(),
+                    missing: text
+                        .chars()
+                        .filter(|&c| !is_ignorable(c) && !font.has_glyph(c))
+                        .collect(),
+                }
+            })
+            .collect();
+        Coverage { graphemes }
     }
 
-    pub fn height(&self) -> f32 {
-        // self.0
-        //     .iter()
-        //     .map(|x| {
-        //         x.exact_bounding_box()
-        //             .map(|bb| bb.max.y - bb.min.y)
-        //             .unwrap_or(0.0)
-        //     })
-        //     .reduce(f32::max)
-        //     .unwrap_or(0.)
-        self.0
+    /// 计算包含墨迹的尺寸, 负的左侧支承、斜体的右侧溢出和超出上行高度的符号都不会被裁切
+    /// 按轮廓计算范围, 不光栅化字形;
+    /// 彩色字形按彩色数据的范围计算, 如位图字形没有轮廓, COLR字形可能超出基础字形的轮廓
+    fn measure(&mut self) {
+        let line_height = self
+            .glyphs
             .iter()
             .map(|g| {
-                let vm = g.font().v_metrics(g.scale());
+                let vm = g.glyph.font().v_metrics(g.glyph.scale());
                 vm.ascent - vm.descent + vm.line_gap
             })
             .reduce(f32::max)
+            .unwrap_or(0.);
+        let baseline = self.ascent().round();
+        let (mut left, mut right) = (0f32, self.advance);
+        let (mut top, mut bottom) = (0f32, line_height);
+        for g in &self.glyphs {
+            let y = baseline + g.y.round();
+            let position = point(g.x.round(), y);
+            if let Some((x, y, width, height)) = color_glyph_bounds(g.font, &g.glyph, position)
+                && width > 0
+            {
+                left = left.min(x as f32);
+                right = right.max((x + width as i32) as f32);
+                top = top.min(y as f32);
+                bottom = bottom.max((y + height as i32) as f32);
+            }
+            let (x, subpixel) = split_x(g.x);
+            let Some(bb) = g.font.pixel_bounds(g.glyph.id(), g.glyph.scale(), subpixel) else {
+                continue;
+            };
+            let shifts = [g.synthetic.shift(bb.min.y), g.synthetic.shift(bb.max.y - 1)];
+            let x = x + bb.min.x;
+            let y = y + bb.min.y as f32;
+            left = left.min((x + shifts[0].min(shifts[1])) as f32);
+            let x = x + bb.width() + shifts[0].max(shifts[1]) + g.synthetic.embolden;
+            right = right.max(x as f32);
+            top = top.min(y);
+            bottom = bottom.max(y + bb.height() as f32);
+        }
+        self.overhang = (-left, -top);
+        self.width = right - left;
+        self.height = bottom - top;
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    /// 各字体上行高度的最大值, 没有字形时为0
+    pub fn ascent(&self) -> f32 {
+        self.glyphs
+            .iter()
+            .map(|g| g.glyph.font().v_metrics(g.glyph.scale()).ascent)
+            .reduce(f32::max)
             .unwrap_or(0.)
     }
-    pub fn width(&self) -> f32 {
-        self.0
+
+    /// 各字体下行高度的最大值, 为基线以下的正距离, 没有字形时为0
+    pub fn descent(&self) -> f32 {
+        self.glyphs
             .iter()
-            .map(|x| x.h_metrics().advance_width)
-            .sum::<f32>()
+            .map(|g| -g.glyph.font().v_metrics(g.glyph.scale()).descent)
+            .reduce(f32::max)
+            .unwrap_or(0.)
     }
 
+    /// 没有任何字体支持、绘制为'?'的字形簇数量
+    pub fn missing_glyphs(&self) -> usize {
+        self.missing
+    }
+
+    /// 步进宽度之和, 即下一段文本的起笔点
+    pub fn advance(&self) -> f32 {
+        self.advance
+    }
+
+    /// 首个字形的起笔点在绘制区域中的(列, 行)坐标, 行坐标为基线位置
+    /// 拼接不同样式的文本时用于对齐基线
+    pub fn origin(&self) -> (f32, f32) {
+        (self.overhang.0, self.overhang.1 + self.ascent().round())
+    }
+
+    /// 每个字符的水平步进宽度
+    pub fn advances(&self) -> impl Iterator<Item = f32> + '_ {
+        self.advances.iter().copied()
+    }
+
+    /// 包含墨迹的宽度, 不小于步进宽度之和
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    /// 按(行, 列, 值)回调每个像素, 值为0时为全黑
+    /// 字形可能互相重叠(如组合符号), 调用方应取较深的值
     pub fn draw(self, mut drawable: impl FnMut(u32, u32, f32)) {
-        let mut h_offset = 0.;
-        let height = self.height();
-        for g in self.0 {
-            let offset_delta = g.h_metrics().advance_width;
-            let pg = g.positioned(point(0., 0.));
-            let g_height = pg
-                .pixel_bounding_box()
-                .map(|bb| bb.max.y - bb.min.y)
-                .unwrap_or(0) as u32;
-            let g_down = (height - g_height as f32) / 2.;
-
-            // height as u32 - g_height + ascent as u32;
-
-            pg.draw(|x, y, v| {
-                drawable(y + g_down as u32, x + h_offset as u32, 1.0 - v);
+        let (dx, dy) = self.overhang;
+        let baseline = dy + self.ascent().round();
+        for g in self.glyphs {
+            let (x, subpixel) = split_x(dx + g.x);
+            let bitmap = g.font.rasterize(g.glyph.id(), g.glyph.scale(), subpixel);
+            let y = (baseline + g.y).round() as i32;
+            draw_bitmap(&bitmap, x, y, g.synthetic, |col, row, v| {
+                if col >= 0 && row >= 0 {
+                    drawable(row as u32, col as u32, 1.0 - v);
+                }
+            });
+        }
+    }
+
+    /// 按(行, 列, 颜色)回调每个像素, 调用方应按source-over叠加
+    /// 彩色字形使用字体自带的颜色, 其余字形使用前景色
+    pub fn draw_rgba(self, foreground: Rgba<u8>, mut drawable: impl FnMut(u32, u32, Rgba<u8>)) {
+        let (dx, dy) = self.overhang;
+        let baseline = dy + self.ascent().round();
+        let mut put = |col: i32, row: i32, color: Rgba<u8>| {
+            if col >= 0 && row >= 0 && color.0[3] > 0 {
+                drawable(row as u32, col as u32, color);
+            }
+        };
+        for g in self.glyphs {
+            let position = point((dx + g.x).round(), (baseline + g.y).round());
+            if draw_color_glyph(g.font, &g.glyph, position, foreground, &mut put) {
+                continue;
+            }
+            let (x, subpixel) = split_x(dx + g.x);
+            let bitmap = g.font.rasterize(g.glyph.id(), g.glyph.scale(), subpixel);
+            let y = (baseline + g.y).round() as i32;
+            draw_bitmap(&bitmap, x, y, g.synthetic, |col, row, v| {
+                put(col, row, with_coverage(foreground, v))
             });
-            h_offset += offset_delta;
         }
     }
+
+    /// 字形轮廓, 坐标系及位置与`draw`一致
+    /// COLR彩色字形按图层拆分为多个路径; 位图字形没有轮廓, 不输出
+    /// 用到渐变、变换等无法表示为纯色图层的COLRv1字形退回为前景色的单色轮廓
+    pub fn paths(&self, foreground: Rgba<u8>) -> Vec<GlyphPath> {
+        let (dx, dy) = self.overhang;
+        let baseline = dy + self.ascent().round();
+        let mut ret = vec![];
+        for g in &self.glyphs {
+            let y = (baseline + g.y).round();
+            let (x, layers) = match color_layers(g.font, &g.glyph, foreground) {
+                Some(layers) => ((dx + g.x).round(), layers),
+                None => {
+                    let (x, subpixel) = split_x(dx + g.x);
+                    let x = x as f32 + subpixel as f32 / SUBPIXEL_STEPS as f32;
+                    (x, vec![(g.glyph.id(), foreground)])
+                }
+            };
+            let shear = match g.synthetic.oblique {
+                true => OBLIQUE_SHEAR,
+                false => 0.,
+            };
+            for (id, color) in layers {
+                let glyph = g.font.glyph(id).scaled(g.glyph.scale());
+                // 伪粗体与光栅化一致, 向右重复绘制
+                for k in 0..=g.synthetic.embolden {
+                    let mut builder = PathBuilder::new(color, x + k as f32, y, shear);
+                    if glyph.build_outline(&mut builder) {
+                        ret.push(builder.finish());
+                    }
+                }
+            }
+        }
+        ret
+    }
+}
+
+/// 将水平位置拆分为整数像素和亚像素档位
+fn split_x(x: f32) -> (i32, u8) {
+    let steps = SUBPIXEL_STEPS as i32;
+    let x = (x * steps as f32).round() as i32;
+    (x.div_euclid(steps), x.rem_euclid(steps) as u8)
+}
+
+/// 在整数像素位置绘制缓存的字形, 按(列, 行, 覆盖率)回调
+fn draw_bitmap(
+    bitmap: &GlyphBitmap,
+    x: i32,
+    y: i32,
+    synthetic: Synthetic,
+    mut f: impl FnMut(i32, i32, f32),
+) {
+    if bitmap.width == 0 {
+        return;
+    }
+    for (i, row) in bitmap.coverage.chunks(bitmap.width as usize).enumerate() {
+        let x = x + bitmap.left + synthetic.shift(bitmap.top + i as i32);
+        for (j, &v) in row.iter().enumerate() {
+            if v > 0. {
+                for k in 0..=synthetic.embolden {
+                    f(x + j as i32 + k, y + bitmap.top + i as i32, v);
+                }
+            }
+        }
+    }
+}
+
+/// 整形一段同方向、同字体的文本
+/// 返回(字形, 步进宽度, 所属字形簇的字节下标), 字形位置相对于该字形的起笔点
+/// 缺字的字形簇替换为默认字体的'?', 并计入`missing`
+fn shape(
+    text: &str,
+    rtl: bool,
+    font: &'static Font<'static>,
+    default_f: &'static Font<'static>,
+    scale: Scale,
+    style: FontStyle,
+    missing: &mut usize,
+) -> Vec<(PlacedGlyph, f32, usize)> {
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.set_direction(match rtl {
+        true => Direction::RightToLeft,
+        false => Direction::LeftToRight,
+    });
+    buffer.guess_segment_properties();
+    let output = rustybuzz::shape(font.face(), &[], buffer);
+
+    // 字体单位到像素的比例, 与rusttype的Scale一致
+    let vm = font.v_metrics_unscaled();
+    let scale_y = scale.y / (vm.ascent - vm.descent);
+    let scale_x = scale_y * scale.x / scale.y;
+
+    // 最近一个已输出字形所属的字素簇
+    let mut drawn = None;
+    output
+        .glyph_infos()
+        .iter()
+        .zip(output.glyph_positions())
+        .filter_map(|(info, pos)| {
+            let cluster = info.cluster as usize;
+            let (font, glyph, advance) = if info.glyph_id == 0 {
+                // 不可见字符不绘制, 缺字的字素簇只绘制一个'?'
+                let c = text[cluster..].chars().next()?;
+                if is_ignorable(c) || drawn == Some(cluster) {
+                    return None;
+                }
+                *missing += 1;
+                let g = default_f.glyph('?').scaled(scale);
+                let advance = g.h_metrics().advance_width;
+                (default_f, g, advance)
+            } else {
+                let g = font.glyph(GlyphId(info.glyph_id as u16)).scaled(scale);
+                (font, g, pos.x_advance as f32 * scale_x)
+            };
+            let synthetic = Synthetic::new(font, style, scale);
+            // 伪粗体加宽了字形, 步进宽度随之增加; 零宽的组合符号不变
+            let advance = match advance > 0. {
+                true => advance + synthetic.embolden as f32,
+                false => advance,
+            };
+            let placed = PlacedGlyph {
+                font,
+                glyph,
+                x: pos.x_offset as f32 * scale_x,
+                y: -pos.y_offset as f32 * scale_y,
+                synthetic,
+            };
+            drawn = Some(cluster);
+            Some((placed, advance, cluster))
+        })
+        .collect()
+}
+
+/// 零宽、格式控制和变体选择符等字符, 不需要字形
+/// emoji肤色修饰符在字体不支持时也直接省略, 只显示基础emoji
+fn is_ignorable(c: char) -> bool {
+    is_invisible(c) || is_variation_selector(c) || matches!(c, '\u{1F3FB}'..='\u{1F3FF}')
+}
+
+/// 为一个字素簇选择字体, 簇中全部可见字符都有字形的字体优先
+/// VS16要求emoji字体优先, VS15要求文本字体优先
+/// 簇中没有可见字符时返回None
+fn select_font(cluster: &str, registry: &FontRegistry, style: FontStyle) -> Option<usize> {
+    let fonts = registry.fonts();
+    let mut chars = cluster.chars().filter(|&c| !is_ignorable(c)).peekable();
+    let base = *chars.peek()?;
+    let prefer_emoji = if cluster.contains('\u{FE0F}') {
+        Some(true)
+    } else if cluster.contains('\u{FE0E}') {
+        Some(false)
+    } else {
+        None
+    };
+    let candidates = registry.candidates(base, style);
+    let order: Cow<[usize]> = match prefer_emoji {
+        Some(emoji) => {
+            let mut order = candidates.to_vec();
+            order.sort_by_key(|&i| fonts[i].is_emoji() != emoji);
+            order.into()
+        }
+        None => candidates[..].into(),
+    };
+    let found = order
+        .iter()
+        .copied()
+        .find(|&i| chars.clone().all(|c| fonts[i].has_glyph(c)))
+        .or_else(|| order.iter().copied().find(|&i| fonts[i].has_glyph(base)))
+        // 所有字体都不支持的字符使用默认字体, 整形后替换为'?'
+        .unwrap_or(0);
+    Some(found)
+}
+
+#[cfg(all(test, feature = "embedded-fonts"))]
+mod tests {
+    use super::*;
+    use std::sync::LazyLock;
+
+    static REGISTRY: LazyLock<FontRegistry> = LazyLock::new(FontRegistry::embedded);
+
+    fn shaped(text: &str) -> VecGlyph {
+        VecGlyph::new(text, Scale::uniform(24.), &REGISTRY)
+    }
+
+    #[test]
+    fn test_fallback() {
+        let fonts = REGISTRY.fonts();
+        let emoji = |text: &str| {
+            fonts[select_font(text, &REGISTRY, FontStyle::default()).unwrap()].is_emoji()
+        };
+        assert!(emoji("\u{263A}\u{FE0F}"));
+        assert!(!emoji("\u{263A}\u{FE0E}"));
+        assert_eq!(
+            select_font("\u{200D}", &REGISTRY, FontStyle::default()),
+            None
+        );
+
+        // 不支持的肤色修饰符被省略
+        let thumbs = shaped("\u{1F44D}\u{1F3FD}");
+        assert_eq!(thumbs.glyphs.len(), 1);
+        assert_eq!(thumbs.width(), shaped("\u{1F44D}").width());
+
+        // 零宽字符不占宽度, 也不绘制为'?'
+        assert_eq!(shaped("a\u{200B}\u{2060}b").width(), shaped("ab").width());
+        assert_eq!(shaped("\u{E000}\u{E001}").glyphs.len(), 2);
+    }
+
+    /// 墨迹的(最小行, 最大行, 最小列, 最大列)
+    fn ink(text: &str) -> (u32, u32, u32, u32) {
+        let mut ret = (u32::MAX, 0, u32::MAX, 0);
+        shaped(text).draw(|r, c, v| {
+            if v < 0.5 {
+                ret = (ret.0.min(r), ret.1.max(r), ret.2.min(c), ret.3.max(c));
+            }
+        });
+        ret
+    }
+
+    #[test]
+    fn test_baseline() {
+        // 字形底部对齐到同一基线, 而不是各自居中
+        let (t_top, t_bottom, ..) = ink("T");
+        let (dot_top, dot_bottom, ..) = ink(".");
+        assert!(t_bottom.abs_diff(dot_bottom) <= 1);
+        assert!(dot_top > t_top + 5);
+        assert!(ink("g").1 > t_bottom + 3);
+
+        // 字距调整
+        let advance = |text: &str| shaped(text).advances().sum::<f32>();
+        assert!(advance("AV") < advance("A") + advance("V") - 1.);
+
+        // 负的左侧支承不被裁切
+        let j = shaped("j");
+        let (.., left, right) = ink("j");
+        assert!(j.overhang.0 > 0.);
+        assert!(left <= 1 && (right as f32) < j.width());
+    }
+
+    #[test]
+    fn test_color_bounds() {
+        // 位图字形没有轮廓, 尺寸按位图计算, 绘制时不超出
+        let mut registry = FontRegistry::new();
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fonts/cbdt.ttf");
+        registry.load_file(path).unwrap();
+        let glyphs = VecGlyph::new("\u{1F600}", Scale::uniform(128.), &registry);
+        let (width, height) = (glyphs.width(), glyphs.height());
+        assert!(width >= 103. && height >= 103.);
+        let mut yellow = 0;
+        glyphs.draw_rgba(Rgba([0, 0, 0, 255]), |row, col, color| {
+            assert!((col as f32) < width && (row as f32) < height);
+            yellow += (color == Rgba([255, 204, 0, 255])) as usize;
+        });
+        assert!(yellow > 1000);
+    }
 }
@@ -0,0 +1,428 @@
+use super::font::FontStyle;
+use super::glyph::{Coverage, Scale, VecGlyph};
+use super::path::GlyphPath;
+use super::registry::FontRegistry;
+use crate::error::Error;
+use image::Rgba;
+use std::str::FromStr;
+use unicode_linebreak::{BreakOpportunity, linebreaks};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// 行内对齐方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl FromStr for Align {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "left" => Ok(Align::Left),
+            "center" | "centre" => Ok(Align::Center),
+            "right" => Ok(Align::Right),
+            _ => Err(Error::Render(format!("unknown align: {s}")).into()),
+        }
+    }
+}
+
+/// 排版参数
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutOptions {
+    /// 最大行宽(像素), 超出时按UAX #14规则换行; None表示只在换行符处换行
+    pub max_width: Option<f32>,
+    /// 行距倍数, 1.0为字体默认行高
+    pub line_spacing: f32,
+    pub align: Align,
+    /// 要求的字体样式, 如粗体
+    pub style: FontStyle,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        Self {
+            max_width: None,
+            line_spacing: 1.0,
+            align: Align::Left,
+            style: FontStyle::default(),
+        }
+    }
+}
+
+/// 多行文本排版结果
+pub struct Layout {
+    lines: Vec<(String, VecGlyph)>,
+    line_height: f32,
+    line_spacing: f32,
+    align: Align,
+    /// 各行上行高度和下行高度的最大值
+    ascent: f32,
+    descent: f32,
+}
+
+/// 文本的测量结果, 不绘制图片
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextMetrics {
+    /// 包含墨迹的宽度, 与渲染得到的图片宽度一致(向上取整前)
+    pub width: f32,
+    /// 包含墨迹的高度, 与渲染得到的图片高度一致(向上取整前)
+    pub height: f32,
+    /// 基线以上的高度
+    pub ascent: f32,
+    /// 基线以下的高度, 为正数
+    pub descent: f32,
+    pub line_count: usize,
+    /// 按逻辑顺序排列的字素簇
+    pub graphemes: Vec<GraphemeMetrics>,
+    /// 没有任何字体支持、绘制为'?'的字形簇数量
+    pub missing_glyphs: usize,
+}
+
+/// 单个字素簇的位置
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphemeMetrics {
+    pub text: String,
+    /// 所在行的下标
+    pub line: usize,
+    /// 起笔点的横坐标, 为所在行起笔点加上之前字素簇的步进宽度(按逻辑顺序累加)
+    pub x: f32,
+    pub advance: f32,
+}
+
+impl Layout {
+    #[tracing::instrument(name = "layout", level = "debug", skip_all, fields(len = text.len(), scale = scale.y))]
+    pub fn new(
+        text: &str,
+        scale: Scale,
+        registry: &FontRegistry,
+        options: &LayoutOptions,
+    ) -> Layout {
+        let breaks = match options.max_width {
+            Some(max_width) => {
+                let advances = VecGlyph::with_style(text, scale, registry, options.style)
+                    .advances()
+                    .collect::<Vec<_>>();
+                wrap(text, &advances, max_width)
+            }
+            None => linebreaks(text)
+                .filter(|(_, op)| *op == BreakOpportunity::Mandatory)
+                .map(|(i, _)| i)
+                .collect(),
+        };
+
+        let mut start = 0;
+        let lines = breaks
+            .into_iter()
+            .map(|end| {
+                let line = text[start..end]
+                    .trim_end_matches(|c: char| c.is_whitespace())
+                    .to_string();
+                start = end;
+                let vg = VecGlyph::with_style(&line, scale, registry, options.style);
+                (line, vg)
+            })
+            .collect::<Vec<_>>();
+
+        // 空行没有字形, 使用首个字体的行高
+        let default_vm = registry.fonts().first().map(|f| f.v_metrics(scale));
+        let line_height = default_vm
+            .map(|vm| vm.ascent - vm.descent + vm.line_gap)
+            .into_iter()
+            .chain(lines.iter().map(|(_, vg)| vg.height()))
+            .reduce(f32::max)
+            .unwrap_or(0.);
+        let ascent = default_vm
+            .map(|vm| vm.ascent)
+            .into_iter()
+            .chain(lines.iter().map(|(_, vg)| vg.ascent()))
+            .fold(0., f32::max);
+        let descent = default_vm
+            .map(|vm| -vm.descent)
+            .into_iter()
+            .chain(lines.iter().map(|(_, vg)| vg.descent()))
+            .fold(0., f32::max);
+
+        Self {
+            lines,
+            line_height,
+            line_spacing: options.line_spacing,
+            align: options.align,
+            ascent,
+            descent,
+        }
+    }
+
+    /// 各行字素簇使用的字体及缺字的字符, 不含换行符和行尾空白
+    pub fn coverage(&self) -> Coverage {
+        let graphemes = self
+            .lines
+            .iter()
+            .flat_map(|(_, vg)| vg.coverage().graphemes)
+            .collect();
+        Coverage { graphemes }
+    }
+
+    /// 测量排版结果
+    pub fn metrics(&self) -> TextMetrics {
+        let width = self.width();
+        let mut graphemes = vec![];
+        for (i, (line, vg)) in self.lines.iter().enumerate() {
+            let mut x = self.line_x(vg, width) as f32 + vg.origin().0;
+            let mut advances = vg.advances();
+            for g in line.graphemes(true) {
+                let advance = advances.by_ref().take(g.chars().count()).sum::<f32>();
+                graphemes.push(GraphemeMetrics {
+                    text: g.to_string(),
+                    line: i,
+                    x,
+                    advance,
+                });
+                x += advance;
+            }
+        }
+        TextMetrics {
+            width,
+            height: self.height(),
+            ascent: self.ascent,
+            descent: self.descent,
+            line_count: self.lines.len(),
+            graphemes,
+            missing_glyphs: self.lines.iter().map(|(_, vg)| vg.missing_glyphs()).sum(),
+        }
+    }
+
+    /// 排版后的各行文本, 不含行尾空白
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().map(|(line, _)| line.as_str())
+    }
+
+    pub fn width(&self) -> f32 {
+        self.lines
+            .iter()
+            .map(|(_, vg)| vg.width())
+            .reduce(f32::max)
+            .unwrap_or(0.)
+    }
+
+    pub fn height(&self) -> f32 {
+        let bottom = self
+            .line_tops()
+            .zip(&self.lines)
+            .map(|(y, (_, vg))| y as f32 + vg.height())
+            .fold(0., f32::max);
+        match self.lines.len() {
+            0 => 0.,
+            n => bottom.max(self.line_advance() * (n - 1) as f32 + self.line_height),
+        }
+    }
+
+    fn line_advance(&self) -> f32 {
+        self.line_height * self.line_spacing
+    }
+
+    /// 绘制参数与`VecGlyph::draw`一致
+    pub fn draw(self, mut drawable: impl FnMut(u32, u32, f32)) {
+        self.draw_lines(|vg, y, x| vg.draw(|r, c, v| drawable(r + y, c + x, v)));
+    }
+
+    /// 绘制参数与`VecGlyph::draw_rgba`一致
+    pub fn draw_rgba(self, foreground: Rgba<u8>, mut drawable: impl FnMut(u32, u32, Rgba<u8>)) {
+        self.draw_lines(|vg, y, x| vg.draw_rgba(foreground, |r, c, v| drawable(r + y, c + x, v)));
+    }
+
+    /// 全部字形的轮廓, 位置与`draw`一致
+    pub fn paths(self, foreground: Rgba<u8>) -> Vec<GlyphPath> {
+        let mut ret = vec![];
+        self.draw_lines(|vg, y, x| {
+            let paths = vg.paths(foreground).into_iter();
+            ret.extend(paths.map(|p| p.translate(x as f32, y as f32)));
+        });
+        ret
+    }
+
+    /// 按对齐方式计算每行的起点, 回调(行, 起点行坐标, 起点列坐标)
+    fn draw_lines(self, mut draw_line: impl FnMut(VecGlyph, u32, u32)) {
+        let width = self.width();
+        let starts = self
+            .line_tops()
+            .zip(&self.lines)
+            .map(|(y, (_, vg))| (y, self.line_x(vg, width)))
+            .collect::<Vec<_>>();
+        for ((_, vg), (y, x)) in self.lines.into_iter().zip(starts) {
+            draw_line(vg, y, x);
+        }
+    }
+
+    /// 每行绘制区域的顶部
+    /// 各行共用等距的基线, 第`i`行的基线为各行基线位置的最大值加上`i`倍行距;
+    /// 某行回退到上行高度较大的字体(如emoji、CJK)时不会挤开该行的基线
+    fn line_tops(&self) -> impl Iterator<Item = u32> + '_ {
+        let baseline = self
+            .lines
+            .iter()
+            .map(|(_, vg)| vg.origin().1)
+            .fold(0., f32::max);
+        let advance = self.line_advance();
+        self.lines.iter().enumerate().map(move |(i, (_, vg))| {
+            (advance * i as f32) as u32 + (baseline - vg.origin().1) as u32
+        })
+    }
+
+    /// 按对齐方式计算的行起点, `width`为全部行的最大宽度
+    fn line_x(&self, vg: &VecGlyph, width: f32) -> u32 {
+        (match self.align {
+            Align::Left => 0.,
+            Align::Center => (width - vg.width()) / 2.,
+            Align::Right => width - vg.width(),
+        }) as u32
+    }
+}
+
+/// 按UAX #14贪心换行, 返回各行结束位置的字节下标
+/// 单个不可拆分片段超过最大行宽时按字符强制拆分
+pub(super) fn wrap(text: &str, advances: &[f32], max_width: f32) -> Vec<usize> {
+    let char_starts = text.char_indices().map(|(i, _)| i).collect::<Vec<_>>();
+    let char_idx = |byte: usize| char_starts.partition_point(|&x| x < byte);
+    // 前缀和, prefix[i]为前i个字符的宽度
+    let prefix = std::iter::once(0.)
+        .chain(advances.iter().scan(0., |acc, &x| {
+            *acc += x;
+            Some(*acc)
+        }))
+        .collect::<Vec<f32>>();
+    // 行宽, 不计行尾空白
+    let width = |l: usize, r: usize| {
+        let r = l + text[l..r].trim_end().len();
+        prefix[char_idx(r)] - prefix[char_idx(l)]
+    };
+
+    let mut ret = vec![];
+    let mut line_start = 0;
+    let mut line_end = 0;
+    for (end, op) in linebreaks(text) {
+        // 加入新片段后超宽, 在上一个断点换行
+        if line_end > line_start && width(line_start, end) > max_width {
+            ret.push(line_end);
+            line_start = line_end;
+        }
+        // 单个片段仍超宽, 在最后一个放得下的字符处强制换行, 每行至少一个字符
+        while width(line_start, end) > max_width {
+            let first = line_start + text[line_start..].chars().next().map_or(0, char::len_utf8);
+            let split = text[first..end]
+                .char_indices()
+                .map(|(i, _)| first + i)
+                .take_while(|&i| width(line_start, i) <= max_width)
+                .last()
+                .unwrap_or(first);
+            if split >= end {
+                break;
+            }
+            ret.push(split);
+            line_start = split;
+        }
+        line_end = end;
+        if op == BreakOpportunity::Mandatory {
+            ret.push(end);
+            line_start = end;
+        }
+    }
+    ret
+}
+
+#[cfg(all(test, feature = "embedded-fonts"))]
+mod tests {
+    use super::*;
+    use std::sync::LazyLock;
+
+    static REGISTRY: LazyLock<FontRegistry> = LazyLock::new(FontRegistry::embedded);
+
+    fn lines(text: &str, max_width: Option<f32>) -> Vec<String> {
+        let options = LayoutOptions {
+            max_width,
+            ..Default::default()
+        };
+        Layout::new(text, Scale::uniform(24.), &REGISTRY, &options)
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    fn width(text: &str) -> f32 {
+        VecGlyph::new(text, Scale::uniform(24.), &REGISTRY).width()
+    }
+
+    #[test]
+    fn test_newline() {
+        assert_eq!(
+            lines("first\nsecond\r\n\nlast", None),
+            ["first", "second", "", "last"]
+        );
+    }
+
+    #[test]
+    fn test_wrap() {
+        let max = width("hello world") + 1.;
+        assert_eq!(
+            lines("hello world foo bar", Some(max)),
+            ["hello world", "foo bar"]
+        );
+        // 过长的单词按字符拆分
+        let max = width("abcd") + 1.;
+        assert_eq!(lines("abcdefgh", Some(max)), ["abcd", "efgh"]);
+    }
+
+    #[test]
+    fn test_align() {
+        let options = LayoutOptions {
+            align: Align::Right,
+            ..Default::default()
+        };
+        let layout = Layout::new("a\nwide line", Scale::uniform(24.), &REGISTRY, &options);
+        let total = layout.width();
+        let mut min_col = u32::MAX;
+        layout.draw(|r, c, v| {
+            if r < 20 && v < 0.5 {
+                min_col = min_col.min(c);
+            }
+        });
+        // 第一行右对齐, 只有一个字符
+        assert!(min_col as f32 > total - width("a") - 2.);
+    }
+
+    /// 每行开头的`x`墨迹的最低行(即各行的基线)减去该行的行距偏移, 各行基线对齐时全部相等
+    fn baselines(text: &str, registry: &FontRegistry) -> Vec<u32> {
+        let options = LayoutOptions::default();
+        let layout = Layout::new(text, Scale::uniform(24.), registry, &options);
+        let (advance, max_col) = (layout.line_advance(), width("x") as u32);
+        let mut ret = vec![0; layout.lines.len()];
+        layout.draw(|r, c, v| {
+            let line = (r as f32 / advance) as usize;
+            if c < max_col && v < 0.5 && line < ret.len() {
+                ret[line] = ret[line].max(r - (advance * line as f32) as u32);
+            }
+        });
+        ret
+    }
+
+    #[test]
+    fn test_baseline() {
+        // 彩色emoji位图超出上行高度, 各行的基线仍相隔一个行距
+        let mut registry = FontRegistry::new();
+        for path in ["fonts/DejaVuSans.ttf", "tests/fonts/cbdt.ttf"] {
+            registry
+                .load_file(format!("{}/{path}", env!("CARGO_MANIFEST_DIR")))
+                .unwrap();
+        }
+        for text in ["x\u{1F600}\u{FE0F}\nx", "x\nx\u{1F600}\u{FE0F}"] {
+            let baselines = baselines(text, &registry);
+            assert_eq!(baselines[0], baselines[1], "{text:?}");
+        }
+        // 叠加的组合符号超出行顶
+        let baselines = baselines("x\nx\u{30A}\u{30A}\u{30A}", &REGISTRY);
+        assert_eq!(baselines[0], baselines[1]);
+    }
+}
@@ -0,0 +1,272 @@
+use image::Rgba;
+use rusttype::OutlineBuilder;
+use std::fmt::Write;
+
+/// 路径命令, 坐标单位为像素, y轴向下, 与光栅图片一致
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathCommand {
+    MoveTo(f32, f32),
+    LineTo(f32, f32),
+    /// 二次贝塞尔曲线, 为(控制点, 终点)
+    QuadTo(f32, f32, f32, f32),
+    /// 三次贝塞尔曲线, 为(控制点1, 控制点2, 终点)
+    CurveTo(f32, f32, f32, f32, f32, f32),
+    Close,
+}
+
+/// 一个字形(或彩色字形的一个图层)的轮廓, 按非零环绕规则填充
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphPath {
+    pub color: Rgba<u8>,
+    pub commands: Vec<PathCommand>,
+}
+
+impl GlyphPath {
+    /// 平移路径
+    pub fn translate(mut self, dx: f32, dy: f32) -> Self {
+        for cmd in &mut self.commands {
+            *cmd = match *cmd {
+                PathCommand::MoveTo(x, y) => PathCommand::MoveTo(x + dx, y + dy),
+                PathCommand::LineTo(x, y) => PathCommand::LineTo(x + dx, y + dy),
+                PathCommand::QuadTo(x1, y1, x, y) => {
+                    PathCommand::QuadTo(x1 + dx, y1 + dy, x + dx, y + dy)
+                }
+                PathCommand::CurveTo(x1, y1, x2, y2, x, y) => {
+                    PathCommand::CurveTo(x1 + dx, y1 + dy, x2 + dx, y2 + dy, x + dx, y + dy)
+                }
+                PathCommand::Close => PathCommand::Close,
+            };
+        }
+        self
+    }
+
+    /// SVG的路径数据, 即`<path>`的`d`属性
+    pub fn to_svg(&self) -> String {
+        let mut ret = String::new();
+        for cmd in &self.commands {
+            match *cmd {
+                PathCommand::MoveTo(x, y) => write!(ret, "M{} {}", num(x), num(y)),
+                PathCommand::LineTo(x, y) => write!(ret, "L{} {}", num(x), num(y)),
+                PathCommand::QuadTo(x1, y1, x, y) => {
+                    write!(ret, "Q{} {} {} {}", num(x1), num(y1), num(x), num(y))
+                }
+                PathCommand::CurveTo(x1, y1, x2, y2, x, y) => write!(
+                    ret,
+                    "C{} {} {} {} {} {}",
+                    num(x1),
+                    num(y1),
+                    num(x2),
+                    num(y2),
+                    num(x),
+                    num(y)
+                ),
+                PathCommand::Close => write!(ret, "Z"),
+            }
+            .unwrap();
+        }
+        ret
+    }
+
+    /// PDF内容流中的路径构造操作符(`m`、`l`、`c`、`h`), 不含填充操作符
+    /// PDF的y轴向上, `height`为页面高度; 二次曲线转换为等价的三次曲线
+    pub fn to_pdf(&self, height: f32) -> String {
+        let mut ret = String::new();
+        let mut current = (0., 0.);
+        let mut start = (0., 0.);
+        for cmd in &self.commands {
+            match *cmd {
+                PathCommand::MoveTo(x, y) => {
+                    writeln!(ret, "{} {} m", num(x), num(height - y)).unwrap();
+                    current = (x, y);
+                    start = (x, y);
+                }
+                PathCommand::LineTo(x, y) => {
+                    writeln!(ret, "{} {} l", num(x), num(height - y)).unwrap();
+                    current = (x, y);
+                }
+                PathCommand::QuadTo(x1, y1, x, y) => {
+                    let (x0, y0) = current;
+                    let c1 = (x0 + (x1 - x0) * 2. / 3., y0 + (y1 - y0) * 2. / 3.);
+                    let c2 = (x + (x1 - x) * 2. / 3., y + (y1 - y) * 2. / 3.);
+                    let points = [c1, c2, (x, y)];
+                    for (x, y) in points {
+                        write!(ret, "{} {} ", num(x), num(height - y)).unwrap();
+                    }
+                    ret.push_str("c\n");
+                    current = (x, y);
+                }
+                PathCommand::CurveTo(x1, y1, x2, y2, x, y) => {
+                    for (x, y) in [(x1, y1), (x2, y2), (x, y)] {
+                        write!(ret, "{} {} ", num(x), num(height - y)).unwrap();
+                    }
+                    ret.push_str("c\n");
+                    current = (x, y);
+                }
+                PathCommand::Close => {
+                    ret.push_str("h\n");
+                    current = start;
+                }
+            }
+        }
+        ret
+    }
+}
+
+/// 生成SVG文档, 相同颜色的相邻路径合并为一个`<path>`
+/// 背景色完全透明时不绘制背景
+pub fn svg_document(width: u32, height: u32, background: Rgba<u8>, paths: &[GlyphPath]) -> String {
+    let mut ret = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    );
+    ret.push('\n');
+    if background.0[3] > 0 {
+        writeln!(
+            ret,
+            r#"<rect width="100%" height="100%"{}/>"#,
+            svg_fill(background)
+        )
+        .unwrap();
+    }
+    for group in paths.chunk_by(|a, b| a.color == b.color) {
+        let data = group.iter().map(GlyphPath::to_svg).collect::<String>();
+        if !data.is_empty() {
+            writeln!(ret, r#"<path d="{data}"{}/>"#, svg_fill(group[0].color)).unwrap();
+        }
+    }
+    ret.push_str("</svg>\n");
+    ret
+}
+
+fn svg_fill(color: Rgba<u8>) -> String {
+    let [r, g, b, a] = color.0;
+    let mut ret = format!(r##" fill="#{r:02x}{g:02x}{b:02x}""##);
+    if a < 255 {
+        write!(ret, r#" fill-opacity="{}""#, num(a as f32 / 255.)).unwrap();
+    }
+    ret
+}
+
+/// 生成单页的PDF文档, 1像素对应1pt
+/// PDF的填充色不含alpha, 颜色的透明度被忽略, 完全透明的背景和路径不绘制
+pub fn pdf_document(width: u32, height: u32, background: Rgba<u8>, paths: &[GlyphPath]) -> Vec<u8> {
+    let h = height as f32;
+    let rgb = |color: Rgba<u8>| {
+        let [r, g, b, _] = color.0.map(|x| num(x as f32 / 255.));
+        format!("{r} {g} {b} rg\n")
+    };
+    let mut content = String::new();
+    if background.0[3] > 0 {
+        content.push_str(&rgb(background));
+        writeln!(content, "0 0 {width} {height} re f").unwrap();
+    }
+    for group in paths.chunk_by(|a, b| a.color == b.color) {
+        if group[0].color.0[3] == 0 {
+            continue;
+        }
+        content.push_str(&rgb(group[0].color));
+        group.iter().for_each(|p| content.push_str(&p.to_pdf(h)));
+        content.push_str("f\n");
+    }
+
+    let objects = [
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {width} {height}] /Resources << >> /Contents 4 0 R >>"
+        ),
+        format!(
+            "<< /Length {} >>\nstream\n{content}endstream",
+            content.len()
+        ),
+    ];
+    let mut ret = b"%PDF-1.4\n".to_vec();
+    let mut offsets = vec![];
+    for (i, obj) in objects.iter().enumerate() {
+        offsets.push(ret.len());
+        ret.extend(format!("{} 0 obj\n{obj}\nendobj\n", i + 1).bytes());
+    }
+    let xref = ret.len();
+    let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        writeln!(trailer, "{offset:010} 00000 n ").unwrap();
+    }
+    write!(
+        trailer,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+        objects.len() + 1
+    )
+    .unwrap();
+    ret.extend(trailer.bytes());
+    ret
+}
+
+/// 保留两位小数并去掉末尾的0
+fn num(x: f32) -> String {
+    let s = format!("{x:.2}");
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    match s {
+        "-0" => "0".to_string(),
+        s => s.to_string(),
+    }
+}
+
+/// 收集字形轮廓, 坐标相对于起笔点, 平移到(x, y)并按`shear`水平错切
+pub(super) struct PathBuilder {
+    x: f32,
+    y: f32,
+    shear: f32,
+    path: GlyphPath,
+}
+
+impl PathBuilder {
+    pub(super) fn new(color: Rgba<u8>, x: f32, y: f32, shear: f32) -> Self {
+        Self {
+            x,
+            y,
+            shear,
+            path: GlyphPath {
+                color,
+                commands: vec![],
+            },
+        }
+    }
+
+    pub(super) fn finish(self) -> GlyphPath {
+        self.path
+    }
+
+    fn map(&self, x: f32, y: f32) -> (f32, f32) {
+        (self.x + x - y * self.shear, self.y + y)
+    }
+}
+
+impl OutlineBuilder for PathBuilder {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.map(x, y);
+        self.path.commands.push(PathCommand::MoveTo(x, y));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.map(x, y);
+        self.path.commands.push(PathCommand::LineTo(x, y));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (x1, y1) = self.map(x1, y1);
+        let (x, y) = self.map(x, y);
+        self.path.commands.push(PathCommand::QuadTo(x1, y1, x, y));
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (x1, y1) = self.map(x1, y1);
+        let (x2, y2) = self.map(x2, y2);
+        let (x, y) = self.map(x, y);
+        self.path
+            .commands
+            .push(PathCommand::CurveTo(x1, y1, x2, y2, x, y));
+    }
+
+    fn close(&mut self) {
+        self.path.commands.push(PathCommand::Close);
+    }
+}
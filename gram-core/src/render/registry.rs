@@ -0,0 +1,354 @@
+use super::font::{Font, FontStyle};
+use crate::unicode::is_emoji;
+use anyhow::{Context, Result, anyhow};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use unicode_script::{Script, UnicodeScript};
+
+/// 已读取的字体文件, 字体数据在进程内常驻, 同一文件只读取一次
+static LOADED: LazyLock<Mutex<HashMap<PathBuf, &'static [u8]>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// 字体数据的地址、长度及在集合中的下标
+type FaceKey = (usize, usize, u32);
+
+/// 已解析的字体, 重复加载时复用
+static FACES: LazyLock<Mutex<HashMap<FaceKey, &'static Font<'static>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+static GLOBAL: LazyLock<RwLock<FontRegistry>> = LazyLock::new(|| {
+    #[cfg(feature = "embedded-fonts")]
+    let registry = FontRegistry::embedded();
+    #[cfg(not(feature = "embedded-fonts"))]
+    let registry = FontRegistry::new();
+    RwLock::new(registry)
+});
+
+/// 字体注册表, 决定渲染时使用的字体及回退顺序
+/// 字体默认按加入顺序回退, 也可以为特定脚本或语言指定优先使用的字体族
+#[derive(Clone, Default)]
+pub struct FontRegistry {
+    fonts: Vec<&'static Font<'static>>,
+    /// 键为ISO 15924脚本代码(如`Hani`)或语言标签(如`ja`), 值为小写的字体族名
+    priorities: HashMap<String, Vec<String>>,
+    language: Option<String>,
+    candidates: CandidateCache,
+}
+
+/// 脚本代码(emoji为`Zsye`, 不属于特定脚本时为None)及样式
+type CandidateKey = (Option<&'static str>, FontStyle);
+
+/// 按脚本和样式缓存的候选字体顺序, 加入字体或修改优先级、语言时清空
+/// 克隆注册表时不复制缓存, 避免修改克隆后与原注册表互相影响
+#[derive(Default)]
+struct CandidateCache(RwLock<HashMap<CandidateKey, Arc<[usize]>>>);
+
+impl Clone for CandidateCache {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl CandidateCache {
+    fn clear(&mut self) {
+        self.0
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+}
+
+impl FontRegistry {
+    /// 空的注册表
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 包含全部内置字体的注册表
+    #[cfg(feature = "embedded-fonts")]
+    pub fn embedded() -> Self {
+        Self {
+            fonts: super::font::embedded_fonts().iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    /// 进程内共享的注册表, `render_text`等函数使用此注册表
+    /// 启用`embedded-fonts`时初始包含全部内置字体, 否则为空
+    pub fn global() -> &'static RwLock<FontRegistry> {
+        &GLOBAL
+    }
+
+    /// 读取`global()`
+    /// 持有锁的线程panic(如解析损坏的字体时)会使锁中毒; 注册表只会追加字体或覆盖设置,
+    /// 不会处于不一致的状态, 因此清除中毒标记继续使用, 否则之后的渲染都会失败
+    pub fn read_global() -> RwLockReadGuard<'static, FontRegistry> {
+        GLOBAL.clear_poison();
+        GLOBAL.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// 修改`global()`, 锁中毒时的处理与`read_global`一致
+    pub fn write_global() -> RwLockWriteGuard<'static, FontRegistry> {
+        GLOBAL.clear_poison();
+        GLOBAL.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// 加入字体, 优先级低于已有字体
+    pub fn add(&mut self, font: &'static Font<'static>) {
+        self.fonts.push(font);
+        self.candidates.clear();
+    }
+
+    /// 从字节加载字体, 支持TTC/OTC字体集合, 返回新加入的字体数量
+    /// 同一份数据中已在注册表内的字体会被跳过, 重复加载时返回0
+    pub fn load_bytes(&mut self, data: &'static [u8]) -> Result<usize> {
+        let count = rustybuzz::ttf_parser::fonts_in_collection(data).unwrap_or(1);
+        let mut faces = FACES.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut added = 0;
+        for index in 0..count {
+            let font = match faces.entry((data.as_ptr() as usize, data.len(), index)) {
+                Entry::Occupied(entry) => *entry.get(),
+                Entry::Vacant(entry) => {
+                    let font = Font::try_from_bytes_and_index(data, index)
+                        .ok_or(anyhow!("invalid font data (index {index})"))?;
+                    *entry.insert(Box::leak(Box::new(font)))
+                }
+            };
+            if !self.fonts.iter().any(|x| std::ptr::eq(*x, font)) {
+                self.add(font);
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    /// 从文件加载字体, 支持TTC/OTC字体集合, 返回新加入的字体数量
+    /// 字体数据在进程退出前不会释放, 同一文件只读取和解析一次
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<usize> {
+        let path = path.as_ref();
+        let data = read_font_file(path)?;
+        self.load_bytes(data)
+            .with_context(|| format!("failed to load {}", path.display()))
+    }
+
+    /// 递归加载目录下的全部字体文件(`.ttf`、`.otf`、`.ttc`、`.otc`), 按路径排序
+    /// 无法解析的文件会被跳过, 返回新加入的字体数量
+    pub fn load_dir(&mut self, dir: impl AsRef<Path>) -> Result<usize> {
+        let mut files = vec![];
+        let mut dirs = vec![dir.as_ref().to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let entries = std::fs::read_dir(&dir)
+                .with_context(|| format!("failed to read {}", dir.display()))?;
+            for entry in entries {
+                let path = entry?.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if path.extension().and_then(|x| x.to_str()).is_some_and(|x| {
+                    matches!(x.to_lowercase().as_str(), "ttf" | "otf" | "ttc" | "otc")
+                }) {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+
+        let mut count = 0;
+        for path in files {
+            match self.load_file(&path) {
+                Ok(n) => count += n,
+                Err(e) => tracing::warn!("skip font {}: {e:#}", path.display()),
+            }
+        }
+        Ok(count)
+    }
+
+    pub fn fonts(&self) -> &[&'static Font<'static>] {
+        &self.fonts
+    }
+
+    pub fn len(&self) -> usize {
+        self.fonts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fonts.is_empty()
+    }
+
+    /// 为脚本或语言指定优先使用的字体族, 覆盖之前的设置
+    /// `key`为ISO 15924脚本代码(如`Hani`、`Arab`)或语言标签(如`ja`、`zh-Hant`);
+    /// emoji使用`Zsye`, 如让加载的彩色emoji字体优先于内置的单色emoji字体
+    pub fn set_priority<S: AsRef<str>>(&mut self, key: &str, families: &[S]) {
+        let families = families.iter().map(|x| x.as_ref().to_lowercase()).collect();
+        self.priorities.insert(key.to_lowercase(), families);
+        self.candidates.clear();
+    }
+
+    /// 设置文本语言, 该语言的优先字体族适用于所有字符; None表示不区分语言
+    pub fn set_language(&mut self, language: Option<&str>) {
+        self.language = language.map(str::to_lowercase);
+        self.candidates.clear();
+    }
+
+    /// 字符的候选字体下标
+    /// 依次为语言的优先字体、字符所属脚本的优先字体, 其余按加入顺序;
+    /// 两组内部再按与`style`不一致的样式数量排序, 如要求粗体时粗体字体在前
+    pub(super) fn candidates(&self, c: char, style: FontStyle) -> Arc<[usize]> {
+        let script = match c.script() {
+            _ if is_emoji(c) => Some("Zsye"),
+            Script::Common | Script::Inherited | Script::Unknown => None,
+            script => Some(script.short_name()),
+        };
+        let cache = &self.candidates.0;
+        if let Some(ret) = cache
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&(script, style))
+        {
+            return ret.clone();
+        }
+        let ret: Arc<[usize]> = self.sorted_candidates(script, style).into();
+        cache
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert((script, style), ret.clone());
+        ret
+    }
+
+    fn sorted_candidates(&self, script: Option<&str>, style: FontStyle) -> Vec<usize> {
+        let script = script.map(str::to_lowercase);
+        let preferred = self
+            .language
+            .iter()
+            .chain(script.iter())
+            .filter_map(|key| self.priorities.get(key))
+            .flatten()
+            .flat_map(|family| {
+                self.fonts
+                    .iter()
+                    .enumerate()
+                    .filter(move |(_, f)| f.family_key() == family)
+                    .map(|(i, _)| i)
+            });
+
+        let mut seen = vec![false; self.fonts.len()];
+        let mut ret = preferred
+            .filter(|&i| !std::mem::replace(&mut seen[i], true))
+            .collect::<Vec<_>>();
+        let preferred = ret.len();
+        ret.extend((0..self.fonts.len()).filter(|&i| !seen[i]));
+
+        let mismatch = |i: &usize| {
+            let s = self.fonts[*i].style();
+            (s.bold != style.bold) as u8
+                + (s.italic != style.italic) as u8
+                + (s.monospace != style.monospace) as u8
+        };
+        ret[..preferred].sort_by_key(mismatch);
+        ret[preferred..].sort_by_key(mismatch);
+        ret
+    }
+}
+
+fn read_font_file(path: &Path) -> Result<&'static [u8]> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    let mut loaded = LOADED.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(data) = loaded.get(&path) {
+        return Ok(data);
+    }
+    let data =
+        std::fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+    let data: &'static [u8] = Box::leak(data.into_boxed_slice());
+    loaded.insert(path, data);
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fonts");
+        let mut registry = FontRegistry::new();
+        assert!(registry.load_dir(dir).unwrap() > 0);
+        // 重复加载同一文件不会加入新的字体
+        let len = registry.len();
+        let path = format!("{dir}/DejaVuSans.ttf");
+        assert_eq!(registry.load_file(&path).unwrap(), 0);
+        assert_eq!(
+            registry
+                .load_file(format!("{dir}/../fonts/DejaVuSans.ttf"))
+                .unwrap(),
+            0
+        );
+        assert_eq!(registry.fonts().len(), len);
+        // 其他注册表复用已解析的字体
+        let mut other = FontRegistry::new();
+        assert_eq!(other.load_file(&path).unwrap(), 1);
+        assert!(
+            registry
+                .fonts()
+                .iter()
+                .any(|f| std::ptr::eq(*f, other.fonts()[0]))
+        );
+        assert!(registry.load_file(format!("{dir}/missing.ttf")).is_err());
+
+        let dejavu = registry
+            .fonts()
+            .iter()
+            .position(|f| f.family() == "DejaVu Sans" && f.style() == FontStyle::default())
+            .unwrap();
+        let hebrew = registry
+            .fonts()
+            .iter()
+            .position(|f| f.family() == "Noto Sans Hebrew")
+            .unwrap();
+        let regular = FontStyle::default();
+        // DejaVu Sans按路径排在前面, 为希伯来文指定优先字体后顺序改变
+        assert!(
+            registry
+                .candidates('א', regular)
+                .iter()
+                .position(|&i| i == dejavu)
+                < registry
+                    .candidates('א', regular)
+                    .iter()
+                    .position(|&i| i == hebrew)
+        );
+        // 同一脚本的字符共用缓存的候选顺序, 修改优先级后缓存失效
+        let cached = registry.candidates('א', regular);
+        assert!(Arc::ptr_eq(&cached, &registry.candidates('ב', regular)));
+        registry.set_priority("Hebr", &["Noto Sans Hebrew"]);
+        assert_eq!(registry.candidates('א', regular)[0], hebrew);
+        assert!(!Arc::ptr_eq(&cached, &registry.candidates('א', regular)));
+        assert_ne!(registry.candidates('a', regular)[0], hebrew);
+        registry.set_priority("he", &["Noto Sans Hebrew"]);
+        registry.set_language(Some("he"));
+        assert_eq!(registry.candidates('a', regular)[0], hebrew);
+
+        // 要求等宽时等宽字体排在其余字体之前
+        let mono = FontStyle {
+            monospace: true,
+            ..regular
+        };
+        let first = registry.candidates('a', mono)[1];
+        assert!(registry.fonts()[first].style().monospace);
+
+        // 彩色emoji字体加在最后, 为emoji指定优先字体后排在单色emoji字体之前
+        let color = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fonts/cbdt.ttf");
+        registry.load_file(color).unwrap();
+        registry.set_language(None);
+        let first = |registry: &FontRegistry, c| {
+            let i = registry.candidates(c, regular)[0];
+            registry.fonts()[i].family().to_string()
+        };
+        assert_ne!(first(&registry, '😀'), "Emoji CBDT");
+        registry.set_priority("Zsye", &["Emoji CBDT"]);
+        assert_eq!(first(&registry, '😀'), "Emoji CBDT");
+        assert_ne!(first(&registry, 'a'), "Emoji CBDT");
+    }
+}
@@ -0,0 +1,586 @@
+use super::color::{blend, with_coverage};
+use super::font::FontStyle;
+use super::glyph::{Scale, VecGlyph};
+use super::layout::wrap;
+use super::registry::FontRegistry;
+use crate::error::Error;
+use crate::extract::entity::{entity_range, utf16_range_to_utf8};
+use anyhow::Result;
+use grammers_tl_types::enums::MessageEntity;
+use image::{Rgba, RgbaImage};
+use std::ops::Range;
+use std::str::FromStr;
+use unicode_bidi::BidiInfo;
+use unicode_linebreak::{BreakOpportunity, linebreaks};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// 剧透内容的显示方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SpoilerMode {
+    /// 以前景色涂黑
+    #[default]
+    Blackout,
+    /// 绘制后模糊
+    Blur,
+}
+
+impl FromStr for SpoilerMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "blackout" => Ok(SpoilerMode::Blackout),
+            "blur" => Ok(SpoilerMode::Blur),
+            _ => Err(Error::Render(format!("unknown spoiler mode: {s}")).into()),
+        }
+    }
+}
+
+/// 富文本渲染参数
+#[derive(Debug, Clone, PartialEq)]
+pub struct RichOptions {
+    pub scale: f32,
+    /// 最大行宽(像素), 不含边距; None表示只在换行符处换行
+    pub max_width: Option<f32>,
+    /// 行距倍数, 1.0为字体默认行高
+    pub line_spacing: f32,
+    pub foreground: Rgba<u8>,
+    pub background: Rgba<u8>,
+    /// 链接、提及、话题标签等的文字颜色, 也用于引用块的竖线
+    pub link_color: Rgba<u8>,
+    /// 行内代码和代码块的背景色
+    pub code_background: Rgba<u8>,
+    pub spoiler: SpoilerMode,
+}
+
+impl Default for RichOptions {
+    fn default() -> Self {
+        Self {
+            scale: 32.,
+            max_width: None,
+            line_spacing: 1.0,
+            foreground: Rgba([0, 0, 0, 255]),
+            background: Rgba([255, 255, 255, 255]),
+            link_color: Rgba([0x24, 0x81, 0xcc, 255]),
+            code_background: Rgba([0, 0, 0, 0x1a]),
+            spoiler: SpoilerMode::Blackout,
+        }
+    }
+}
+
+/// 一段文本的样式, 由覆盖它的全部entity叠加而成
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Style {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    strike: bool,
+    spoiler: bool,
+    code: bool,
+    pre: bool,
+    link: bool,
+    quote: bool,
+    custom_emoji: bool,
+}
+
+impl Style {
+    fn apply(&mut self, entity: &MessageEntity) {
+        match entity {
+            MessageEntity::Bold(_) => self.bold = true,
+            MessageEntity::Italic(_) => self.italic = true,
+            MessageEntity::Underline(_) => self.underline = true,
+            MessageEntity::Strike(_) => self.strike = true,
+            MessageEntity::Spoiler(_) => self.spoiler = true,
+            MessageEntity::Code(_) => self.code = true,
+            MessageEntity::Pre(_) => self.pre = true,
+            MessageEntity::Blockquote(_) => self.quote = true,
+            MessageEntity::CustomEmoji(_) => self.custom_emoji = true,
+            MessageEntity::Mention(_)
+            | MessageEntity::Hashtag(_)
+            | MessageEntity::BotCommand(_)
+            | MessageEntity::Url(_)
+            | MessageEntity::Email(_)
+            | MessageEntity::TextUrl(_)
+            | MessageEntity::MentionName(_)
+            | MessageEntity::InputMessageEntityMentionName(_)
+            | MessageEntity::Phone(_)
+            | MessageEntity::Cashtag(_)
+            | MessageEntity::BankCard(_) => self.link = true,
+            MessageEntity::Unknown(_) => {}
+        }
+    }
+
+    fn font_style(&self) -> FontStyle {
+        FontStyle {
+            bold: self.bold,
+            italic: self.italic,
+            monospace: self.code || self.pre,
+        }
+    }
+}
+
+/// 行内同一样式的一段文本, 自定义emoji每个字素簇单独成段
+struct Piece {
+    /// 自定义emoji为None, 绘制为占位方块
+    vg: Option<VecGlyph>,
+    style: Style,
+    /// 相对于行首的起笔点
+    x: f32,
+    advance: f32,
+}
+
+struct Line {
+    pieces: Vec<Piece>,
+    /// 引用块、代码块的缩进
+    indent: f32,
+    style: Style,
+    rtl: bool,
+    /// 基线到行顶的距离
+    baseline: f32,
+    height: f32,
+}
+
+impl Line {
+    /// 包含墨迹的宽度
+    fn width(&self) -> f32 {
+        self.pieces
+            .iter()
+            .map(|p| match &p.vg {
+                Some(vg) => p.x + p.advance.max(vg.width() - vg.origin().0),
+                None => p.x + p.advance,
+            })
+            .fold(0., f32::max)
+    }
+}
+
+/// 按消息的entity渲染带格式的文本, 返回RGBA图片
+/// 支持粗体、斜体、下划线、删除线、剧透、行内代码和代码块、链接着色、引用块和自定义emoji占位;
+/// 注册表中没有粗体或斜体字体时以伪粗体、伪斜体绘制, 代码使用等宽字体
+/// 使用`FontRegistry::global()`中的字体
+pub fn render_message(
+    text: &str,
+    entities: &[MessageEntity],
+    options: &RichOptions,
+) -> Result<RgbaImage> {
+    let registry = FontRegistry::read_global();
+    render_message_with(text, entities, options, &registry)
+}
+
+/// 使用指定字体注册表的`render_message`
+#[tracing::instrument(level = "debug", skip_all, fields(len = text.len(), entities = entities.len()))]
+pub fn render_message_with(
+    text: &str,
+    entities: &[MessageEntity],
+    options: &RichOptions,
+    registry: &FontRegistry,
+) -> Result<RgbaImage> {
+    // 每个字节的样式
+    let mut styles = vec![Style::default(); text.len()];
+    for entity in entities {
+        if let Some((offset, length)) = entity_range(entity) {
+            let (l, r) = utf16_range_to_utf8(text, offset as usize, length as usize)?;
+            styles[l..r].iter_mut().for_each(|s| s.apply(entity));
+        }
+    }
+
+    let scale = options.scale;
+    let (ascent, line_height) = registry
+        .fonts()
+        .first()
+        .map(|f| {
+            let vm = f.v_metrics(Scale::uniform(scale));
+            (vm.ascent.round(), vm.ascent - vm.descent + vm.line_gap)
+        })
+        .unwrap_or((0., 0.));
+
+    // 与`Layout`一致按UAX #14的强制断行分段, 段尾的换行符(包括CRLF)不参与排版
+    let paragraphs = linebreaks(text)
+        .filter(|(_, op)| *op == BreakOpportunity::Mandatory)
+        .scan(0, |start, (end, _)| {
+            let para = text[*start..end].trim_end_matches(is_line_break);
+            let range = *start..*start + para.len();
+            *start = end;
+            Some((para, range))
+        })
+        .collect::<Vec<_>>();
+
+    let mut lines = vec![];
+    for (para, range) in paragraphs {
+        let style = styles.get(range.start).copied().unwrap_or_default();
+        let indent = match (style.quote, style.pre) {
+            (true, true) => scale,
+            (true, false) => scale * 0.75,
+            (false, true) => scale * 0.25,
+            (false, false) => 0.,
+        };
+        let rtl = BidiInfo::new(para, None)
+            .paragraphs
+            .first()
+            .is_some_and(|p| p.level.is_rtl());
+
+        let spans = split_spans(text, range.clone(), &styles);
+        let breaks = match options.max_width {
+            Some(max_width) if !para.is_empty() => {
+                let advances = spans
+                    .iter()
+                    .flat_map(|(range, style)| {
+                        advances(&text[range.clone()], *style, scale, registry)
+                    })
+                    .collect::<Vec<_>>();
+                wrap(para, &advances, (max_width - indent).max(scale))
+            }
+            _ => vec![para.len()],
+        };
+
+        let mut line_start = range.start;
+        for end in breaks {
+            let end = range.start + end;
+            let line_end = line_start + text[line_start..end].trim_end().len();
+            let pieces = spans
+                .iter()
+                .filter_map(|(r, style)| {
+                    let r = r.start.max(line_start)..r.end.min(line_end);
+                    (r.start < r.end).then_some((r, *style))
+                })
+                .flat_map(|(r, style)| pieces(&text[r], style, scale, registry))
+                .collect();
+            lines.push(Line {
+                pieces,
+                indent,
+                style,
+                rtl,
+                baseline: ascent,
+                height: line_height,
+            });
+            line_start = end;
+        }
+    }
+
+    // 同一行中不同字体的片段对齐到共同的基线
+    for line in &mut lines {
+        let mut x = 0.;
+        for piece in &mut line.pieces {
+            piece.x = x;
+            x += piece.advance;
+            if let Some(vg) = &piece.vg {
+                line.baseline = line.baseline.max(vg.origin().1);
+            }
+        }
+        let descent = line_height - ascent;
+        line.height = line.baseline + descent;
+        for vg in line.pieces.iter().filter_map(|p| p.vg.as_ref()) {
+            line.height = line.height.max(line.baseline - vg.origin().1 + vg.height());
+        }
+    }
+
+    let padding = (scale * 0.25).round();
+    let content_width = lines
+        .iter()
+        .map(|l| l.indent + l.width())
+        .fold(0., f32::max)
+        .ceil();
+    let advances = lines
+        .iter()
+        .map(|l| (l.height.max(line_height) * options.line_spacing).round())
+        .collect::<Vec<_>>();
+    let height = match lines.last() {
+        Some(last) => advances.iter().sum::<f32>() - advances.last().unwrap() + last.height,
+        None => 0.,
+    };
+    let mut img = RgbaImage::from_pixel(
+        (content_width + padding * 2.) as u32,
+        (height.ceil() + padding * 2.) as u32,
+        options.background,
+    );
+
+    let mut blurred = vec![];
+    let mut top = padding;
+    for (line, advance) in lines.into_iter().zip(advances) {
+        let canvas = Canvas {
+            top,
+            left: padding + line.indent,
+            baseline: top + line.baseline,
+        };
+        let row = top..top + advance.max(line.height);
+
+        if line.style.quote {
+            let bar = (scale / 10.).round().max(2.);
+            fill(
+                &mut img,
+                padding..padding + bar,
+                row.clone(),
+                options.link_color,
+            );
+        }
+        if line.style.pre {
+            let left = padding + if line.style.quote { scale * 0.75 } else { 0. };
+            let cols = left..padding + content_width;
+            fill(&mut img, cols, row.clone(), options.code_background);
+        }
+
+        // 从右到左的段落按相反顺序排列各片段并右对齐
+        let width = line.width();
+        let offset = match line.rtl {
+            true => content_width - line.indent - width,
+            false => 0.,
+        };
+        for mut piece in line.pieces {
+            if line.rtl {
+                piece.x = width - piece.x - piece.advance;
+            }
+            piece.x += offset;
+            if let Some(rect) = canvas.draw(&mut img, piece, line.height, options) {
+                blurred.push(rect);
+            }
+        }
+        top += advance;
+    }
+
+    for (cols, rows) in blurred {
+        blur(&mut img, cols, rows, scale / 4.);
+    }
+    Ok(img)
+}
+
+/// 将段落按样式切分, 返回(字节区间, 样式)
+fn split_spans(text: &str, range: Range<usize>, styles: &[Style]) -> Vec<(Range<usize>, Style)> {
+    let mut ret: Vec<(Range<usize>, Style)> = vec![];
+    for (i, c) in text[range.clone()].char_indices() {
+        let (start, end) = (range.start + i, range.start + i + c.len_utf8());
+        match ret.last_mut() {
+            Some((r, style)) if *style == styles[start] => r.end = end,
+            _ => ret.push((start..end, styles[start])),
+        }
+    }
+    ret
+}
+
+/// 一段同样式文本中每个字符的步进宽度
+fn advances(text: &str, style: Style, scale: f32, registry: &FontRegistry) -> Vec<f32> {
+    if style.custom_emoji {
+        return text
+            .graphemes(true)
+            .flat_map(|g| std::iter::once(scale).chain(g.chars().skip(1).map(|_| 0.)))
+            .collect();
+    }
+    VecGlyph::with_style(text, Scale::uniform(scale), registry, style.font_style())
+        .advances()
+        .collect()
+}
+
+fn pieces(text: &str, style: Style, scale: f32, registry: &FontRegistry) -> Vec<Piece> {
+    if style.custom_emoji {
+        return text
+            .graphemes(true)
+            .map(|_| Piece {
+                vg: None,
+                style,
+                x: 0.,
+                advance: scale,
+            })
+            .collect();
+    }
+    let vg = VecGlyph::with_style(text, Scale::uniform(scale), registry, style.font_style());
+    vec![Piece {
+        advance: vg.advance(),
+        vg: Some(vg),
+        style,
+        x: 0.,
+    }]
+}
+
+/// 一行的绘制位置
+struct Canvas {
+    top: f32,
+    left: f32,
+    baseline: f32,
+}
+
+impl Canvas {
+    /// 绘制一个片段及其装饰, 需要模糊时返回模糊的区域
+    fn draw(
+        &self,
+        img: &mut RgbaImage,
+        piece: Piece,
+        height: f32,
+        options: &RichOptions,
+    ) -> Option<(Range<f32>, Range<f32>)> {
+        let scale = options.scale;
+        let style = piece.style;
+        let cols = self.left + piece.x..self.left + piece.x + piece.advance;
+        let rows = self.top..self.top + height;
+        let color = match style.link {
+            true => options.link_color,
+            false => options.foreground,
+        };
+
+        if style.code && !style.pre {
+            fill(img, cols.clone(), rows.clone(), options.code_background);
+        }
+        match piece.vg {
+            Some(vg) => {
+                let (ox, oy) = vg.origin();
+                let x = (cols.start - ox).round() as i64;
+                let y = (self.baseline - oy).round() as i64;
+                vg.draw_rgba(color, |row, col, c| {
+                    let (col, row) = (x + col as i64, y + row as i64);
+                    if col >= 0
+                        && row >= 0
+                        && let Some(pixel) = img.get_pixel_mut_checked(col as u32, row as u32)
+                    {
+                        blend(pixel, c);
+                    }
+                });
+            }
+            None => {
+                // 自定义emoji没有可用的图片, 以半透明的方块占位
+                let size = (scale * 0.8).round();
+                let left = (cols.start + (piece.advance - size) / 2.).round();
+                let top = (self.baseline - size * 0.85).round();
+                let placeholder = with_coverage(options.link_color, 0.4);
+                fill(img, left..left + size, top..top + size, placeholder);
+            }
+        }
+
+        let thickness = (scale / 16.).round().max(1.);
+        if style.underline {
+            let y = (self.baseline + scale * 0.08).round();
+            fill(img, cols.clone(), y..y + thickness, color);
+        }
+        if style.strike {
+            let y = (self.baseline - scale * 0.28).round();
+            fill(img, cols.clone(), y..y + thickness, color);
+        }
+        match (style.spoiler, options.spoiler) {
+            (true, SpoilerMode::Blackout) => {
+                fill(img, cols, rows, options.foreground);
+                None
+            }
+            (true, SpoilerMode::Blur) => Some((cols, rows)),
+            (false, _) => None,
+        }
+    }
+}
+
+/// 以source-over填充矩形, 坐标四舍五入到整数像素
+fn fill(img: &mut RgbaImage, cols: Range<f32>, rows: Range<f32>, color: Rgba<u8>) {
+    let clamp = |x: f32, max: u32| (x.round().max(0.) as u32).min(max);
+    for y in clamp(rows.start, img.height())..clamp(rows.end, img.height()) {
+        for x in clamp(cols.start, img.width())..clamp(cols.end, img.width()) {
+            blend(img.get_pixel_mut(x, y), color);
+        }
+    }
+}
+
+/// 对矩形区域做高斯模糊
+fn blur(img: &mut RgbaImage, cols: Range<f32>, rows: Range<f32>, sigma: f32) {
+    let clamp = |x: f32, max: u32| (x.round().max(0.) as u32).min(max);
+    let (left, top) = (
+        clamp(cols.start, img.width()),
+        clamp(rows.start, img.height()),
+    );
+    let width = clamp(cols.end, img.width()).saturating_sub(left);
+    let height = clamp(rows.end, img.height()).saturating_sub(top);
+    if width == 0 || height == 0 {
+        return;
+    }
+    let region = image::imageops::crop_imm(img, left, top, width, height).to_image();
+    let region = image::imageops::blur(&region, sigma);
+    image::imageops::replace(img, &region, left as i64, top as i64);
+}
+
+/// UAX #14中的强制换行字符
+fn is_line_break(c: char) -> bool {
+    matches!(
+        c,
+        '\n' | '\r' | '\u{0B}' | '\u{0C}' | '\u{85}' | '\u{2028}' | '\u{2029}'
+    )
+}
+
+#[cfg(all(test, feature = "embedded-fonts"))]
+mod tests {
+    use super::*;
+    use grammers_tl_types::types;
+    use std::sync::LazyLock;
+
+    static REGISTRY: LazyLock<FontRegistry> = LazyLock::new(FontRegistry::embedded);
+
+    fn render(text: &str, entities: &[MessageEntity], options: &RichOptions) -> RgbaImage {
+        render_message_with(text, entities, options, &REGISTRY).unwrap()
+    }
+
+    /// 前景色像素的数量
+    fn ink(img: &RgbaImage) -> usize {
+        img.pixels().filter(|p| p.0[0] < 128).count()
+    }
+
+    #[test]
+    fn test_rich() {
+        let options = RichOptions::default();
+        let plain = render("hello", &[], &options);
+        let bold = MessageEntity::Bold(types::MessageEntityBold {
+            offset: 0,
+            length: 5,
+        });
+        assert!(ink(&render("hello", &[bold], &options)) > ink(&plain) * 5 / 4);
+        // 粗体、斜体和代码使用对应样式的字体, 而不是伪粗体或伪斜体
+        for (bold, italic, monospace) in [
+            (true, false, false),
+            (false, true, false),
+            (true, true, false),
+            (false, false, true),
+            (true, false, true),
+        ] {
+            let style = FontStyle {
+                bold,
+                italic,
+                monospace,
+            };
+            let font = REGISTRY.fonts()[REGISTRY.candidates('h', style)[0]];
+            assert_eq!(font.style(), style);
+        }
+
+        // 涂黑的剧透完全遮住文字
+        let spoiler = MessageEntity::Spoiler(types::MessageEntitySpoiler {
+            offset: 0,
+            length: 5,
+        });
+        let hidden = render("hello", std::slice::from_ref(&spoiler), &options);
+        assert_eq!(hidden.dimensions(), plain.dimensions());
+        let (w, h) = hidden.dimensions();
+        assert_eq!(*hidden.get_pixel(w / 2, h / 2), options.foreground);
+        let blurred = render(
+            "hello",
+            &[spoiler],
+            &RichOptions {
+                spoiler: SpoilerMode::Blur,
+                ..Default::default()
+            },
+        );
+        assert!(ink(&blurred) < ink(&plain) / 2);
+
+        // 引用块缩进并在左侧绘制竖线
+        let quote = MessageEntity::Blockquote(types::MessageEntityBlockquote {
+            collapsed: false,
+            offset: 0,
+            length: 5,
+        });
+        let quoted = render("hello", &[quote], &options);
+        assert!(quoted.width() > plain.width());
+        assert_eq!(*quoted.get_pixel(9, h / 2), options.link_color);
+
+        // CRLF与LF换行的结果相同, 不绘制回车符
+        let lf = render("hello\nworld", &[], &options);
+        assert!(lf.height() > plain.height());
+        assert_eq!(render("hello\r\nworld", &[], &options), lf);
+        assert_eq!(render("hello\rworld", &[], &options), lf);
+        render("", &[], &options);
+
+        // 越界的entity报错
+        let bad = MessageEntity::Code(types::MessageEntityCode {
+            offset: 3,
+            length: 5,
+        });
+        assert!(render_message_with("hello", &[bad], &options, &REGISTRY).is_err());
+    }
+}
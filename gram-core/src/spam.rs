@@ -0,0 +1,214 @@
+use anyhow::Result;
+use grammers_tl_types::enums::MessageEntity;
+use regex::Regex;
+use std::collections::HashSet;
+use wildcard::Wildcard;
+
+pub mod config;
+pub mod features;
+
+pub use config::{Feature, Rule, SpamConfig};
+pub use features::Features;
+
+/// 基于提取器的垃圾消息评分器
+pub struct SpamScorer {
+    config: SpamConfig,
+    allowed_usernames: HashSet<String>,
+    bad_domains: Vec<Wildcard<'static>>,
+    obfuscation: Vec<Regex>,
+}
+
+/// 评分结果
+#[derive(Debug, Clone)]
+pub struct SpamReport {
+    pub score: f64,
+    pub is_spam: bool,
+    pub fired: Vec<FiredRule>,
+    pub features: Features,
+}
+
+/// 已触发的规则
+#[derive(Debug, Clone, PartialEq)]
+pub struct FiredRule {
+    pub name: String,
+    pub feature: Feature,
+    /// 触发时的特征值
+    pub value: f64,
+    /// 该规则贡献的分数
+    pub score: f64,
+}
+
+impl SpamScorer {
+    pub fn new(config: SpamConfig) -> Result<Self> {
+        let allowed_usernames = config
+            .allowed_usernames
+            .iter()
+            .map(|x| x.trim_start_matches('@').to_lowercase())
+            .collect();
+        let bad_domains = config
+            .bad_domains
+            .iter()
+            .map(|x| Wildcard::from_owned(x.to_lowercase().into_bytes()))
+            .collect::<Result<_, _>>()?;
+        let obfuscation = config
+            .obfuscation_patterns
+            .iter()
+            .map(|x| Regex::new(x))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            config,
+            allowed_usernames,
+            bad_domains,
+            obfuscation,
+        })
+    }
+
+    pub fn config(&self) -> &SpamConfig {
+        &self.config
+    }
+
+    /// 输入消息文本和消息entities
+    /// 输出总分和触发的规则
+    pub fn score(&self, message: &str, entities: Option<&[MessageEntity]>) -> Result<SpamReport> {
+        let features = Features::extract(
+            message,
+            entities,
+            &self.allowed_usernames,
+            &self.bad_domains,
+            &self.obfuscation,
+            self.config.min_letters_for_caps,
+        )?;
+
+        let fired = self
+            .config
+            .rules
+            .iter()
+            .filter_map(|rule| {
+                let value = features.value(rule.feature);
+                if value <= 0. || value < rule.min {
+                    return None;
+                }
+                let score = if rule.per_unit {
+                    rule.weight * value
+                } else {
+                    rule.weight
+                };
+                Some(FiredRule {
+                    name: rule.name.clone(),
+                    feature: rule.feature,
+                    value,
+                    score,
+                })
+            })
+            .collect::<Vec<_>>();
+        let score = fired.iter().map(|x| x.score).sum::<f64>();
+
+        Ok(SpamReport {
+            score,
+            is_spam: score >= self.config.threshold,
+            fired,
+            features,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grammers_tl_types as tl;
+
+    fn fired(report: &SpamReport) -> Vec<&str> {
+        report.fired.iter().map(|x| x.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_clean() {
+        let scorer = SpamScorer::new(SpamConfig::default()).unwrap();
+        let report = scorer
+            .score("hello everyone, see you tomorrow", None)
+            .unwrap();
+        assert_eq!(report.score, 0.);
+        assert!(!report.is_spam);
+    }
+
+    #[test]
+    fn test_ad() {
+        let config = SpamConfig {
+            allowed_usernames: vec!["@MyGroup".to_owned()],
+            bad_domains: vec!["*.casino".to_owned()],
+            ..Default::default()
+        };
+        let scorer = SpamScorer::new(config).unwrap();
+        let text = "@bonus_bot @mygroup FREE MONEY NOW!!! 💰💰💰 join t.me/+AbCdEfGh12 \
+                    https://win.big.casino/x t . me/promo";
+        let entities = vec![
+            MessageEntity::Mention(tl::types::MessageEntityMention {
+                offset: 0,
+                length: 10,
+            }),
+            MessageEntity::Mention(tl::types::MessageEntityMention {
+                offset: 11,
+                length: 8,
+            }),
+        ];
+        let report = scorer.score(text, Some(&entities)).unwrap();
+        assert!(report.is_spam);
+        assert_eq!(
+            report.features.foreign_usernames,
+            HashSet::from(["bonus_bot".to_owned()])
+        );
+        assert_eq!(
+            fired(&report),
+            vec!["invite-links", "obfuscation", "bad-domains"]
+        );
+    }
+
+    #[test]
+    fn test_text_url_mismatch() {
+        let scorer = SpamScorer::new(SpamConfig::default()).unwrap();
+        let text = "official site: google.com and @durov";
+        let entities = vec![
+            MessageEntity::TextUrl(tl::types::MessageEntityTextUrl {
+                offset: 15,
+                length: 10,
+                url: "https://evil.example/login".to_owned(),
+            }),
+            MessageEntity::TextUrl(tl::types::MessageEntityTextUrl {
+                offset: 30,
+                length: 6,
+                url: "https://t.me/durov".to_owned(),
+            }),
+        ];
+        let report = scorer.score(text, Some(&entities)).unwrap();
+        assert_eq!(
+            report.features.hidden_url_mismatches,
+            vec![(
+                "google.com".to_owned(),
+                "https://evil.example/login".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_load_toml() {
+        let config = SpamConfig::from_toml(
+            r#"
+            threshold = 1.0
+            bad_domains = ["spam.example"]
+
+            [[rules]]
+            name = "caps"
+            feature = "caps_ratio"
+            min = 0.5
+            weight = 2.0
+            per_unit = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.min_letters_for_caps, 10);
+        let scorer = SpamScorer::new(config).unwrap();
+        let report = scorer.score("THIS IS ALL CAPS TEXT", None).unwrap();
+        assert_eq!(fired(&report), vec!["caps"]);
+        assert_eq!(report.score, 2.0);
+    }
+}
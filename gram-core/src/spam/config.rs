@@ -0,0 +1,125 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// 垃圾消息评分配置
+/// 可以从TOML或JSON加载, 便于管理员在不重新编译的情况下调整规则
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpamConfig {
+    /// 判定为垃圾消息的分数阈值
+    pub threshold: f64,
+    /// 本群自有的用户名, 不计入外部用户名, 不区分大小写
+    pub allowed_usernames: Vec<String>,
+    /// 已知的恶意域名, 支持`*`通配符, 如`*.casino`
+    pub bad_domains: Vec<String>,
+    /// 混淆检测使用的正则表达式
+    pub obfuscation_patterns: Vec<String>,
+    /// 大写比例仅在字母数不少于此值时计算, 避免短消息误判
+    pub min_letters_for_caps: usize,
+    /// 评分规则
+    pub rules: Vec<Rule>,
+}
+
+impl Default for SpamConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 5.0,
+            allowed_usernames: vec![],
+            bad_domains: vec![],
+            obfuscation_patterns: [
+                // t.me 的各种拆写: `t . me`、`t。me`、`t dot me`
+                r"(?i)\bt(?:\s+[.。·,，]\s*|[。·,，]\s*|\.\s+|\s+dot\s+)me\b",
+                // 被空格拆开的用户名: `@ user`
+                r"@\s+\w{4,}",
+                // 全角字母拼写的链接
+                r"[ｔＴ]\s*[．.。]\s*[ｍＭ][ｅＥ]",
+                // 零宽字符
+                r"[\u{200B}-\u{200D}\u{2060}\u{FEFF}]",
+            ]
+            .map(String::from)
+            .to_vec(),
+            min_letters_for_caps: 10,
+            rules: vec![
+                Rule::new("foreign-usernames", Feature::ForeignUsernames, 2.0, 1.5),
+                Rule::new("invite-links", Feature::Invites, 1.0, 2.0),
+                Rule::new(
+                    "hidden-url-mismatch",
+                    Feature::HiddenUrlMismatches,
+                    1.0,
+                    3.0,
+                ),
+                Rule::new("obfuscation", Feature::ObfuscationHits, 1.0, 2.5),
+                Rule::new("emoji-density", Feature::EmojiDensity, 0.3, 1.0),
+                Rule::new("all-caps", Feature::CapsRatio, 0.7, 1.0),
+                Rule::new("bad-domains", Feature::BadDomains, 1.0, 5.0),
+            ],
+        }
+    }
+}
+
+impl SpamConfig {
+    pub fn from_toml(s: &str) -> Result<Self> {
+        Ok(toml::from_str(s)?)
+    }
+
+    pub fn from_json(s: &str) -> Result<Self> {
+        Ok(serde_json::from_str(s)?)
+    }
+
+    /// 根据文件扩展名选择格式读取配置, 支持`.toml`和`.json`
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("toml") => Self::from_toml(&content),
+            Some("json") => Self::from_json(&content),
+            _ => Err(anyhow!("unsupported config format: {}", path.display())),
+        }
+    }
+}
+
+/// 单条评分规则
+/// 当特征值不小于`min`时触发, 分数累加`weight`
+/// 如`per_unit`为真, 则分数为`weight * 特征值`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    pub name: String,
+    pub feature: Feature,
+    pub min: f64,
+    pub weight: f64,
+    #[serde(default)]
+    pub per_unit: bool,
+}
+
+impl Rule {
+    pub fn new(name: &str, feature: Feature, min: f64, weight: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            feature,
+            min,
+            weight,
+            per_unit: false,
+        }
+    }
+}
+
+/// 规则可引用的消息特征
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// 不在白名单中的用户名数量
+    ForeignUsernames,
+    /// 邀请链接数量
+    Invites,
+    /// 显示文本与实际链接不符的TextUrl数量
+    HiddenUrlMismatches,
+    /// 混淆模式命中次数
+    ObfuscationHits,
+    /// emoji占非空白字符的比例
+    EmojiDensity,
+    /// 大写字母占字母的比例
+    CapsRatio,
+    /// 命中恶意域名的链接数量
+    BadDomains,
+}
@@ -0,0 +1,151 @@
+use super::config::Feature;
+use crate::extract::entity::extract_entity;
+use crate::extract::username::{deeplink, extract_usernames};
+use crate::unicode::{is_emoji, is_invisible};
+use anyhow::Result;
+use grammers_tl_types as tl;
+use regex::Regex;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+use tl::enums::MessageEntity;
+use url::Url;
+use wildcard::Wildcard;
+
+static URLS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(?:https?://)?(?:[a-z0-9-]+\.)+[a-z]{2,}(?:/\S*)?").unwrap()
+});
+
+/// 从消息中提取的评分特征
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Features {
+    /// 不在白名单中的用户名
+    pub foreign_usernames: HashSet<String>,
+    /// 邀请码
+    pub invites: HashSet<String>,
+    /// 显示文本与实际链接不符的TextUrl, 为(显示文本, 链接)
+    pub hidden_url_mismatches: Vec<(String, String)>,
+    pub obfuscation_hits: usize,
+    pub emoji_density: f64,
+    pub caps_ratio: f64,
+    /// 命中恶意域名的域名
+    pub bad_domains: Vec<String>,
+}
+
+impl Features {
+    pub fn extract(
+        message: &str,
+        entities: Option<&[MessageEntity]>,
+        allowed_usernames: &HashSet<String>,
+        bad_domains: &[Wildcard<'_>],
+        obfuscation: &[Regex],
+        min_letters_for_caps: usize,
+    ) -> Result<Self> {
+        let entities = entities.unwrap_or_default();
+
+        let (usernames, _) = extract_usernames(message, Some(entities.to_vec()))?;
+        let foreign_usernames = usernames
+            .into_iter()
+            .filter(|x| !allowed_usernames.contains(&x.to_lowercase()))
+            .collect();
+
+        let mut invites = deeplink::extract_invites(message);
+        let mut urls = URLS
+            .find_iter(message)
+            .map(|x| x.as_str().to_string())
+            .collect::<Vec<_>>();
+        let mut hidden_url_mismatches = vec![];
+        for ent in entities {
+            if let MessageEntity::TextUrl(tl::types::MessageEntityTextUrl { url, .. }) = ent {
+                invites.extend(deeplink::get_invite(url));
+                urls.push(url.clone());
+                let shown = extract_entity(message, ent)?.unwrap_or_default();
+                if is_text_url_mismatch(shown, url) {
+                    hidden_url_mismatches.push((shown.to_string(), url.clone()));
+                }
+            }
+        }
+
+        let obfuscation_hits = obfuscation
+            .iter()
+            .map(|re| re.find_iter(message).count())
+            .sum::<usize>()
+            + message.chars().filter(|&c| is_invisible(c)).count();
+
+        let visible = message
+            .chars()
+            .filter(|c| !c.is_whitespace() && !is_invisible(*c))
+            .count();
+        let emoji = message.chars().filter(|&c| is_emoji(c)).count();
+        let emoji_density = ratio(emoji, visible);
+
+        let cased = message
+            .chars()
+            .filter(|c| c.is_uppercase() || c.is_lowercase())
+            .count();
+        let upper = message.chars().filter(|c| c.is_uppercase()).count();
+        let caps_ratio = if cased >= min_letters_for_caps {
+            ratio(upper, cased)
+        } else {
+            0.
+        };
+
+        let bad_domains = urls
+            .iter()
+            .filter_map(|x| host(x))
+            .filter(|h| bad_domains.iter().any(|w| w.is_match(h.as_bytes())))
+            .collect();
+
+        Ok(Self {
+            foreign_usernames,
+            invites,
+            hidden_url_mismatches,
+            obfuscation_hits,
+            emoji_density,
+            caps_ratio,
+            bad_domains,
+        })
+    }
+
+    /// 获取规则引用的特征值
+    pub fn value(&self, feature: Feature) -> f64 {
+        match feature {
+            Feature::ForeignUsernames => self.foreign_usernames.len() as f64,
+            Feature::Invites => self.invites.len() as f64,
+            Feature::HiddenUrlMismatches => self.hidden_url_mismatches.len() as f64,
+            Feature::ObfuscationHits => self.obfuscation_hits as f64,
+            Feature::EmojiDensity => self.emoji_density,
+            Feature::CapsRatio => self.caps_ratio,
+            Feature::BadDomains => self.bad_domains.len() as f64,
+        }
+    }
+}
+
+fn ratio(a: usize, b: usize) -> f64 {
+    if b == 0 { 0. } else { a as f64 / b as f64 }
+}
+
+/// 提取链接的域名, 统一为小写
+fn host(link: &str) -> Option<String> {
+    let url = Url::parse(link)
+        .ok()
+        .filter(|x| x.has_host())
+        .or_else(|| Url::parse(&format!("http://{link}")).ok())?;
+    Some(url.host_str()?.trim_start_matches("www.").to_lowercase())
+}
+
+/// 判断TextUrl的显示文本是否在冒充另一个链接或用户名
+/// 显示文本为普通文字时不视为不符
+fn is_text_url_mismatch(shown: &str, url: &str) -> bool {
+    let shown = shown.trim();
+    if let Some(name) = shown.strip_prefix('@') {
+        return deeplink::get_username(url).is_none_or(|x| !x.eq_ignore_ascii_case(name));
+    }
+    if URLS.find(shown).is_none_or(|m| m.as_str() != shown) {
+        return false;
+    }
+    match (host(shown), host(url)) {
+        (Some(a), Some(b)) if a != b => true,
+        (Some(_), Some(_)) => deeplink::get_username(shown) != deeplink::get_username(url),
+        _ => false,
+    }
+}
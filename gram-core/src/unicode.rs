@@ -0,0 +1,35 @@
+/// 判断字符是否为emoji
+/// 仅覆盖常见emoji区块, 不包含数字、`#`等可组成keycap的基础字符
+pub fn is_emoji(c: char) -> bool {
+    matches!(c as u32,
+        0x1F000..=0x1FAFF // 麻将、扑克、带圈字母、表情、交通、补充符号等
+        | 0x2600..=0x27BF // 杂项符号、装饰符号
+        | 0x2B00..=0x2BFF // 箭头、星形等
+        | 0x2300..=0x23FF // 杂项技术符号(⌚、⏰等)
+        | 0x3030 | 0x303D | 0x3297 | 0x3299
+        | 0x00A9 | 0x00AE | 0x203C | 0x2049 | 0x2122 | 0x2139
+    )
+}
+
+/// 判断字符是否为emoji变体选择符(VS15/VS16)
+pub fn is_variation_selector(c: char) -> bool {
+    matches!(c, '\u{FE0E}' | '\u{FE0F}')
+}
+
+/// 判断字符是否为零宽或不可见的格式字符
+/// 此类字符常被用于混淆关键词
+pub fn is_invisible(c: char) -> bool {
+    matches!(c,
+        '\u{00AD}' // soft hyphen
+        | '\u{034F}' // combining grapheme joiner
+        | '\u{061C}' // arabic letter mark
+        | '\u{115F}' | '\u{1160}' | '\u{3164}' | '\u{FFA0}' // hangul filler
+        | '\u{180E}' // mongolian vowel separator
+        | '\u{200B}'..='\u{200F}' // zero width space/joiner, lrm/rlm
+        | '\u{202A}'..='\u{202E}' // bidi embedding/override
+        | '\u{2060}'..='\u{2064}' // word joiner, invisible operators
+        | '\u{2066}'..='\u{206F}' // bidi isolate, deprecated format
+        | '\u{FEFF}' // zero width no-break space
+        | '\u{E0000}'..='\u{E007F}' // tags
+    )
+}
@@ -0,0 +1,24 @@
+use gram_core::log::{LogBuilder, LogFormat, Rotation};
+
+/// 写入JSON格式的日志文件
+/// 单独的测试程序, 避免全局subscriber收集其他测试的日志
+#[test]
+fn test_log_file() {
+    let dir = std::env::temp_dir().join(format!("gram-log-{}", std::process::id()));
+    let builder = LogBuilder::new()
+        .with_env(false)
+        .directives("warn,gram_core::render=trace")
+        .format(LogFormat::Json)
+        .file(&dir, "test.log", Rotation::NEVER)
+        .max_files(2);
+    builder.clone().init().unwrap();
+    tracing::warn!(answer = 42, "hello");
+    let log = std::fs::read_to_string(dir.join("test.log")).unwrap();
+    let line = log.lines().find(|x| x.contains("hello")).unwrap();
+    let line = serde_json::from_str::<serde_json::Value>(line).unwrap();
+    assert_eq!(line["fields"]["message"], "hello");
+    assert_eq!(line["fields"]["answer"], 42);
+    // 全局subscriber已存在
+    assert!(builder.init().is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}
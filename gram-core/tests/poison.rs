@@ -0,0 +1,24 @@
+#![cfg(feature = "embedded-fonts")]
+
+use gram_core::render::layout::LayoutOptions;
+use gram_core::render::registry::FontRegistry;
+
+/// 其他线程持有字体注册表的锁时panic, 之后的渲染和加载不受影响
+/// 单独的测试程序, 避免中毒的锁影响其他测试
+#[test]
+fn poisoned_registry() {
+    let panicked = std::thread::spawn(|| {
+        let _registry = FontRegistry::global().write().unwrap();
+        panic!("poison the font registry");
+    })
+    .join();
+    assert!(panicked.is_err() && FontRegistry::global().is_poisoned());
+
+    let img = gram_core::render::render_text("hi", 32., &LayoutOptions::default());
+    assert!(img.width() > 0 && img.height() > 0);
+    assert!(!FontRegistry::global().is_poisoned());
+
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fonts/cbdt.ttf");
+    assert_eq!(FontRegistry::write_global().load_file(path).unwrap(), 1);
+    assert!(!FontRegistry::read_global().is_empty());
+}
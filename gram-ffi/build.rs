@@ -0,0 +1,17 @@
+use std::env;
+use std::path::PathBuf;
+
+/// 生成头文件到`OUT_DIR`, 不修改源码目录; 检入的`include/gram.h`由测试检查是否与之一致
+fn main() {
+    let dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    let config = cbindgen::Config::from_file(dir.join("cbindgen.toml")).unwrap();
+    cbindgen::Builder::new()
+        .with_crate(&dir)
+        .with_config(config)
+        .generate()
+        .expect("failed to generate gram.h")
+        .write_to_file(out.join("gram.h"));
+}
@@ -0,0 +1,151 @@
+#ifndef GRAM_H
+#define GRAM_H
+
+/* 由cbindgen根据src/lib.rs生成, 不要手动修改 */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// 返回值, 非`GRAM_STATUS_OK`时可通过`gram_last_error`获取错误信息
+typedef enum GramStatus {
+  GRAM_STATUS_OK = 0,
+  // 参数为NULL、不是UTF-8或包含NUL
+  GRAM_STATUS_INVALID_ARGUMENT = 1,
+  // entity的JSON无效
+  GRAM_STATUS_ENTITY_DECODE = 2,
+  // entity的偏移超出文本范围
+  GRAM_STATUS_OFFSET = 3,
+  // 不支持的entity类型
+  GRAM_STATUS_UNSUPPORTED_ENTITY = 4,
+  // 渲染参数无效或未加载字体
+  GRAM_STATUS_RENDER = 5,
+  // 内部错误, 不应出现
+  GRAM_STATUS_PANIC = 6,
+  // 其他错误, 如读取字体文件失败
+  GRAM_STATUS_OTHER = 7,
+} GramStatus;
+
+// 链接的类型
+typedef enum GramLinkKind {
+  // 公开用户名, 如`t.me/username`
+  GRAM_LINK_KIND_USERNAME = 0,
+  // 邀请链接, 如`t.me/+hash`
+  GRAM_LINK_KIND_INVITE = 1,
+} GramLinkKind;
+
+// 提取的用户名和用户ID, 均已去重并排序
+typedef struct GramUsernames {
+  // 不带@前缀的用户名
+  char **usernames;
+  size_t usernames_len;
+  int64_t *user_ids;
+  size_t user_ids_len;
+} GramUsernames;
+
+// 链接参数
+typedef struct GramParam {
+  char *key;
+  char *value;
+} GramParam;
+
+// 解析后的用户名链接或邀请链接
+typedef struct GramDeepLink {
+  enum GramLinkKind kind;
+  // 用户名, 邀请链接为NULL
+  char *username;
+  // 链接参数, 与`tg://`形式的参数名一致, 如`start`、`post`; 邀请码为`invite`
+  struct GramParam *params;
+  size_t params_len;
+  // 规范的`https://t.me/`链接
+  char *url;
+} GramDeepLink;
+
+// 渲染参数, 全部置零时为默认值
+typedef struct GramRenderOptions {
+  // 最大行宽(像素), 超出时自动换行; 不大于0时只在换行符处换行
+  float max_width;
+  // 行距倍数, 不大于0时为1
+  float line_spacing;
+  // `left`、`center`或`right`, NULL为`left`
+  const char *align;
+  // 前景色, 如`#000000`; 与`background`均为NULL时输出白底黑字的灰度图, 否则输出RGBA图片
+  const char *foreground;
+  // 背景色, 可为`transparent`
+  const char *background;
+} GramRenderOptions;
+
+// 本库分配的字节数组
+typedef struct GramBuffer {
+  uint8_t *data;
+  size_t len;
+} GramBuffer;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// 当前线程最近一次调用的错误信息, 最近一次调用成功时为NULL
+// 返回的字符串由本库持有, 在当前线程下一次调用本库函数前有效, 不要释放
+const char *gram_last_error(void);
+
+// 释放本库返回的字符串, `s`可为NULL
+// # Safety
+// `s`须为NULL或本库返回的字符串, 且只释放一次
+void gram_string_free(char *s);
+
+// 提取消息中的用户名和用户ID, `entities`可为NULL, 结果用`gram_usernames_free`释放
+// # Safety
+// `message`和`entities`须为NULL或以NUL结尾的字符串, `out`须可写
+enum GramStatus gram_extract_usernames(const char *message,
+                                       const char *entities,
+                                       struct GramUsernames **out);
+
+// 释放`gram_extract_usernames`的结果, `usernames`可为NULL
+// # Safety
+// `usernames`须为NULL或`gram_extract_usernames`的结果, 且只释放一次
+void gram_usernames_free(struct GramUsernames *usernames);
+
+// 解析`t.me`系列域名或`tg:`协议的链接, 结果用`gram_deeplink_free`释放
+// 不是用户名链接或邀请链接时返回`GRAM_STATUS_OK`, `out`为NULL
+// # Safety
+// `url`须为以NUL结尾的字符串, `out`须可写
+enum GramStatus gram_parse_deeplink(const char *url, struct GramDeepLink **out);
+
+// 释放`gram_parse_deeplink`的结果, `link`可为NULL
+// # Safety
+// `link`须为NULL或`gram_parse_deeplink`的结果, 且只释放一次
+void gram_deeplink_free(struct GramDeepLink *link);
+
+// 提取entity对应的文本, 结果用`gram_string_free`释放
+// entity没有文本时返回`GRAM_STATUS_OK`, `out`为NULL
+// # Safety
+// `message`和`entity`须为以NUL结尾的字符串, `out`须可写
+enum GramStatus gram_extract_entity(const char *message, const char *entity, char **out);
+
+// 渲染文本为PNG图片, `options`可为NULL, 结果用`gram_buffer_free`释放
+// # Safety
+// `text`须为以NUL结尾的字符串, `options`须为NULL或有效的指针, `out`须可写
+enum GramStatus gram_render_text(const char *text,
+                                 float scale,
+                                 const struct GramRenderOptions *options,
+                                 struct GramBuffer **out);
+
+// 释放本库返回的字节数组, `buffer`可为NULL
+// # Safety
+// `buffer`须为NULL或本库返回的字节数组, 且只释放一次
+void gram_buffer_free(struct GramBuffer *buffer);
+
+// 加载字体文件或目录到全局字体注册表, 加载的字体数量写入`count`, `count`可为NULL
+// # Safety
+// `path`须为以NUL结尾的字符串, `count`须为NULL或可写的指针
+enum GramStatus gram_load_fonts(const char *path,
+                                size_t *count);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* GRAM_H */
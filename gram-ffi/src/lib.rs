@@ -0,0 +1,456 @@
+//! gram的C ABI, 头文件`include/gram.h`由cbindgen生成并检入, 测试检查其与构建时生成的一致
+//!
+//! 约定:
+//! - 字符串参数为以NUL结尾的UTF-8字符串, entities参数为JSON, 支持的格式参见`EntityFields::from_value`
+//! - 函数返回`GramStatus`, 失败时通过`gram_last_error`获取错误信息
+//! - 输出参数`out`不可为NULL, 失败时置为NULL; 输出的对象由本库分配, 须调用对应的`gram_*_free`释放
+
+use anyhow::{Result, anyhow};
+use gram_core::error::Error;
+use gram_core::extract::username::deeplink;
+use gram_core::format::{deserialize_entities, deserialize_entity};
+use gram_core::render::color::parse_color;
+use gram_core::render::layout::{Align, LayoutOptions};
+use gram_core::render::registry::FontRegistry;
+use image::{DynamicImage, ImageFormat};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::fmt;
+use std::io::Cursor;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::path::Path;
+use std::ptr;
+
+/// 返回值, 非`GRAM_STATUS_OK`时可通过`gram_last_error`获取错误信息
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GramStatus {
+    Ok = 0,
+    /// 参数为NULL、不是UTF-8或包含NUL
+    InvalidArgument = 1,
+    /// entity的JSON无效
+    EntityDecode = 2,
+    /// entity的偏移超出文本范围
+    Offset = 3,
+    /// 不支持的entity类型
+    UnsupportedEntity = 4,
+    /// 渲染参数无效或未加载字体
+    Render = 5,
+    /// 内部错误, 不应出现
+    Panic = 6,
+    /// 其他错误, 如读取字体文件失败
+    Other = 7,
+}
+
+/// 参数不符合约定
+#[derive(Debug)]
+struct InvalidArgument(String);
+
+impl fmt::Display for InvalidArgument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for InvalidArgument {}
+
+fn invalid(message: impl Into<String>) -> anyhow::Error {
+    InvalidArgument(message.into()).into()
+}
+
+fn status_of(e: &anyhow::Error) -> GramStatus {
+    if e.downcast_ref::<InvalidArgument>().is_some() {
+        return GramStatus::InvalidArgument;
+    }
+    match e.downcast_ref::<Error>() {
+        Some(Error::EntityDecode { .. }) => GramStatus::EntityDecode,
+        Some(Error::Offset { .. }) => GramStatus::Offset,
+        Some(Error::UnsupportedEntity { .. }) => GramStatus::UnsupportedEntity,
+        Some(Error::Render(_)) => GramStatus::Render,
+        None => GramStatus::Other,
+    }
+}
+
+thread_local! {
+    /// 当前线程最近一次调用的错误信息
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// 执行`job`并记录错误, panic不会越过FFI边界
+fn call(job: impl FnOnce() -> Result<()>) -> GramStatus {
+    let (status, message) = match catch_unwind(AssertUnwindSafe(job)) {
+        Ok(Ok(())) => (GramStatus::Ok, None),
+        Ok(Err(e)) => (status_of(&e), Some(format!("{e:#}"))),
+        Err(panic) => {
+            let message = match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+                (Some(s), _) => s.to_string(),
+                (_, Some(s)) => s.clone(),
+                _ => "panic".to_string(),
+            };
+            (GramStatus::Panic, Some(message))
+        }
+    };
+    let message = message.map(|x| CString::new(x.replace('\0', "\\0")).unwrap());
+    LAST_ERROR.with_borrow_mut(|x| *x = message);
+    status
+}
+
+/// 当前线程最近一次调用的错误信息, 最近一次调用成功时为NULL
+/// 返回的字符串由本库持有, 在当前线程下一次调用本库函数前有效, 不要释放
+#[unsafe(no_mangle)]
+pub extern "C" fn gram_last_error() -> *const c_char {
+    LAST_ERROR.with_borrow(|x| x.as_ref().map_or(ptr::null(), |x| x.as_ptr()))
+}
+
+/// # Safety
+/// `s`须为NULL或以NUL结尾的字符串
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Result<Option<&'a str>> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    let s = unsafe { CStr::from_ptr(s) };
+    s.to_str()
+        .map(Some)
+        .map_err(|_| invalid(format!("{name} is not valid UTF-8")))
+}
+
+/// # Safety
+/// 同`str_arg`
+unsafe fn required_str_arg<'a>(s: *const c_char, name: &str) -> Result<&'a str> {
+    unsafe { str_arg(s, name) }?.ok_or_else(|| invalid(format!("{name} is null")))
+}
+
+/// 检查输出参数并先置为NULL
+/// # Safety
+/// `out`须为NULL或可写的指针
+unsafe fn out_arg<'a, T>(out: *mut *mut T) -> Result<&'a mut *mut T> {
+    let out = unsafe { out.as_mut() }.ok_or_else(|| invalid("out is null"))?;
+    *out = ptr::null_mut();
+    Ok(out)
+}
+
+fn c_string(s: impl Into<Vec<u8>>) -> Result<CString> {
+    CString::new(s).map_err(|_| invalid("output contains NUL"))
+}
+
+/// 转移所有权, 用`free_slice`释放
+fn into_raw_slice<T>(v: Vec<T>) -> (*mut T, usize) {
+    let len = v.len();
+    (Box::into_raw(v.into_boxed_slice()).cast(), len)
+}
+
+/// # Safety
+/// `data`和`len`须来自`into_raw_slice`
+unsafe fn free_slice<T>(data: *mut T, len: usize) -> Box<[T]> {
+    unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)) }
+}
+
+/// # Safety
+/// `s`须为NULL或来自`CString::into_raw`
+unsafe fn free_c_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// 释放本库返回的字符串, `s`可为NULL
+/// # Safety
+/// `s`须为NULL或本库返回的字符串, 且只释放一次
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gram_string_free(s: *mut c_char) {
+    unsafe { free_c_string(s) }
+}
+
+/// 提取的用户名和用户ID, 均已去重并排序
+#[repr(C)]
+pub struct GramUsernames {
+    /// 不带@前缀的用户名
+    pub usernames: *mut *mut c_char,
+    pub usernames_len: usize,
+    pub user_ids: *mut i64,
+    pub user_ids_len: usize,
+}
+
+/// 提取消息中的用户名和用户ID, `entities`可为NULL, 结果用`gram_usernames_free`释放
+/// # Safety
+/// `message`和`entities`须为NULL或以NUL结尾的字符串, `out`须可写
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gram_extract_usernames(
+    message: *const c_char,
+    entities: *const c_char,
+    out: *mut *mut GramUsernames,
+) -> GramStatus {
+    call(|| {
+        let out = unsafe { out_arg(out) }?;
+        let message = unsafe { required_str_arg(message, "message") }?;
+        let entities = unsafe { str_arg(entities, "entities") }?
+            .map(deserialize_entities)
+            .transpose()?;
+        let (usernames, user_ids) =
+            gram_core::extract::username::extract_usernames(message, entities)?;
+        let mut usernames = usernames.into_iter().collect::<Vec<_>>();
+        let mut user_ids = user_ids.into_iter().collect::<Vec<_>>();
+        usernames.sort();
+        user_ids.sort();
+        // 先构造全部字符串, 避免出错时泄漏
+        let usernames = usernames
+            .into_iter()
+            .map(c_string)
+            .collect::<Result<Vec<_>>>()?;
+        let usernames = usernames.into_iter().map(CString::into_raw).collect();
+        let (usernames, usernames_len) = into_raw_slice(usernames);
+        let (user_ids, user_ids_len) = into_raw_slice(user_ids);
+        *out = Box::into_raw(Box::new(GramUsernames {
+            usernames,
+            usernames_len,
+            user_ids,
+            user_ids_len,
+        }));
+        Ok(())
+    })
+}
+
+/// 释放`gram_extract_usernames`的结果, `usernames`可为NULL
+/// # Safety
+/// `usernames`须为NULL或`gram_extract_usernames`的结果, 且只释放一次
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gram_usernames_free(usernames: *mut GramUsernames) {
+    if usernames.is_null() {
+        return;
+    }
+    let usernames = unsafe { Box::from_raw(usernames) };
+    for s in unsafe { free_slice(usernames.usernames, usernames.usernames_len) } {
+        unsafe { free_c_string(s) };
+    }
+    drop(unsafe { free_slice(usernames.user_ids, usernames.user_ids_len) });
+}
+
+/// 链接的类型
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GramLinkKind {
+    /// 公开用户名, 如`t.me/username`
+    Username = 0,
+    /// 邀请链接, 如`t.me/+hash`
+    Invite = 1,
+}
+
+/// 链接参数
+#[repr(C)]
+pub struct GramParam {
+    pub key: *mut c_char,
+    pub value: *mut c_char,
+}
+
+/// 解析后的用户名链接或邀请链接
+#[repr(C)]
+pub struct GramDeepLink {
+    pub kind: GramLinkKind,
+    /// 用户名, 邀请链接为NULL
+    pub username: *mut c_char,
+    /// 链接参数, 与`tg://`形式的参数名一致, 如`start`、`post`; 邀请码为`invite`
+    pub params: *mut GramParam,
+    pub params_len: usize,
+    /// 规范的`https://t.me/`链接
+    pub url: *mut c_char,
+}
+
+/// 解析`t.me`系列域名或`tg:`协议的链接, 结果用`gram_deeplink_free`释放
+/// 不是用户名链接或邀请链接时返回`GRAM_STATUS_OK`, `out`为NULL
+/// # Safety
+/// `url`须为以NUL结尾的字符串, `out`须可写
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gram_parse_deeplink(
+    url: *const c_char,
+    out: *mut *mut GramDeepLink,
+) -> GramStatus {
+    call(|| {
+        let out = unsafe { out_arg(out) }?;
+        let url = unsafe { required_str_arg(url, "url") }?;
+        let Some(link) = deeplink::DeepLink::parse(url) else {
+            return Ok(());
+        };
+        let kind = match link.kind {
+            deeplink::LinkKind::Username => GramLinkKind::Username,
+            deeplink::LinkKind::Invite => GramLinkKind::Invite,
+        };
+        let url = c_string(link.to_url())?;
+        let username = link.username.map(c_string).transpose()?;
+        let params = link
+            .params
+            .into_iter()
+            .map(|(key, value)| Ok((c_string(key)?, c_string(value)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let params = params
+            .into_iter()
+            .map(|(key, value)| GramParam {
+                key: key.into_raw(),
+                value: value.into_raw(),
+            })
+            .collect();
+        let (params, params_len) = into_raw_slice(params);
+        *out = Box::into_raw(Box::new(GramDeepLink {
+            kind,
+            username: username.map_or(ptr::null_mut(), CString::into_raw),
+            params,
+            params_len,
+            url: url.into_raw(),
+        }));
+        Ok(())
+    })
+}
+
+/// 释放`gram_parse_deeplink`的结果, `link`可为NULL
+/// # Safety
+/// `link`须为NULL或`gram_parse_deeplink`的结果, 且只释放一次
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gram_deeplink_free(link: *mut GramDeepLink) {
+    if link.is_null() {
+        return;
+    }
+    let link = unsafe { Box::from_raw(link) };
+    for param in unsafe { free_slice(link.params, link.params_len) } {
+        unsafe {
+            free_c_string(param.key);
+            free_c_string(param.value);
+        }
+    }
+    unsafe {
+        free_c_string(link.username);
+        free_c_string(link.url);
+    }
+}
+
+/// 提取entity对应的文本, 结果用`gram_string_free`释放
+/// entity没有文本时返回`GRAM_STATUS_OK`, `out`为NULL
+/// # Safety
+/// `message`和`entity`须为以NUL结尾的字符串, `out`须可写
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gram_extract_entity(
+    message: *const c_char,
+    entity: *const c_char,
+    out: *mut *mut c_char,
+) -> GramStatus {
+    call(|| {
+        let out = unsafe { out_arg(out) }?;
+        let message = unsafe { required_str_arg(message, "message") }?;
+        let entity = deserialize_entity(unsafe { required_str_arg(entity, "entity") }?)?;
+        if let Some(text) = gram_core::extract::entity::extract_entity(message, &entity)? {
+            *out = c_string(text)?.into_raw();
+        }
+        Ok(())
+    })
+}
+
+/// 渲染参数, 全部置零时为默认值
+#[repr(C)]
+pub struct GramRenderOptions {
+    /// 最大行宽(像素), 超出时自动换行; 不大于0时只在换行符处换行
+    pub max_width: f32,
+    /// 行距倍数, 不大于0时为1
+    pub line_spacing: f32,
+    /// `left`、`center`或`right`, NULL为`left`
+    pub align: *const c_char,
+    /// 前景色, 如`#000000`; 与`background`均为NULL时输出白底黑字的灰度图, 否则输出RGBA图片
+    pub foreground: *const c_char,
+    /// 背景色, 可为`transparent`
+    pub background: *const c_char,
+}
+
+/// 本库分配的字节数组
+#[repr(C)]
+pub struct GramBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+/// 渲染文本为PNG图片, `options`可为NULL, 结果用`gram_buffer_free`释放
+/// # Safety
+/// `text`须为以NUL结尾的字符串, `options`须为NULL或有效的指针, `out`须可写
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gram_render_text(
+    text: *const c_char,
+    scale: f32,
+    options: *const GramRenderOptions,
+    out: *mut *mut GramBuffer,
+) -> GramStatus {
+    call(|| {
+        let out = unsafe { out_arg(out) }?;
+        let text = unsafe { required_str_arg(text, "text") }?;
+        let options = unsafe { options.as_ref() };
+        let (mut layout, mut colors) = (LayoutOptions::default(), (None, None));
+        if let Some(options) = options {
+            if options.max_width > 0. {
+                layout.max_width = Some(options.max_width);
+            }
+            if options.line_spacing > 0. {
+                layout.line_spacing = options.line_spacing;
+            }
+            if let Some(align) = unsafe { str_arg(options.align, "align") }? {
+                layout.align = align.parse::<Align>()?;
+            }
+            colors = unsafe {
+                (
+                    str_arg(options.foreground, "foreground")?,
+                    str_arg(options.background, "background")?,
+                )
+            };
+        }
+        if scale.is_nan() || scale <= 0. {
+            return Err(invalid("scale must be positive"));
+        }
+        if FontRegistry::read_global().is_empty() {
+            return Err(Error::Render("no fonts loaded, call gram_load_fonts first".into()).into());
+        }
+        let img: DynamicImage = match colors {
+            (None, None) => gram_core::render::render_text(text, scale, &layout).into(),
+            (foreground, background) => gram_core::render::render_text_rgba(
+                text,
+                scale,
+                &layout,
+                parse_color(foreground.unwrap_or("black"))?,
+                parse_color(background.unwrap_or("white"))?,
+            )
+            .into(),
+        };
+        let mut png = Vec::new();
+        img.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .map_err(|e| Error::Render(e.to_string()))?;
+        let (data, len) = into_raw_slice(png);
+        *out = Box::into_raw(Box::new(GramBuffer { data, len }));
+        Ok(())
+    })
+}
+
+/// 释放本库返回的字节数组, `buffer`可为NULL
+/// # Safety
+/// `buffer`须为NULL或本库返回的字节数组, 且只释放一次
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gram_buffer_free(buffer: *mut GramBuffer) {
+    if buffer.is_null() {
+        return;
+    }
+    let buffer = unsafe { Box::from_raw(buffer) };
+    drop(unsafe { free_slice(buffer.data, buffer.len) });
+}
+
+/// 加载字体文件或目录到全局字体注册表, 加载的字体数量写入`count`, `count`可为NULL
+/// # Safety
+/// `path`须为以NUL结尾的字符串, `count`须为NULL或可写的指针
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gram_load_fonts(path: *const c_char, count: *mut usize) -> GramStatus {
+    call(|| {
+        let path = Path::new(unsafe { required_str_arg(path, "path") }?);
+        let mut registry = FontRegistry::write_global();
+        let ret = if path.is_dir() {
+            registry.load_dir(path)
+        } else {
+            registry.load_file(path)
+        }
+        .map_err(|e| anyhow!("failed to load fonts from {}: {e:#}", path.display()))?;
+        if let Some(count) = unsafe { count.as_mut() } {
+            *count = ret;
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,43 @@
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// 用C编译器编译`harness.c`, 链接本crate的动态库并运行
+#[test]
+fn c_harness() {
+    let manifest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // 测试程序位于`target/<profile>/deps`, 动态库位于`target/<profile>`
+    let exe = env::current_exe().unwrap();
+    let lib_dir = exe.parent().unwrap().parent().unwrap();
+    // `cargo test`只构建rlib, 需单独构建动态库
+    let mut cargo = Command::new(env!("CARGO"));
+    cargo.args(["build", "--lib", "-p", "gram-ffi"]);
+    if !cfg!(debug_assertions) {
+        cargo.arg("--release");
+    }
+    assert!(
+        cargo.status().unwrap().success(),
+        "failed to build gram-ffi"
+    );
+    let out = env::temp_dir().join(format!("gram-ffi-harness-{}", std::process::id()));
+
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(cc)
+        .args(["-std=c99", "-Wall", "-Werror"])
+        .arg("-I")
+        .arg(manifest.join("include"))
+        .arg(manifest.join("tests/harness.c"))
+        // 动态库自带依赖的系统库, 静态库则需按平台传入`native-static-libs`
+        .arg(format!("-L{}", lib_dir.display()))
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-lgram_ffi")
+        .arg("-o")
+        .arg(&out)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success(), "failed to compile harness.c");
+
+    let status = Command::new(&out).status().unwrap();
+    std::fs::remove_file(&out).unwrap();
+    assert!(status.success(), "harness failed");
+}
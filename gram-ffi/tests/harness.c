@@ -0,0 +1,134 @@
+/* gram-ffi的C测试, 由tests/c_harness.rs编译并链接动态库运行 */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "gram.h"
+
+static int failures = 0;
+
+#define CHECK(cond)                                                          \
+    do {                                                                     \
+        if (!(cond)) {                                                       \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, \
+                    #cond);                                                  \
+            failures++;                                                      \
+        }                                                                    \
+    } while (0)
+
+static void test_extract_usernames(void) {
+    const char *message = "hi @alice and @carol, see t.me/durov";
+    const char *entities =
+        "[{\"type\": \"mention\", \"offset\": 3, \"length\": 6},"
+        " {\"type\": \"text_mention\", \"offset\": 14, \"length\": 6,"
+        " \"user\": {\"id\": 42}}]";
+    GramUsernames *out = NULL;
+    CHECK(gram_extract_usernames(message, entities, &out) == GRAM_STATUS_OK);
+    CHECK(gram_last_error() == NULL);
+    CHECK(out != NULL);
+    if (out != NULL) {
+        CHECK(out->usernames_len == 3);
+        CHECK(strcmp(out->usernames[0], "alice") == 0);
+        CHECK(strcmp(out->usernames[1], "carol") == 0);
+        CHECK(strcmp(out->usernames[2], "durov") == 0);
+        CHECK(out->user_ids_len == 1);
+        CHECK(out->user_ids[0] == 42);
+    }
+    gram_usernames_free(out);
+
+    /* 不传entities时只提取链接中的用户名 */
+    CHECK(gram_extract_usernames(message, NULL, &out) == GRAM_STATUS_OK);
+    CHECK(out != NULL && out->usernames_len == 1 && out->user_ids_len == 0);
+    gram_usernames_free(out);
+
+    CHECK(gram_extract_usernames(message, "[{\"type\": 1}]", &out) ==
+          GRAM_STATUS_ENTITY_DECODE);
+    CHECK(out == NULL);
+    CHECK(gram_last_error() != NULL);
+    CHECK(gram_extract_usernames(NULL, NULL, &out) ==
+          GRAM_STATUS_INVALID_ARGUMENT);
+    CHECK(strstr(gram_last_error(), "message") != NULL);
+    CHECK(gram_extract_usernames(message, NULL, NULL) ==
+          GRAM_STATUS_INVALID_ARGUMENT);
+}
+
+static void test_parse_deeplink(void) {
+    GramDeepLink *link = NULL;
+    CHECK(gram_parse_deeplink("tg://resolve?domain=durov&start=abc", &link) ==
+          GRAM_STATUS_OK);
+    CHECK(link != NULL);
+    if (link != NULL) {
+        CHECK(link->kind == GRAM_LINK_KIND_USERNAME);
+        CHECK(strcmp(link->username, "durov") == 0);
+        CHECK(link->params_len == 1);
+        CHECK(strcmp(link->params[0].key, "start") == 0);
+        CHECK(strcmp(link->params[0].value, "abc") == 0);
+        CHECK(strcmp(link->url, "https://t.me/durov?start=abc") == 0);
+    }
+    gram_deeplink_free(link);
+
+    CHECK(gram_parse_deeplink("https://t.me/+AbCdEf", &link) == GRAM_STATUS_OK);
+    CHECK(link != NULL && link->kind == GRAM_LINK_KIND_INVITE &&
+          link->username == NULL);
+    gram_deeplink_free(link);
+
+    /* 不是链接时成功且输出为NULL */
+    CHECK(gram_parse_deeplink("https://example.com", &link) == GRAM_STATUS_OK);
+    CHECK(link == NULL);
+    CHECK(gram_parse_deeplink("\xff", &link) == GRAM_STATUS_INVALID_ARGUMENT);
+}
+
+static void test_extract_entity(void) {
+    char *text = NULL;
+    /* offset和length为UTF-16单位 */
+    CHECK(gram_extract_entity(
+              "\xf0\x9f\x98\x80 @alice",
+              "{\"_\": \"messageEntityMention\", \"offset\": 3, \"length\": 6}",
+              &text) == GRAM_STATUS_OK);
+    CHECK(text != NULL && strcmp(text, "@alice") == 0);
+    gram_string_free(text);
+
+    CHECK(gram_extract_entity(
+              "hi", "{\"type\": \"bold\", \"offset\": 1, \"length\": 5}",
+              &text) == GRAM_STATUS_OFFSET);
+    CHECK(text == NULL);
+    gram_string_free(NULL);
+}
+
+static void test_render_text(void) {
+    static const unsigned char png[] = {0x89, 'P', 'N', 'G'};
+    GramBuffer *buffer = NULL;
+    CHECK(gram_render_text("hello", 32, NULL, &buffer) == GRAM_STATUS_OK);
+    CHECK(buffer != NULL && buffer->len > sizeof(png) &&
+          memcmp(buffer->data, png, sizeof(png)) == 0);
+    gram_buffer_free(buffer);
+
+    GramRenderOptions options;
+    memset(&options, 0, sizeof(options));
+    options.max_width = 100;
+    options.align = "center";
+    options.foreground = "#ff0000";
+    options.background = "transparent";
+    CHECK(gram_render_text("hello world", 32, &options, &buffer) ==
+          GRAM_STATUS_OK);
+    CHECK(buffer != NULL && buffer->len > sizeof(png));
+    gram_buffer_free(buffer);
+
+    options.align = "middle";
+    CHECK(gram_render_text("hello", 32, &options, &buffer) ==
+          GRAM_STATUS_RENDER);
+    CHECK(buffer == NULL);
+    CHECK(strstr(gram_last_error(), "middle") != NULL);
+}
+
+int main(void) {
+    test_extract_usernames();
+    test_parse_deeplink();
+    test_extract_entity();
+    test_render_text();
+    if (failures != 0) {
+        fprintf(stderr, "%d check(s) failed\n", failures);
+        return 1;
+    }
+    return 0;
+}
@@ -0,0 +1,10 @@
+/// 检入的`include/gram.h`须与构建时由cbindgen生成的头文件一致, 修改导出的接口后需同步更新
+#[test]
+fn header_up_to_date() {
+    let generated = concat!(env!("OUT_DIR"), "/gram.h");
+    let checked_in = concat!(env!("CARGO_MANIFEST_DIR"), "/include/gram.h");
+    assert!(
+        std::fs::read_to_string(generated).unwrap() == std::fs::read_to_string(checked_in).unwrap(),
+        "include/gram.h is out of date, run `cp {generated} {checked_in}`"
+    );
+}
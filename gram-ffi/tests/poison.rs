@@ -0,0 +1,31 @@
+use gram_core::render::registry::FontRegistry;
+use gram_ffi::{GramStatus, gram_buffer_free, gram_load_fonts, gram_render_text};
+use std::ptr;
+
+/// 其他线程持有字体注册表的锁时panic, 之后的调用不受影响
+/// 单独的测试程序, 避免中毒的锁影响其他测试
+#[test]
+fn poisoned_registry() {
+    let panicked = std::thread::spawn(|| {
+        let _registry = FontRegistry::global().write().unwrap();
+        panic!("poison the font registry");
+    })
+    .join();
+    assert!(panicked.is_err() && FontRegistry::global().is_poisoned());
+
+    let mut buffer = ptr::null_mut();
+    let status = unsafe { gram_render_text(c"hi".as_ptr(), 32., ptr::null(), &mut buffer) };
+    assert_eq!(status, GramStatus::Ok);
+    assert!(!buffer.is_null());
+    unsafe { gram_buffer_free(buffer) };
+
+    let path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../gram-core/tests/fonts/cbdt.ttf\0"
+    );
+    let mut count = 0;
+    let status = unsafe { gram_load_fonts(path.as_ptr().cast(), &mut count) };
+    assert_eq!(status, GramStatus::Ok);
+    assert_eq!(count, 1);
+    assert!(!FontRegistry::global().is_poisoned());
+}
@@ -0,0 +1,79 @@
+// 需先执行`npm run build:debug`
+import assert from 'node:assert/strict'
+import { createRequire } from 'node:module'
+import test from 'node:test'
+
+const gram = createRequire(import.meta.url)('../index.js')
+
+test('extract username', () => {
+  const message = 'hi @alice and @carol, see t.me/durov'
+  // GramJS的entity, userId为BigInteger(序列化为字符串)或原生BigInt
+  const entities = [
+    { className: 'MessageEntityMention', offset: 3, length: 6 },
+    { className: 'MessageEntityMentionName', offset: 14, length: 6, userId: 42n },
+  ]
+  assert.deepEqual(gram.extractUsername(message, entities), {
+    usernames: ['alice', 'carol', 'durov'],
+    userIds: [42],
+  })
+  // Bot API的entity
+  const botapi = [{ type: 'text_mention', offset: 14, length: 6, user: { id: 42 } }]
+  assert.deepEqual(gram.extractUsername(message, botapi).userIds, [42])
+  assert.deepEqual(gram.extractUsername(message).usernames, ['durov'])
+  assert.equal(gram.extractUsernameUrl('tg://resolve?domain=durov'), 'durov')
+  assert.equal(gram.extractUsernameUrl('https://example.com'), null)
+})
+
+test('extract entities', () => {
+  const message = 'hi 😀 @alice'
+  const entity = { type: 'mention', offset: 6, length: 6 }
+  assert.equal(gram.extractEntity(message, entity), '@alice')
+  assert.equal(gram.extractEntity(message, JSON.stringify(entity)), '@alice')
+  const [mention, link] = gram.extractEntities(message, [
+    entity,
+    { type: 'text_link', offset: 0, length: 2, url: 'https://t.me/durov' },
+  ])
+  assert.equal(mention.kind, 'mention')
+  assert.equal(mention.text, '@alice')
+  assert.equal(link.kind, 'text_url')
+  assert.equal(link.url, 'https://t.me/durov')
+  assert.throws(() => gram.extractEntity(message, { type: 'bold', offset: 10, length: 5 }))
+  assert.throws(() => gram.extractEntity(message, { type: 'foo', offset: 0, length: 1 }))
+
+  const found = gram.extract(message, [entity])
+  assert.deepEqual(found, [
+    { kind: 'username', value: 'alice', source: 'mention', offset: 6, length: 6 },
+  ])
+})
+
+test('deep links', () => {
+  const link = gram.parseDeeplink('https://t.me/durov/12?start=x')
+  assert.deepEqual(link, {
+    kind: 'username',
+    username: 'durov',
+    params: { post: '12', start: 'x' },
+    url: 'https://t.me/durov/12?start=x',
+  })
+  assert.equal(gram.parseDeeplink('https://example.com'), null)
+  const links = gram.extractDeeplinks('join t.me/+AbCdEfGhIjKlMnOp or tg://resolve?domain=foo_bar')
+  assert.deepEqual(
+    links.map((x) => [x.kind, x.username ?? x.params.invite]),
+    [
+      ['invite', 'AbCdEfGhIjKlMnOp'],
+      ['username', 'foo_bar'],
+    ],
+  )
+})
+
+test('render text', () => {
+  const png = gram.renderText('hello', 32)
+  assert.ok(Buffer.isBuffer(png))
+  assert.deepEqual([...png.subarray(1, 4)], [...Buffer.from('PNG')])
+  const rgba = gram.renderText('hello\nworld', 32, {
+    mode: 'rgba',
+    align: 'center',
+    background: 'transparent',
+  })
+  assert.ok(rgba.length > 0)
+  assert.throws(() => gram.renderText('x', 32, { mode: 'cmyk' }), /unknown mode/)
+})
@@ -0,0 +1,103 @@
+/**
+ * 消息实体, 可为:
+ * - telethon格式或GramJS `toJSON()`的JSON字符串
+ * - GramJS的`Api.MessageEntity*`对象(按`className`识别类型, `userId`、`documentId`可为`BigInteger`)
+ * - Bot API的entity, 如`{ type: "text_link", offset: 0, length: 4, url: "..." }`
+ */
+export type EntityLike = string | object
+
+/** 消息实体列表, 为JSON字符串或`EntityLike`中对象的数组 */
+export type EntitiesLike = string | object[]
+
+/** 消息实体, 类型名为TL类型名的蛇形命名, 如`text_url`、`mention_name` */
+export interface Entity {
+  kind: string
+  /** UTF-16偏移 */
+  offset: number
+  /** UTF-16长度 */
+  length: number
+  /** entity对应的文本 */
+  text?: string
+  url?: string
+  userId?: number
+  language?: string
+  /** 与Bot API一致, 为字符串 */
+  customEmojiId?: string
+  collapsed: boolean
+}
+
+export interface Usernames {
+  /** 不带@前缀的用户名 */
+  usernames: string[]
+  userIds: number[]
+}
+
+/** 解析后的用户名链接或邀请链接 */
+export interface DeepLink {
+  kind: 'username' | 'invite'
+  /** 用户名, 邀请链接没有该字段 */
+  username?: string
+  /** 链接参数, 与`tg://`形式的参数名一致, 如`start`、`post`; 邀请码为`invite` */
+  params: Record<string, string>
+  /** 规范的`https://t.me/`链接 */
+  url: string
+}
+
+/** 带来源的提取结果 */
+export interface Extraction {
+  kind: 'username' | 'user_id' | 'invite'
+  /** 用户名(不带@)、用户ID或邀请码 */
+  value: string
+  source: 'text' | 'mention' | 'mention_name' | 'text_url'
+  /** 来源在消息中的UTF-16偏移 */
+  offset: number
+  /** 来源在消息中的UTF-16长度 */
+  length: number
+  /** 来源为链接时的原始链接 */
+  link?: string
+}
+
+export interface RenderOptions {
+  /** 最大行宽(像素), 超出时按UAX #14规则自动换行; 不指定时只在换行符处换行 */
+  maxWidth?: number
+  /** 对齐方式, 默认为`left` */
+  align?: 'left' | 'center' | 'right'
+  /** 行距倍数, 默认为1 */
+  lineSpacing?: number
+  /** `L`为白底黑字的灰度图, `rgba`为RGBA图片, 默认为`L` */
+  mode?: 'L' | 'rgba'
+  /** 前景色, 如`#000000`, 不用于`L`模式 */
+  foreground?: string
+  /** 背景色, 可为`transparent`或带alpha的`#rrggbbaa`, 不用于`L`模式 */
+  background?: string
+}
+
+/** 提取实体对应的文本, entity没有文本时返回null */
+export function extractEntity(message: string, entity: EntityLike): string | null
+
+/** 转换为`Entity`并填充对应的文本 */
+export function extractEntities(message: string, entities: EntitiesLike): Entity[]
+
+/** 提取用户名和用户ID, 结果已去重并排序 */
+export function extractUsername(message: string, entities?: EntitiesLike | null): Usernames
+
+/** 从URL中提取用户名, 如`https://t.me/your_username`, 也支持`tg:`开头的URL */
+export function extractUsernameUrl(url: string): string | null
+
+/** 解析`t.me`系列域名或`tg:`协议的链接, 不是用户名链接或邀请链接时返回null */
+export function parseDeeplink(url: string): DeepLink | null
+
+/** 提取文本中的所有用户名链接和邀请链接 */
+export function extractDeeplinks(text: string): DeepLink[]
+
+/** 提取用户名、用户ID和邀请码, 保留来源, 按来源在消息中的位置排列; 区间越界的entity被忽略 */
+export function extract(message: string, entities?: EntitiesLike | null): Extraction[]
+
+/**
+ * 渲染文本为PNG图片
+ * @param scale 字体尺寸
+ */
+export function renderText(text: string, scale: number, options?: RenderOptions): Buffer
+
+/** 加载字体文件或目录到全局字体注册表, 返回加载的字体数量 */
+export function loadFonts(path: string): number
@@ -0,0 +1,24 @@
+'use strict'
+
+const native = require('./gram-node.node')
+
+// entities可为JSON字符串, 也可为GramJS的entity对象或Bot API的entity, 统一序列化为JSON字符串
+// GramJS的`BigInteger`序列化为字符串, 原生`BigInt`同样转换为字符串
+function toJson(value) {
+  if (value === undefined || value === null || typeof value === 'string') {
+    return value ?? undefined
+  }
+  return JSON.stringify(value, (_, x) => (typeof x === 'bigint' ? x.toString() : x))
+}
+
+module.exports = {
+  extractEntity: (message, entity) => native.extractEntity(message, toJson(entity)),
+  extractEntities: (message, entities) => native.extractEntities(message, toJson(entities)),
+  extractUsername: (message, entities) => native.extractUsername(message, toJson(entities)),
+  extractUsernameUrl: native.extractUsernameUrl,
+  parseDeeplink: native.parseDeeplink,
+  extractDeeplinks: native.extractDeeplinks,
+  extract: (message, entities) => native.extract(message, toJson(entities)),
+  renderText: native.renderText,
+  loadFonts: native.loadFonts,
+}
@@ -0,0 +1,17 @@
+// 编译原生模块并复制为gram-node.node, 用法: node scripts/build.mjs [--release]
+import { execFileSync } from 'node:child_process'
+import { copyFileSync } from 'node:fs'
+import { dirname, join } from 'node:path'
+import { fileURLToPath } from 'node:url'
+
+const root = join(dirname(fileURLToPath(import.meta.url)), '..')
+const release = process.argv.includes('--release')
+const args = ['build', '-p', 'gram-node', ...(release ? ['--release'] : [])]
+execFileSync('cargo', args, { cwd: root, stdio: 'inherit' })
+
+const lib = {
+  win32: 'gram_node.dll',
+  darwin: 'libgram_node.dylib',
+}[process.platform] ?? 'libgram_node.so'
+const target = process.env.CARGO_TARGET_DIR ?? join(root, '..', 'target')
+copyFileSync(join(target, release ? 'release' : 'debug', lib), join(root, 'gram-node.node'))
@@ -0,0 +1,265 @@
+use gram_core::extract::username::deeplink;
+use gram_core::format::{EntityFields, deserialize_entities, deserialize_entity};
+use gram_core::render::color::parse_color;
+use gram_core::render::layout::{Align, LayoutOptions};
+use gram_core::render::registry::FontRegistry;
+use grammers_tl_types::enums::MessageEntity;
+use image::{DynamicImage, ImageFormat};
+use napi::bindgen_prelude::Buffer;
+use napi_derive::napi;
+use std::collections::HashMap;
+use std::io::Cursor;
+
+// entities参数由index.js序列化为JSON字符串, 以支持GramJS对象中的`BigInteger`和原生`BigInt`
+
+fn js_err(e: anyhow::Error) -> napi::Error {
+    napi::Error::from_reason(format!("{e:#}"))
+}
+
+fn parse_entities(entities: Option<String>) -> napi::Result<Vec<MessageEntity>> {
+    entities
+        .map(|x| deserialize_entities(&x))
+        .transpose()
+        .map(Option::unwrap_or_default)
+        .map_err(js_err)
+}
+
+/// 消息实体, 类型名为TL类型名的蛇形命名, 如`text_url`、`mention_name`
+#[napi(object)]
+pub struct Entity {
+    pub kind: String,
+    /// UTF-16偏移
+    pub offset: i32,
+    /// UTF-16长度
+    pub length: i32,
+    /// entity对应的文本
+    pub text: Option<String>,
+    pub url: Option<String>,
+    pub user_id: Option<i64>,
+    pub language: Option<String>,
+    /// 与Bot API一致, 为字符串
+    pub custom_emoji_id: Option<String>,
+    pub collapsed: bool,
+}
+
+impl Entity {
+    fn new(entity: &MessageEntity, text: Option<String>) -> Self {
+        let fields = EntityFields::from(entity);
+        Self {
+            kind: fields.kind,
+            offset: fields.offset,
+            length: fields.length,
+            text,
+            url: fields.url,
+            user_id: fields.user_id,
+            language: fields.language,
+            custom_emoji_id: fields.custom_emoji_id.map(|x| x.to_string()),
+            collapsed: fields.collapsed,
+        }
+    }
+}
+
+/// 提取实体对应的文本, entity没有文本时返回null
+#[napi]
+pub fn extract_entity(message: String, entity: String) -> napi::Result<Option<String>> {
+    let entity = deserialize_entity(&entity).map_err(js_err)?;
+    let ret = gram_core::extract::entity::extract_entity(&message, &entity).map_err(js_err)?;
+    Ok(ret.map(str::to_string))
+}
+
+/// 转换为`Entity`并填充对应的文本
+#[napi]
+pub fn extract_entities(message: String, entities: String) -> napi::Result<Vec<Entity>> {
+    parse_entities(Some(entities))?
+        .iter()
+        .map(|entity| {
+            let text =
+                gram_core::extract::entity::extract_entity(&message, entity).map_err(js_err)?;
+            Ok(Entity::new(entity, text.map(str::to_string)))
+        })
+        .collect()
+}
+
+#[napi(object)]
+pub struct Usernames {
+    /// 不带@前缀的用户名
+    pub usernames: Vec<String>,
+    pub user_ids: Vec<i64>,
+}
+
+/// 提取用户名和用户ID, 结果已去重
+#[napi]
+pub fn extract_username(message: String, entities: Option<String>) -> napi::Result<Usernames> {
+    let entities = parse_entities(entities)?;
+    let (usernames, user_ids) =
+        gram_core::extract::username::extract_usernames(&message, Some(entities))
+            .map_err(js_err)?;
+    let mut usernames = usernames.into_iter().collect::<Vec<_>>();
+    let mut user_ids = user_ids.into_iter().collect::<Vec<_>>();
+    usernames.sort();
+    user_ids.sort();
+    Ok(Usernames {
+        usernames,
+        user_ids,
+    })
+}
+
+/// 从URL中提取用户名, 支持`tg:`开头的URL
+#[napi]
+pub fn extract_username_url(url: String) -> Option<String> {
+    deeplink::get_username(&url)
+}
+
+/// 解析后的用户名链接或邀请链接
+#[napi(object)]
+pub struct DeepLink {
+    /// `username`或`invite`
+    pub kind: String,
+    /// 用户名, 邀请链接为null
+    pub username: Option<String>,
+    /// 链接参数, 与`tg://`形式的参数名一致, 如`start`、`post`; 邀请码为`invite`
+    pub params: HashMap<String, String>,
+    /// 规范的`https://t.me/`链接
+    pub url: String,
+}
+
+impl From<deeplink::DeepLink> for DeepLink {
+    fn from(link: deeplink::DeepLink) -> Self {
+        Self {
+            kind: link.kind.as_str().to_string(),
+            url: link.to_url(),
+            username: link.username,
+            params: link.params.into_iter().collect(),
+        }
+    }
+}
+
+/// 解析`t.me`系列域名或`tg:`协议的链接, 不是用户名链接或邀请链接时返回null
+#[napi]
+pub fn parse_deeplink(url: String) -> Option<DeepLink> {
+    deeplink::DeepLink::parse(&url).map(DeepLink::from)
+}
+
+/// 提取文本中的所有用户名链接和邀请链接
+#[napi]
+pub fn extract_deeplinks(text: String) -> Vec<DeepLink> {
+    deeplink::extract_links(&text)
+        .into_iter()
+        .map(DeepLink::from)
+        .collect()
+}
+
+/// 带来源的提取结果
+#[napi(object)]
+pub struct Extraction {
+    /// `username`、`user_id`或`invite`
+    pub kind: String,
+    /// 用户名(不带@)、用户ID或邀请码
+    pub value: String,
+    /// `text`、`mention`、`mention_name`或`text_url`
+    pub source: String,
+    /// 来源在消息中的UTF-16偏移
+    pub offset: u32,
+    /// 来源在消息中的UTF-16长度
+    pub length: u32,
+    /// 来源为链接时的原始链接
+    pub link: Option<String>,
+}
+
+/// 提取用户名、用户ID和邀请码, 保留来源, 按来源在消息中的位置排列
+#[napi]
+pub fn extract(message: String, entities: Option<String>) -> napi::Result<Vec<Extraction>> {
+    let entities = parse_entities(entities)?;
+    let ret = gram_core::extract::username::extract_all(&message, &entities)
+        .into_iter()
+        .map(|x| Extraction {
+            kind: x.kind.to_string(),
+            value: x.value,
+            source: x.source.to_string(),
+            offset: x.offset as u32,
+            length: x.length as u32,
+            link: x.link,
+        })
+        .collect();
+    Ok(ret)
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct RenderOptions {
+    /// 最大行宽(像素), 超出时自动换行
+    pub max_width: Option<f64>,
+    /// `left`、`center`或`right`, 默认为`left`
+    pub align: Option<String>,
+    /// 行距倍数, 默认为1
+    pub line_spacing: Option<f64>,
+    /// `L`为白底黑字的灰度图, `rgba`为RGBA图片, 默认为`L`
+    pub mode: Option<String>,
+    /// 前景色, 不用于`L`模式
+    pub foreground: Option<String>,
+    /// 背景色, 可为`transparent`, 不用于`L`模式
+    pub background: Option<String>,
+}
+
+/// 渲染文本为PNG图片
+#[napi]
+pub fn render_text(
+    text: String,
+    scale: f64,
+    options: Option<RenderOptions>,
+) -> napi::Result<Buffer> {
+    let options = options.unwrap_or_default();
+    let layout = LayoutOptions {
+        max_width: options.max_width.map(|x| x as f32),
+        line_spacing: options.line_spacing.unwrap_or(1.) as f32,
+        align: options
+            .align
+            .as_deref()
+            .unwrap_or("left")
+            .parse::<Align>()
+            .map_err(js_err)?,
+        ..Default::default()
+    };
+    if FontRegistry::read_global().is_empty() {
+        return Err(napi::Error::from_reason(
+            "no fonts loaded, call loadFonts first",
+        ));
+    }
+    let scale = scale as f32;
+    let color = |s: Option<&str>, default: &str| parse_color(s.unwrap_or(default)).map_err(js_err);
+    let img: DynamicImage = match options
+        .mode
+        .as_deref()
+        .unwrap_or("L")
+        .to_lowercase()
+        .as_str()
+    {
+        "l" => gram_core::render::render_text(&text, scale, &layout).into(),
+        "rgba" => gram_core::render::render_text_rgba(
+            &text,
+            scale,
+            &layout,
+            color(options.foreground.as_deref(), "black")?,
+            color(options.background.as_deref(), "white")?,
+        )
+        .into(),
+        mode => return Err(napi::Error::from_reason(format!("unknown mode: {mode}"))),
+    };
+    let mut ret = Vec::new();
+    img.write_to(&mut Cursor::new(&mut ret), ImageFormat::Png)
+        .map_err(|e| napi::Error::from_reason(e.to_string()))?;
+    Ok(ret.into())
+}
+
+/// 加载字体文件或目录到全局字体注册表, 返回加载的字体数量
+#[napi]
+pub fn load_fonts(path: String) -> napi::Result<u32> {
+    let path = std::path::Path::new(&path);
+    let mut registry = FontRegistry::write_global();
+    let ret = if path.is_dir() {
+        registry.load_dir(path)
+    } else {
+        registry.load_file(path)
+    };
+    ret.map(|x| x as u32).map_err(js_err)
+}
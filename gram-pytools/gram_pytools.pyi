@@ -1,26 +1,99 @@
-from typing import Optional
+"""
+渲染、提取等耗时的函数执行时释放GIL, 可在其他线程中并发调用;
+`*_async`版本在Rust线程池中执行, 返回当前事件循环中的`asyncio.Future`, 不阻塞事件循环
+"""
 
+from typing import Any, Awaitable, Iterable, Optional, Union
 
-def extract_entity(message: str, entity: str) -> Optional[str]:
+EntityLike = Union[str, "Entity", dict[str, Any], Any]
+"""
+消息实体, 可为:
+- telethon格式的JSON字符串
+- `extract_entities`返回的`Entity`
+- telethon的`MessageEntity*`对象, 或其`to_dict()`的结果
+- pyrogram的`MessageEntity`对象
+- Bot API格式的dict, 如`{"type": "text_link", "offset": 0, "length": 4, "url": "..."}`
+
+对象和dict按`offset`、`length`、`url`、`user_id`(或`user.id`)、`language`、`custom_emoji_id`等字段读取
+"""
+
+EntitiesLike = Union[str, Iterable[Union["Entity", dict[str, Any], Any]]]
+"""消息实体列表, 为telethon格式的JSON字符串, 或`EntityLike`中对象、dict的列表"""
+
+
+class GramError(Exception):
+    """gram_pytools中所有异常的基类, 未分类的错误(如读取文件失败)直接抛出该异常"""
+
+
+class EntityDecodeError(GramError):
+    """entity解码失败, 如JSON格式错误、缺少`offset`等字段"""
+
+    line: int
+    """JSON中出错的行, 从1开始; 不是JSON语法错误时为0"""
+    column: int
+    """JSON中出错的列, 从1开始; 不是JSON语法错误时为0"""
+
+
+class OffsetError(GramError):
+    """entity的UTF-16区间超出文本范围, 或端点落在代理对中间"""
+
+    offset: int
+    length: int
+    index: int
+    """出错的端点, 为`offset`或`offset + length`"""
+    text_length: int
+    """文本的UTF-16长度"""
+
+
+class UnsupportedEntityError(GramError):
+    """不支持的entity类型"""
+
+    kind: str
+    """类型名, 如`MessageEntityFoo`"""
+
+
+class RenderError(GramError):
+    """渲染失败, 如颜色、对齐方式等参数无效, 或未加载字体"""
+
+
+def setup_logging(level: Union[int, str, None] = None) -> None:
+    """
+    将Rust侧的日志转发到Python的`logging`, 默认不转发
+    logger名称为Rust模块路径, 如`gram_core.render.glyph`; 消息前缀为所在的span, 如`layout{len=7 scale=20.0}: `
+    可多次调用以修改级别; 其他线程(如`*_async`函数的线程池)中的日志由后台线程转发, 可能稍有延迟
+    :param level: 转发的最低级别, 为`logging`的级别或级别名, 另有`TRACE`(5); 默认为`INFO`
+    :raise GramError: 已安装其他全局tracing subscriber
+    """
+    ...
+
+
+def extract_entity(message: str, entity: EntityLike) -> Optional[str]:
     """
     提取实体对应的文本切片
     :param message: 消息文本内容, 原始内容
-    :param entity: 消息实体的JSON编码, 支持telethon格式
+    :param entity: 消息实体, 支持telethon格式的JSON编码及telethon、pyrogram的对象
     :return: 返回该entity对应的、在message中的文本内容, 如entity没有文本, 返回None
     """
     ...
 
 
-def extract_username(message: str, entities: Optional[str]) -> tuple[set[str], set[int]]:
+def extract_username(message: str, entities: Optional[EntitiesLike]) -> tuple[set[str], set[int]]:
     """
     提取用户名
     :param message: 消息文本内容, 原始内容
-    :param entities: 消息entities的JSON-Lines编码, 支持telethon格式
+    :param entities: 消息entities, 可直接传入`message.entities`; 也支持telethon格式的JSON编码
     :return: 返回两个列表, 分别为用户名和用户ID, 用户名是不带@前缀的
     """
     ...
 
 
+def extract_username_async(
+    message: str, entities: Optional[EntitiesLike] = None
+) -> Awaitable[tuple[set[str], set[int]]]:
+    """`extract_username`的awaitable版本"""
+    ...
+
+
 def extract_username_url(url: str) -> Optional[str]:
     """
     从URL中提取用户名
@@ -30,13 +103,557 @@ def extract_username_url(url: str) -> Optional[str]:
     ...
 
 
-def render_text(text: str, scale: float) -> bytes:
+class DeepLink:
     """
-    渲染文本为PNG格式字节串
-    调用者可使用`io.BytesIO`将返回值包装后使用`PIL.Image.open`打开
+    解析后的用户名链接或邀请链接, 可比较、哈希及pickle
+    """
+
+    def __init__(
+        self,
+        kind: str,
+        username: Optional[str] = None,
+        params: Union[dict[str, str], Iterable[tuple[str, str]], None] = None,
+    ) -> None: ...
+
+    @property
+    def kind(self) -> str:
+        """`username`或`invite`"""
+        ...
+
+    @property
+    def username(self) -> Optional[str]:
+        """用户名, 邀请链接为None"""
+        ...
+
+    @property
+    def params(self) -> dict[str, str]:
+        """链接参数, 与`tg://`形式的参数名一致, 如`start`、`post`; 邀请码为`invite`"""
+        ...
+
+    def param(self, key: str) -> Optional[str]: ...
+
+    def to_url(self) -> str:
+        """
+        转换为规范的`https://t.me/`链接
+        """
+        ...
+
+
+def parse_deeplink(url: str) -> Optional[DeepLink]:
+    """
+    解析`t.me`系列域名或`tg:`协议的链接
+    :return: 不是用户名链接或邀请链接时返回None
+    """
+    ...
+
+
+def extract_deeplinks(text: str) -> list[DeepLink]:
+    """
+    提取文本中的所有用户名链接和邀请链接
+    """
+    ...
+
+
+class Entity:
+    """
+    消息实体, 可比较、哈希及pickle, 也可作为`EntityLike`传入其他函数
+    """
+
+    def __init__(
+        self,
+        kind: str,
+        offset: int,
+        length: int,
+        text: Optional[str] = None,
+        url: Optional[str] = None,
+        user_id: Optional[int] = None,
+        language: Optional[str] = None,
+        custom_emoji_id: Optional[int] = None,
+        collapsed: bool = False,
+    ) -> None:
+        """
+        :param kind: 类型名, 写法与`EntityLike`中的dict一致, 如`text_link`、`MessageEntityTextUrl`
+        :raise UnsupportedEntityError: 不支持的类型
+        """
+        ...
+
+    @property
+    def kind(self) -> str:
+        """TL类型名的蛇形命名, 如`text_url`、`mention_name`"""
+        ...
+
+    @property
+    def offset(self) -> int:
+        """UTF-16偏移"""
+        ...
+
+    @property
+    def length(self) -> int:
+        """UTF-16长度"""
+        ...
 
-    :param text: 待渲染文本
+    @property
+    def text(self) -> Optional[str]:
+        """entity对应的文本"""
+        ...
+
+    @property
+    def url(self) -> Optional[str]: ...
+    @property
+    def user_id(self) -> Optional[int]: ...
+    @property
+    def language(self) -> Optional[str]: ...
+    @property
+    def custom_emoji_id(self) -> Optional[int]: ...
+    @property
+    def collapsed(self) -> bool: ...
+
+
+def extract_entities(message: str, entities: EntitiesLike) -> list[Entity]:
+    """
+    转换为`Entity`并填充对应的文本
+    :param message: 消息文本内容, 原始内容
+    :param entities: 消息entities, 可直接传入`message.entities`
+    """
+    ...
+
+
+class Extraction:
+    """
+    带来源的提取结果, 可比较、哈希及pickle
+    """
+
+    def __init__(
+        self,
+        kind: str,
+        value: str,
+        source: str,
+        offset: int,
+        length: int,
+        link: Optional[str] = None,
+    ) -> None: ...
+
+    kind: str
+    """`username`、`user_id`或`invite`"""
+    value: str
+    """用户名(不带@)、用户ID或邀请码"""
+    source: str
+    """`text`(文本中的链接)、`mention`、`mention_name`或`text_url`"""
+    offset: int
+    """来源在消息中的UTF-16偏移"""
+    length: int
+    """来源在消息中的UTF-16长度"""
+    link: Optional[str]
+    """来源为链接时的原始链接"""
+
+
+def extract(message: str, entities: Optional[EntitiesLike] = None) -> list[Extraction]:
+    """
+    提取用户名、用户ID和邀请码, 保留每个结果的来源
+    :param message: 消息文本内容, 原始内容
+    :param entities: 消息entities
+    :return: 按来源在消息中的位置排列, 同一个值可能出现多次; 区间越界的entity被忽略
+    """
+    ...
+
+
+def extract_async(
+    message: str, entities: Optional[EntitiesLike] = None
+) -> Awaitable[list[Extraction]]:
+    """`extract`的awaitable版本"""
+    ...
+
+
+def render_text(
+    text: str,
+    scale: float,
+    max_width: Optional[float] = None,
+    align: str = "left",
+    line_spacing: float = 1.0,
+    mode: str = "L",
+    foreground: str = "black",
+    background: str = "white",
+    format: str = "png",
+) -> bytes:
+    """
+    渲染文本为PNG等格式的图片, 或SVG、PDF格式的矢量文件
+    图片可使用`io.BytesIO`将返回值包装后使用`PIL.Image.open`打开; 如需像素数据, 使用`render_text_buffer`以避免编解码
+
+    :param text: 待渲染文本, 支持换行符
     :param scale: 字体尺寸，推荐值为72
+    :param max_width: 最大行宽(像素), 超出时按UAX #14规则自动换行; 为None时只在换行符处换行
+    :param align: 对齐方式, 可选`left`、`center`、`right`
+    :param line_spacing: 行距倍数
+    :param mode: `L`为白底黑字的灰度图; `rgba`为RGBA图片, 彩色emoji保留字体自带的颜色;
+        `svg`、`pdf`将字形轮廓输出为矢量路径, 排版与`rgba`一致, 位图彩色emoji不会输出
+    :param foreground: 前景色, 如`#000000`, 不用于`L`模式
+    :param background: 背景色, 可为`transparent`或带alpha的`#rrggbbaa`, 不用于`L`模式; PDF忽略透明度
+    :param format: 图片编码格式, 可选`png`、`webp`(无损)、`jpeg`、`bmp`, 仅用于`L`和`rgba`模式;
+        JPEG不支持透明度, alpha通道会被丢弃
+    :return: 编码后的图片, 或SVG、PDF文件的内容
+    """
+    ...
+
+
+def render_text_async(
+    text: str,
+    scale: float,
+    max_width: Optional[float] = None,
+    align: str = "left",
+    line_spacing: float = 1.0,
+    mode: str = "L",
+    foreground: str = "black",
+    background: str = "white",
+    format: str = "png",
+) -> Awaitable[bytes]:
+    """
+    `render_text`的awaitable版本, 如`await render_text_async("hello", 72)`
+    参数错误(如对齐方式无效)直接抛出, 渲染中的错误在await时抛出
+    """
+    ...
+
+
+class RawImage:
+    """
+    未编码的图片像素, 按行优先排列, 每像素1字节(`L`)或4字节(`RGBA`)
+    实现了缓冲区协议, 均不复制数据:
+    `numpy.asarray(img)`得到形状为(高, 宽)或(高, 宽, 4)的`uint8`数组;
+    `numpy.frombuffer(img, dtype=numpy.uint8)`得到一维数组
+    """
+
+    width: int
+    height: int
+    mode: str
+    """PIL的模式名, 为`L`或`RGBA`"""
+
+    def tobytes(self) -> bytes:
+        """复制像素数据为`bytes`"""
+        ...
+
+    def __len__(self) -> int: ...
+    def __buffer__(self, flags: int) -> memoryview: ...
+
+
+def render_text_buffer(
+    text: str,
+    scale: float,
+    max_width: Optional[float] = None,
+    align: str = "left",
+    line_spacing: float = 1.0,
+    mode: str = "L",
+    foreground: str = "black",
+    background: str = "white",
+) -> RawImage:
+    """
+    渲染文本为未编码的像素, 参数与`render_text`一致, `mode`只支持`L`和`rgba`
+    """
+    ...
+
+
+def render_text_raw(
+    text: str,
+    scale: float,
+    max_width: Optional[float] = None,
+    align: str = "left",
+    line_spacing: float = 1.0,
+    mode: str = "L",
+    foreground: str = "black",
+    background: str = "white",
+) -> tuple[bytes, int, int, str]:
+    """
+    渲染文本为未编码的像素, 参数与`render_text_buffer`一致
+    :return: (像素, 宽, 高, PIL模式名), 可使用`PIL.Image.frombytes(mode, (width, height), data)`构造图片
+    """
+    ...
+
+
+def render_message(
+    text: str,
+    entities_json: Optional[EntitiesLike] = None,
+    scale: float = 32.0,
+    max_width: Optional[float] = None,
+    line_spacing: float = 1.0,
+    spoiler: str = "blackout",
+    foreground: str = "black",
+    background: str = "white",
+    link_color: str = "#2481cc",
+) -> bytes:
+    """
+    按entity渲染带格式的消息为RGBA图片
+    支持粗体、斜体、下划线、删除线、剧透、行内代码和代码块、链接、引用块, 自定义emoji绘制为占位方块
+
+    :param text: 消息文本
+    :param entities_json: 消息entities, 同`extract_username`
+    :param scale: 字体尺寸
+    :param max_width: 最大行宽(像素), 超出时自动换行; 为None时只在换行符处换行
+    :param line_spacing: 行距倍数
+    :param spoiler: 剧透的显示方式, `blackout`为涂黑, `blur`为模糊
+    :param foreground: 前景色
+    :param background: 背景色, 可为`transparent`
+    :param link_color: 链接、提及等的颜色, 也用于引用块的竖线
     :return: PNG格式的字节串
     """
     ...
+
+
+def render_message_async(
+    text: str,
+    entities_json: Optional[EntitiesLike] = None,
+    scale: float = 32.0,
+    max_width: Optional[float] = None,
+    line_spacing: float = 1.0,
+    spoiler: str = "blackout",
+    foreground: str = "black",
+    background: str = "white",
+    link_color: str = "#2481cc",
+) -> Awaitable[bytes]:
+    """`render_message`的awaitable版本"""
+    ...
+
+
+def render_captcha(
+    answer: Optional[str] = None,
+    length: int = 5,
+    scale: float = 48.0,
+    seed: Optional[int] = None,
+    mix_fonts: bool = True,
+    confusables: bool = False,
+    max_rotation: float = 25.0,
+    warp: float = 0.06,
+    noise_lines: int = 3,
+    noise_dots: int = 200,
+) -> tuple[bytes, str]:
+    """
+    生成验证码图片, 字形随机旋转、偏移并整体扭曲, 叠加干扰曲线和干扰点
+
+    :param answer: 答案, 为None时从不易混淆的大写字母和数字中随机生成
+    :param length: 随机生成答案时的长度
+    :param scale: 字体尺寸
+    :param seed: 随机数种子, 相同的种子和参数生成相同的图片
+    :param mix_fonts: 每个字形从支持该字符的字体中随机选择
+    :param confusables: 随机将部分拉丁字母替换为外形相同的西里尔字母
+    :param max_rotation: 单个字形的最大旋转角度(度)
+    :param warp: 扭曲振幅, 相对于字体尺寸
+    :param noise_lines: 干扰曲线的数量
+    :param noise_dots: 干扰点的数量
+    :return: (PNG格式的灰度图, 答案)
+    :raise RenderError: 参数无效, 如`scale`不为正数
+    """
+    ...
+
+
+def render_captcha_async(
+    answer: Optional[str] = None,
+    length: int = 5,
+    scale: float = 48.0,
+    seed: Optional[int] = None,
+    mix_fonts: bool = True,
+    confusables: bool = False,
+    max_rotation: float = 25.0,
+    warp: float = 0.06,
+    noise_lines: int = 3,
+    noise_dots: int = 200,
+) -> Awaitable[tuple[bytes, str]]:
+    """`render_captcha`的awaitable版本"""
+    ...
+
+
+def check_captcha(answer: str, input: str) -> bool:
+    """
+    检查验证码输入, 忽略大小写和空白, 西里尔字母视为外形相同的拉丁字母
+    """
+    ...
+
+
+def render_avatar(name: str, peer_id: int, size: int = 160, format: str = "png") -> bytes:
+    """
+    渲染没有头像的用户的占位头像, 与Telegram客户端的样式一致: 按ID选择颜色的渐变圆形, 圆上为白色首字母
+    :param name: 显示名称, 取第一个和最后一个单词的首字母, emoji也可作为首字母
+    :param peer_id: 用户、群组或频道的ID, 支持Bot API格式的负数ID
+    :param size: 图片的宽和高(像素)
+    :param format: 图片编码格式, 与`render_text`一致; 圆形以外透明, JPEG会丢失透明度
+    :return: 编码后的RGBA图片
+    """
+    ...
+
+
+def render_avatar_async(
+    name: str, peer_id: int, size: int = 160, format: str = "png"
+) -> Awaitable[bytes]:
+    """`render_avatar`的awaitable版本"""
+    ...
+
+
+class TextMetrics:
+    """
+    文本的测量结果
+    """
+
+    width: float
+    """包含墨迹的宽度, 向上取整后与`render_text`生成的图片宽度一致"""
+    height: float
+    """包含墨迹的高度, 向上取整后与`render_text`生成的图片高度一致"""
+    ascent: float
+    """基线以上的高度"""
+    descent: float
+    """基线以下的高度, 为正数"""
+    line_count: int
+    graphemes: list[tuple[str, int, float, float]]
+    """按逻辑顺序排列的(字素簇, 行下标, 起笔点横坐标, 步进宽度)"""
+    missing_glyphs: int
+    """没有任何字体支持、绘制为'?'的字形数量"""
+
+
+def measure_text(
+    text: str,
+    scale: float,
+    max_width: Optional[float] = None,
+    align: str = "left",
+    line_spacing: float = 1.0,
+) -> TextMetrics:
+    """
+    测量文本排版后的尺寸, 不绘制图片, 参数与`render_text`一致
+    可通过`missing_glyphs`判断文本是否含有内置字体都不支持的字符
+    """
+    ...
+
+
+def font_coverage(text: str) -> list[tuple[str, str, list[int]]]:
+    """
+    文本中每个字素簇使用的字体, 与`render_text`的字体回退一致
+    可用于发现由未分配或私用区码位构成的"隐形"昵称
+
+    :param text: 待检查文本
+    :return: (字素簇, 字体族名, 没有字体支持的码位)的列表, 不含换行符
+    """
+    ...
+
+
+def load_fonts(path: str) -> int:
+    """
+    加载字体到全局字体注册表, 优先级低于已有字体
+    未启用`embedded-fonts`特性构建时, 渲染前必须先加载字体
+
+    :param path: 字体文件(支持TTC/OTC字体集合)或目录, 目录会被递归扫描
+    :return: 新加入的字体数量, 重复加载同一文件时为0
+    """
+    ...
+
+
+def set_font_priority(key: str, families: list[str]) -> None:
+    """
+    为脚本或语言指定优先使用的字体族, 覆盖之前的设置
+
+    :param key: ISO 15924脚本代码(如`Hani`、`Arab`)或语言标签(如`ja`)
+    :param families: 字体族名列表, 如`["Noto Sans JP"]`
+    """
+    ...
+
+
+def set_font_language(language: Optional[str] = None) -> None:
+    """
+    设置文本语言, 该语言的优先字体族适用于所有字符
+
+    :param language: 语言标签, 为None时不区分语言
+    """
+    ...
+
+
+class SpamScorer:
+    """
+    垃圾消息评分器, 规则可从TOML/JSON加载
+    """
+
+    def __init__(self, config: Optional[str] = None) -> None:
+        """
+        :param config: TOML格式的规则配置, 为None时使用默认规则
+        """
+        ...
+
+    @staticmethod
+    def from_json(config: str) -> "SpamScorer":
+        """
+        :param config: JSON格式的规则配置
+        """
+        ...
+
+    @staticmethod
+    def from_path(path: str) -> "SpamScorer":
+        """
+        :param path: 规则配置文件路径, 根据扩展名识别`.toml`或`.json`
+        """
+        ...
+
+    def score(self, message: str, entities: Optional[EntitiesLike] = None) -> tuple[float, bool, list[str]]:
+        """
+        为消息评分
+        :param message: 消息文本内容, 原始内容
+        :param entities: 消息entities, 同`extract_username`
+        :return: 返回总分、是否超过阈值、触发的规则名列表
+        """
+        ...
+
+
+def normalize_text(message: str, entities: Optional[EntitiesLike] = None) -> str:
+    """
+    归一化消息文本, 用于近似重复检测
+    删除链接/提及等实体、零宽字符、emoji变体选择符, 并折叠标点和空白
+    :param message: 消息文本内容, 原始内容
+    :param entities: 消息entities, 同`extract_username`
+    :return: 归一化后的文本
+    """
+    ...
+
+
+def simhash(message: str, entities: Optional[EntitiesLike] = None) -> int:
+    """
+    计算归一化文本的64位SimHash签名
+    :param message: 消息文本内容, 原始内容
+    :param entities: 消息entities, 同`extract_username`
+    :return: 无符号64位整数
+    """
+    ...
+
+
+def minhash(message: str, entities: Optional[EntitiesLike] = None) -> list[int]:
+    """
+    计算归一化文本的MinHash签名
+    :param message: 消息文本内容, 原始内容
+    :param entities: 消息entities, 同`extract_username`
+    :return: 长度为128的无符号64位整数列表
+    """
+    ...
+
+
+class DedupIndex:
+    """
+    基于MinHash LSH的内存近似重复索引
+    """
+
+    def __init__(self, threshold: float = 0.9) -> None:
+        """
+        :param threshold: 相似度阈值, 取值(0, 1]
+        :raise GramError: 阈值超出范围或为NaN
+        """
+        ...
+
+    def insert(self, id: int, message: str, entities: Optional[EntitiesLike] = None) -> None:
+        """
+        索引一条消息, 相同id会覆盖旧记录
+        """
+        ...
+
+    def remove(self, id: int) -> bool:
+        """
+        删除一条消息, 返回是否存在
+        """
+        ...
+
+    def query(self, message: str, entities: Optional[EntitiesLike] = None) -> list[tuple[int, float]]:
+        """
+        查询相似度不低于阈值的已索引消息
+        :return: (id, 估计相似度)列表, 按相似度降序排列
+        """
+        ...
+
+    def __len__(self) -> int: ...
@@ -1,31 +1,164 @@
-use gram_core::format::{deserialize_telethon_entities, deserialize_telethon_entity};
-use gram_core::render::font::FONTS;
-use gram_core::render::glyph::{Scale, VecGlyph};
-use image::{ImageBuffer, Luma};
-use pyo3::{create_exception, prelude::*};
+use gram_core::error::Error;
+use gram_core::extract::username::deeplink;
+use gram_core::format::{deserialize_telethon_entities, deserialize_telethon_entity, EntityFields};
+use gram_core::render::captcha::CaptchaOptions;
+use gram_core::render::color::parse_color;
+use gram_core::render::layout::{Align, LayoutOptions};
+use gram_core::render::registry::FontRegistry;
+use gram_core::render::rich::{RichOptions, SpoilerMode};
+use grammers_tl_types::enums::MessageEntity;
+use image::{DynamicImage, ImageFormat};
+use pyo3::exceptions::{PyBufferError, PyValueError};
+use pyo3::types::{PyBytes, PyDict, PyInt, PyString, PyType};
+use pyo3::IntoPyObjectExt;
+use pyo3::{create_exception, ffi, prelude::*};
 use std::collections::HashSet;
+use std::ffi::{c_int, c_void};
 use std::io::Cursor;
+use std::ptr;
 
-create_exception!(gram_tools, AnyhowError, pyo3::exceptions::PyException);
+mod logging;
+mod task;
+
+create_exception!(
+    gram_pytools,
+    GramError,
+    pyo3::exceptions::PyException,
+    "gram_pytools中所有异常的基类"
+);
+create_exception!(
+    gram_pytools,
+    EntityDecodeError,
+    GramError,
+    "entity解码失败, `line`、`column`为JSON中出错的位置, 不是JSON语法错误时为0"
+);
+create_exception!(
+    gram_pytools,
+    OffsetError,
+    GramError,
+    "entity的UTF-16区间越界, 属性有`offset`、`length`、`index`(出错的端点)和`text_length`"
+);
+create_exception!(
+    gram_pytools,
+    UnsupportedEntityError,
+    GramError,
+    "不支持的entity类型, `kind`为类型名"
+);
+create_exception!(
+    gram_pytools,
+    RenderError,
+    GramError,
+    "渲染失败, 如参数无效或未加载字体"
+);
+
+/// 转换为对应类型的Python异常, 未分类的错误为`GramError`
+fn py_err(e: anyhow::Error) -> PyErr {
+    let message = format!("{e:#}");
+    let Some(error) = e.downcast_ref::<Error>() else {
+        return GramError::new_err(message);
+    };
+    Python::attach(|py| {
+        let (err, attrs) = match error {
+            Error::EntityDecode { line, column, .. } => (
+                EntityDecodeError::new_err(message),
+                vec![("line", line), ("column", column)],
+            ),
+            Error::Offset {
+                offset,
+                length,
+                index,
+                text_length,
+            } => (
+                OffsetError::new_err(message),
+                vec![
+                    ("offset", offset),
+                    ("length", length),
+                    ("index", index),
+                    ("text_length", text_length),
+                ],
+            ),
+            Error::UnsupportedEntity { kind } => {
+                let err = UnsupportedEntityError::new_err(message);
+                err.value(py).setattr("kind", kind).ok();
+                (err, vec![])
+            }
+            Error::Render(_) => (RenderError::new_err(message), vec![]),
+        };
+        for (name, value) in attrs {
+            err.value(py).setattr(name, value).ok();
+        }
+        err
+    })
+}
+
+/// 在释放GIL后调用, 避免持有GIL等待字体注册表的锁
+fn check_fonts() -> anyhow::Result<()> {
+    if FontRegistry::read_global().is_empty() {
+        return Err(Error::Render("no fonts loaded, call load_fonts first".into()).into());
+    }
+    Ok(())
+}
 
 /// A Python module implemented in Rust.
 #[pymodule]
 fn gram_pytools(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("GramError", m.py().get_type::<GramError>())?;
+    m.add("EntityDecodeError", m.py().get_type::<EntityDecodeError>())?;
+    m.add("OffsetError", m.py().get_type::<OffsetError>())?;
+    m.add(
+        "UnsupportedEntityError",
+        m.py().get_type::<UnsupportedEntityError>(),
+    )?;
+    m.add("RenderError", m.py().get_type::<RenderError>())?;
+    m.add_function(wrap_pyfunction!(logging::setup_logging, m)?)?;
     m.add_function(wrap_pyfunction!(extract_entity, m)?)?;
     m.add_function(wrap_pyfunction!(extract_username, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_username_async, m)?)?;
     m.add_function(wrap_pyfunction!(extract_username_url, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_deeplink, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_deeplinks, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_entities, m)?)?;
+    m.add_function(wrap_pyfunction!(extract, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_async, m)?)?;
+    m.add_class::<DeepLink>()?;
+    m.add_class::<Entity>()?;
+    m.add_class::<Extraction>()?;
     m.add_function(wrap_pyfunction!(render_text, m)?)?;
+    m.add_function(wrap_pyfunction!(render_text_async, m)?)?;
+    m.add_function(wrap_pyfunction!(render_text_raw, m)?)?;
+    m.add_function(wrap_pyfunction!(render_text_buffer, m)?)?;
+    m.add_class::<RawImage>()?;
+    m.add_function(wrap_pyfunction!(render_message, m)?)?;
+    m.add_function(wrap_pyfunction!(render_message_async, m)?)?;
+    m.add_function(wrap_pyfunction!(render_captcha, m)?)?;
+    m.add_function(wrap_pyfunction!(render_captcha_async, m)?)?;
+    m.add_function(wrap_pyfunction!(measure_text, m)?)?;
+    m.add_function(wrap_pyfunction!(font_coverage, m)?)?;
+    m.add_class::<TextMetrics>()?;
+    m.add_function(wrap_pyfunction!(check_captcha, m)?)?;
+    m.add_function(wrap_pyfunction!(render_avatar, m)?)?;
+    m.add_function(wrap_pyfunction!(render_avatar_async, m)?)?;
+    m.add_function(wrap_pyfunction!(load_fonts, m)?)?;
+    m.add_function(wrap_pyfunction!(set_font_priority, m)?)?;
+    m.add_function(wrap_pyfunction!(set_font_language, m)?)?;
+    m.add_class::<SpamScorer>()?;
+    m.add_function(wrap_pyfunction!(normalize_text, m)?)?;
+    m.add_function(wrap_pyfunction!(simhash, m)?)?;
+    m.add_function(wrap_pyfunction!(minhash, m)?)?;
+    m.add_class::<DedupIndex>()?;
     Ok(())
 }
 
 #[pyfunction]
 /// 兼容telethon
-pub fn extract_entity<'a>(message: &'a str, entity: &'a str) -> PyResult<Option<&'a str>> {
-    let ent =
-        deserialize_telethon_entity(entity).map_err(|e| AnyhowError::new_err(e.to_string()))?;
-    let ret = gram_core::extract::entity::extract_entity(message, &ent)
-        .map_err(|e| AnyhowError::new_err(e.to_string()))?;
-    Ok(ret)
+pub fn extract_entity<'a>(
+    py: Python<'_>,
+    message: &'a str,
+    entity: EntityArg,
+) -> PyResult<Option<&'a str>> {
+    task::detach(py, || {
+        gram_core::extract::entity::extract_entity(message, &entity.0)
+    })
 }
 
 #[pyfunction]
@@ -36,33 +169,1515 @@ pub fn extract_username_url(url: &str) -> Option<String> {
 #[pyfunction]
 /// 兼容telethon
 pub fn extract_username(
-    message: &str,
-    entities: Option<&str>,
+    py: Python<'_>,
+    message: String,
+    entities: Option<EntitiesArg>,
 ) -> PyResult<(HashSet<String>, HashSet<i64>)> {
-    let entities = if let Some(entities) = entities {
-        let ent = deserialize_telethon_entities(entities)
-            .map_err(|e| AnyhowError::new_err(e.to_string()))?;
-        Some(ent)
-    } else {
-        None
+    task::detach(py, extract_username_job(message, entities))
+}
+
+#[pyfunction]
+#[pyo3(signature = (message, entities=None))]
+/// `extract_username`的awaitable版本, 在线程池中执行
+pub fn extract_username_async<'py>(
+    py: Python<'py>,
+    message: String,
+    entities: Option<EntitiesArg>,
+) -> PyResult<Bound<'py, PyAny>> {
+    task::spawn(py, extract_username_job(message, entities))
+}
+
+fn extract_username_job(
+    message: String,
+    entities: Option<EntitiesArg>,
+) -> impl FnOnce() -> anyhow::Result<(HashSet<String>, HashSet<i64>)> + Send + 'static {
+    move || gram_core::extract::username::extract_usernames(&message, entities.map(|x| x.0))
+}
+
+/// entity参数, 可为telethon格式的JSON字符串, `Entity`, 也可为telethon、pyrogram的entity对象或dict
+pub struct EntityArg(MessageEntity);
+
+/// entities参数, 可为telethon格式的JSON字符串, 也可为telethon、pyrogram的entity对象或dict的列表
+pub struct EntitiesArg(Vec<MessageEntity>);
+
+impl<'py> FromPyObject<'_, 'py> for EntityArg {
+    type Error = PyErr;
+
+    fn extract(obj: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        if let Ok(json) = obj.cast::<PyString>() {
+            return deserialize_telethon_entity(json.to_str()?)
+                .map(EntityArg)
+                .map_err(py_err);
+        }
+        entity_from_object(&obj).map(EntityArg)
+    }
+}
+
+impl<'py> FromPyObject<'_, 'py> for EntitiesArg {
+    type Error = PyErr;
+
+    fn extract(obj: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        if let Ok(json) = obj.cast::<PyString>() {
+            return deserialize_telethon_entities(json.to_str()?)
+                .map(EntitiesArg)
+                .map_err(py_err);
+        }
+        obj.try_iter()?
+            .map(|x| entity_from_object(&x?))
+            .collect::<PyResult<_>>()
+            .map(EntitiesArg)
+    }
+}
+
+/// 读取entity对象的属性或dict的键构造entity
+/// 类型取`type`(pyrogram、Bot API)、`_`(telethon的`to_dict`)或对象的类名(telethon)
+fn entity_from_object(obj: &Bound<'_, PyAny>) -> PyResult<MessageEntity> {
+    if let Ok(entity) = obj.cast::<Entity>() {
+        return MessageEntity::try_from(entity.get().fields.clone()).map_err(py_err);
+    }
+    let kind = match (field(obj, "type")?, field(obj, "_")?) {
+        (Some(kind), _) | (None, Some(kind)) => kind,
+        (None, None) => obj.get_type().name()?.into_any(),
+    };
+    // pyrogram的`MessageEntityType`为枚举, 取枚举名
+    let kind = match kind.cast::<PyString>() {
+        Ok(kind) => kind.to_string(),
+        Err(_) => kind.getattr("name")?.str()?.to_string(),
+    };
+    let required = |name: &str| {
+        field(obj, name)?
+            .ok_or_else(|| {
+                py_err(Error::entity_decode(format!("missing field `{name}` for {kind}")).into())
+            })?
+            .extract::<i32>()
     };
-    gram_core::extract::username::extract_usernames(message, entities)
-        .map_err(|e| AnyhowError::new_err(e.to_string()))
+    // telethon为`user_id`, 发送时为`InputUser`对象; pyrogram和Bot API为`user`对象
+    let user_id = match field(obj, "user_id")?.or(field(obj, "user")?) {
+        Some(user) if user.cast::<PyInt>().is_ok() => Some(user.extract()?),
+        Some(user) => match field(&user, "user_id")?.or(field(&user, "id")?) {
+            Some(id) => Some(id.extract()?),
+            None => None,
+        },
+        None => None,
+    };
+    // Bot API中为字符串
+    let custom_emoji_id = match field(obj, "custom_emoji_id")?.or(field(obj, "document_id")?) {
+        Some(id) if id.cast::<PyString>().is_ok() => Some(id.to_string().parse().map_err(|e| {
+            py_err(Error::entity_decode(format!("invalid custom_emoji_id: {e}")).into())
+        })?),
+        Some(id) => Some(id.extract()?),
+        None => None,
+    };
+    let fields = EntityFields {
+        offset: required("offset")?,
+        length: required("length")?,
+        url: field(obj, "url")?.map(|x| x.extract()).transpose()?,
+        user_id,
+        language: field(obj, "language")?.map(|x| x.extract()).transpose()?,
+        custom_emoji_id,
+        collapsed: field(obj, "collapsed")?.is_some_and(|x| x.is_truthy().unwrap_or(false)),
+        kind,
+    };
+    MessageEntity::try_from(fields).map_err(py_err)
+}
+
+/// dict的键或对象的属性, 不存在或为None时返回None
+fn field<'py>(obj: &Bound<'py, PyAny>, name: &str) -> PyResult<Option<Bound<'py, PyAny>>> {
+    let ret = match obj.cast::<PyDict>() {
+        Ok(dict) => dict.get_item(name)?,
+        Err(_) => obj.getattr_opt(name)?,
+    };
+    Ok(ret.filter(|x| !x.is_none()))
+}
+
+/// Python中的`repr`
+fn py_repr<'py>(py: Python<'py>, value: impl IntoPyObject<'py>) -> PyResult<String> {
+    Ok(value.into_bound_py_any(py)?.repr()?.to_string())
+}
+
+/// 解析后的用户名链接或邀请链接
+#[pyclass(frozen, eq, hash, module = "gram_pytools")]
+#[derive(PartialEq, Eq, Hash)]
+pub struct DeepLink(deeplink::DeepLink);
+
+#[pymethods]
+impl DeepLink {
+    #[new]
+    #[pyo3(signature = (kind, username=None, params=None))]
+    /// `params`可为dict或(键, 值)列表
+    fn new(
+        kind: &str,
+        username: Option<String>,
+        params: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Self> {
+        let params = match params {
+            Some(params) => match params.cast::<PyDict>() {
+                Ok(dict) => dict.items().extract()?,
+                Err(_) => params.extract()?,
+            },
+            None => vec![],
+        };
+        Ok(Self(deeplink::DeepLink {
+            kind: kind
+                .parse()
+                .map_err(|e: anyhow::Error| PyValueError::new_err(e.to_string()))?,
+            username,
+            params,
+        }))
+    }
+
+    /// `username`或`invite`
+    #[getter]
+    fn kind(&self) -> &'static str {
+        self.0.kind.as_str()
+    }
+
+    #[getter]
+    fn username(&self) -> Option<&str> {
+        self.0.username.as_deref()
+    }
+
+    #[getter]
+    fn params<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let ret = PyDict::new(py);
+        for (k, v) in &self.0.params {
+            ret.set_item(k, v)?;
+        }
+        Ok(ret)
+    }
+
+    fn param(&self, key: &str) -> Option<&str> {
+        self.0.param(key)
+    }
+
+    fn to_url(&self) -> String {
+        self.0.to_url()
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!(
+            "DeepLink(kind={}, username={}, params={})",
+            py_repr(py, self.kind())?,
+            py_repr(py, self.username())?,
+            self.params(py)?.repr()?
+        ))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn __reduce__<'py>(
+        slf: &Bound<'py, Self>,
+    ) -> PyResult<(
+        Bound<'py, PyType>,
+        (&'static str, Option<String>, Vec<(String, String)>),
+    )> {
+        let link = &slf.get().0;
+        Ok((
+            slf.get_type(),
+            (
+                link.kind.as_str(),
+                link.username.clone(),
+                link.params.clone(),
+            ),
+        ))
+    }
+}
+
+/// 消息实体, 类型名为TL类型名的蛇形命名, 如`text_url`、`mention_name`
+#[pyclass(frozen, eq, hash, module = "gram_pytools")]
+#[derive(PartialEq, Eq, Hash)]
+pub struct Entity {
+    fields: EntityFields,
+    text: Option<String>,
+}
+
+#[pymethods]
+impl Entity {
+    #[new]
+    #[pyo3(signature = (
+        kind, offset, length, text=None, url=None, user_id=None, language=None,
+        custom_emoji_id=None, collapsed=false
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    /// `kind`支持的写法与`EntityLike`中的dict一致
+    fn new(
+        kind: String,
+        offset: i32,
+        length: i32,
+        text: Option<String>,
+        url: Option<String>,
+        user_id: Option<i64>,
+        language: Option<String>,
+        custom_emoji_id: Option<i64>,
+        collapsed: bool,
+    ) -> PyResult<Self> {
+        let fields = EntityFields {
+            kind,
+            offset,
+            length,
+            url,
+            user_id,
+            language,
+            custom_emoji_id,
+            collapsed,
+        };
+        let entity = MessageEntity::try_from(fields).map_err(py_err)?;
+        Ok(Self::from_entity(&entity, text))
+    }
+
+    #[getter]
+    fn kind(&self) -> &str {
+        &self.fields.kind
+    }
+
+    /// UTF-16偏移
+    #[getter]
+    fn offset(&self) -> i32 {
+        self.fields.offset
+    }
+
+    /// UTF-16长度
+    #[getter]
+    fn length(&self) -> i32 {
+        self.fields.length
+    }
+
+    /// entity对应的文本
+    #[getter]
+    fn text(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+
+    #[getter]
+    fn url(&self) -> Option<&str> {
+        self.fields.url.as_deref()
+    }
+
+    #[getter]
+    fn user_id(&self) -> Option<i64> {
+        self.fields.user_id
+    }
+
+    #[getter]
+    fn language(&self) -> Option<&str> {
+        self.fields.language.as_deref()
+    }
+
+    #[getter]
+    fn custom_emoji_id(&self) -> Option<i64> {
+        self.fields.custom_emoji_id
+    }
+
+    #[getter]
+    fn collapsed(&self) -> bool {
+        self.fields.collapsed
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        let mut ret = format!(
+            "Entity(kind={}, offset={}, length={}, text={}",
+            py_repr(py, self.kind())?,
+            self.offset(),
+            self.length(),
+            py_repr(py, self.text())?
+        );
+        // 只显示该类型有意义的字段
+        if let Some(url) = self.url() {
+            ret.push_str(&format!(", url={}", py_repr(py, url)?));
+        }
+        if let Some(user_id) = self.user_id() {
+            ret.push_str(&format!(", user_id={user_id}"));
+        }
+        if let Some(language) = self.language() {
+            ret.push_str(&format!(", language={}", py_repr(py, language)?));
+        }
+        if let Some(id) = self.custom_emoji_id() {
+            ret.push_str(&format!(", custom_emoji_id={id}"));
+        }
+        if self.collapsed() {
+            ret.push_str(", collapsed=True");
+        }
+        ret.push(')');
+        Ok(ret)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn __reduce__<'py>(
+        slf: &Bound<'py, Self>,
+    ) -> PyResult<(
+        Bound<'py, PyType>,
+        (
+            String,
+            i32,
+            i32,
+            Option<String>,
+            Option<String>,
+            Option<i64>,
+            Option<String>,
+            Option<i64>,
+            bool,
+        ),
+    )> {
+        let Entity { fields, text } = slf.get();
+        Ok((
+            slf.get_type(),
+            (
+                fields.kind.clone(),
+                fields.offset,
+                fields.length,
+                text.clone(),
+                fields.url.clone(),
+                fields.user_id,
+                fields.language.clone(),
+                fields.custom_emoji_id,
+                fields.collapsed,
+            ),
+        ))
+    }
+}
+
+impl Entity {
+    fn from_entity(entity: &MessageEntity, text: Option<String>) -> Self {
+        Self {
+            fields: EntityFields::from(entity),
+            text,
+        }
+    }
+}
+
+/// 带来源的提取结果
+#[pyclass(frozen, eq, hash, get_all, module = "gram_pytools")]
+#[derive(PartialEq, Eq, Hash)]
+pub struct Extraction {
+    /// `username`、`user_id`或`invite`
+    kind: String,
+    /// 用户名(不带@)、用户ID或邀请码
+    value: String,
+    /// `text`、`mention`、`mention_name`或`text_url`
+    source: String,
+    /// 来源在消息中的UTF-16偏移
+    offset: usize,
+    /// 来源在消息中的UTF-16长度
+    length: usize,
+    /// 来源为链接时的原始链接
+    link: Option<String>,
+}
+
+#[pymethods]
+impl Extraction {
+    #[new]
+    #[pyo3(signature = (kind, value, source, offset, length, link=None))]
+    fn new(
+        kind: String,
+        value: String,
+        source: String,
+        offset: usize,
+        length: usize,
+        link: Option<String>,
+    ) -> Self {
+        Self {
+            kind,
+            value,
+            source,
+            offset,
+            length,
+            link,
+        }
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!(
+            "Extraction(kind={}, value={}, source={}, offset={}, length={}, link={})",
+            py_repr(py, &self.kind)?,
+            py_repr(py, &self.value)?,
+            py_repr(py, &self.source)?,
+            self.offset,
+            self.length,
+            py_repr(py, &self.link)?
+        ))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn __reduce__<'py>(
+        slf: &Bound<'py, Self>,
+    ) -> PyResult<(
+        Bound<'py, PyType>,
+        (String, String, String, usize, usize, Option<String>),
+    )> {
+        let x = slf.get();
+        Ok((
+            slf.get_type(),
+            (
+                x.kind.clone(),
+                x.value.clone(),
+                x.source.clone(),
+                x.offset,
+                x.length,
+                x.link.clone(),
+            ),
+        ))
+    }
+}
+
+impl From<gram_core::extract::username::Extraction> for Extraction {
+    fn from(x: gram_core::extract::username::Extraction) -> Self {
+        Self {
+            kind: x.kind.to_string(),
+            value: x.value,
+            source: x.source.to_string(),
+            offset: x.offset,
+            length: x.length,
+            link: x.link,
+        }
+    }
 }
 
 #[pyfunction]
-pub fn render_text(text: String, scale: f32) -> PyResult<Vec<u8>> {
-    let vg = VecGlyph::new(&text, Scale::uniform(scale), FONTS.clone());
-    let mut img: ImageBuffer<Luma<u8>, Vec<u8>> =
-        ImageBuffer::from_pixel(vg.width() as u32, vg.height() as u32, Luma([u8::MAX]));
+/// 解析用户名链接或邀请链接
+pub fn parse_deeplink(url: &str) -> Option<DeepLink> {
+    deeplink::DeepLink::parse(url).map(DeepLink)
+}
 
-    vg.draw(|x, y, d| {
-        if let Some(pixel) = img.get_pixel_mut_checked(y, x) {
-            pixel.0[0] = (d * 255.) as u8;
+#[pyfunction]
+/// 提取文本中的所有用户名链接和邀请链接
+pub fn extract_deeplinks(py: Python<'_>, text: &str) -> PyResult<Vec<DeepLink>> {
+    task::detach(py, || {
+        let ret = deeplink::extract_links(text)
+            .into_iter()
+            .map(DeepLink)
+            .collect();
+        Ok(ret)
+    })
+}
+
+#[pyfunction]
+/// 转换为`Entity`并填充对应的文本
+pub fn extract_entities(
+    py: Python<'_>,
+    message: &str,
+    entities: EntitiesArg,
+) -> PyResult<Vec<Entity>> {
+    task::detach(py, || {
+        entities
+            .0
+            .iter()
+            .map(|entity| {
+                let text = gram_core::extract::entity::extract_entity(message, entity)?;
+                Ok(Entity::from_entity(entity, text.map(str::to_string)))
+            })
+            .collect()
+    })
+}
+
+#[pyfunction]
+#[pyo3(signature = (message, entities=None))]
+/// 提取用户名、用户ID和邀请码, 保留来源
+pub fn extract(
+    py: Python<'_>,
+    message: String,
+    entities: Option<EntitiesArg>,
+) -> PyResult<Vec<Extraction>> {
+    task::detach(py, extract_job(message, entities))
+}
+
+#[pyfunction]
+#[pyo3(signature = (message, entities=None))]
+/// `extract`的awaitable版本, 在线程池中执行
+pub fn extract_async<'py>(
+    py: Python<'py>,
+    message: String,
+    entities: Option<EntitiesArg>,
+) -> PyResult<Bound<'py, PyAny>> {
+    task::spawn(py, extract_job(message, entities))
+}
+
+fn extract_job(
+    message: String,
+    entities: Option<EntitiesArg>,
+) -> impl FnOnce() -> anyhow::Result<Vec<Extraction>> + Send + 'static {
+    move || {
+        let entities = entities.map(|x| x.0).unwrap_or_default();
+        let ret = gram_core::extract::username::extract_all(&message, &entities)
+            .into_iter()
+            .map(Extraction::from)
+            .collect();
+        Ok(ret)
+    }
+}
+
+#[pyfunction]
+#[pyo3(signature = (
+    text, scale, max_width=None, align="left", line_spacing=1.0,
+    mode="L", foreground="black", background="white", format="png"
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn render_text(
+    py: Python<'_>,
+    text: String,
+    scale: f32,
+    max_width: Option<f32>,
+    align: &str,
+    line_spacing: f32,
+    mode: &str,
+    foreground: &str,
+    background: &str,
+    format: &str,
+) -> PyResult<Vec<u8>> {
+    let options = layout_options(max_width, align, line_spacing)?;
+    let job = render_text_job(text, scale, options, mode, (foreground, background), format);
+    task::detach(py, job)
+}
+
+/// `render_text`的awaitable版本, 在线程池中执行
+#[pyfunction]
+#[pyo3(signature = (
+    text, scale, max_width=None, align="left", line_spacing=1.0,
+    mode="L", foreground="black", background="white", format="png"
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn render_text_async<'py>(
+    py: Python<'py>,
+    text: String,
+    scale: f32,
+    max_width: Option<f32>,
+    align: &str,
+    line_spacing: f32,
+    mode: &str,
+    foreground: &str,
+    background: &str,
+    format: &str,
+) -> PyResult<Bound<'py, PyAny>> {
+    let options = layout_options(max_width, align, line_spacing)?;
+    let job = render_text_job(text, scale, options, mode, (foreground, background), format);
+    task::spawn(py, job)
+}
+
+fn render_text_job(
+    text: String,
+    scale: f32,
+    options: LayoutOptions,
+    mode: &str,
+    colors: (&str, &str),
+    format: &str,
+) -> impl FnOnce() -> anyhow::Result<Vec<u8>> + Send + 'static {
+    let mode = mode.to_string();
+    let (foreground, background) = (colors.0.to_string(), colors.1.to_string());
+    let format = format.to_string();
+    move || {
+        check_fonts()?;
+        let vector = match mode.to_lowercase().as_str() {
+            "svg" => {
+                let (foreground, background) =
+                    (parse_color(&foreground)?, parse_color(&background)?);
+                let svg =
+                    gram_core::render::render_svg(&text, scale, &options, foreground, background);
+                svg.into_bytes()
+            }
+            "pdf" => {
+                let (foreground, background) =
+                    (parse_color(&foreground)?, parse_color(&background)?);
+                gram_core::render::render_pdf(&text, scale, &options, foreground, background)
+            }
+            _ => {
+                let img = render_image(&text, scale, &options, &mode, &foreground, &background)?;
+                return encode_image(&img, &format);
+            }
+        };
+        if !format.eq_ignore_ascii_case("png") {
+            let message = format!("format {format} is not supported in {mode} mode");
+            return Err(Error::Render(message).into());
         }
-    });
+        Ok(vector)
+    }
+}
+
+/// 渲染文本为未编码的像素数据, 返回(像素, 宽, 高, PIL模式名)
+/// 可直接使用`PIL.Image.frombytes`构造图片
+#[pyfunction]
+#[pyo3(signature = (
+    text, scale, max_width=None, align="left", line_spacing=1.0,
+    mode="L", foreground="black", background="white"
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn render_text_raw(
+    py: Python<'_>,
+    text: &str,
+    scale: f32,
+    max_width: Option<f32>,
+    align: &str,
+    line_spacing: f32,
+    mode: &str,
+    foreground: &str,
+    background: &str,
+) -> PyResult<(Vec<u8>, u32, u32, &'static str)> {
+    let img = render_text_buffer(
+        py,
+        text,
+        scale,
+        max_width,
+        align,
+        line_spacing,
+        mode,
+        foreground,
+        background,
+    )?;
+    Ok((img.data, img.width, img.height, img.mode))
+}
+
+/// 渲染文本为支持缓冲区协议的`RawImage`, 可被`numpy`零拷贝读取
+#[pyfunction]
+#[pyo3(signature = (
+    text, scale, max_width=None, align="left", line_spacing=1.0,
+    mode="L", foreground="black", background="white"
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn render_text_buffer(
+    py: Python<'_>,
+    text: &str,
+    scale: f32,
+    max_width: Option<f32>,
+    align: &str,
+    line_spacing: f32,
+    mode: &str,
+    foreground: &str,
+    background: &str,
+) -> PyResult<RawImage> {
+    let options = layout_options(max_width, align, line_spacing)?;
+    task::detach(py, || {
+        check_fonts()?;
+        let img = render_image(text, scale, &options, mode, foreground, background)?;
+        Ok(RawImage::new(img))
+    })
+}
+
+fn layout_options(
+    max_width: Option<f32>,
+    align: &str,
+    line_spacing: f32,
+) -> PyResult<LayoutOptions> {
+    Ok(LayoutOptions {
+        max_width,
+        line_spacing,
+        align: align.parse::<Align>().map_err(py_err)?,
+        ..Default::default()
+    })
+}
+
+/// 按`L`或`rgba`模式渲染位图
+fn render_image(
+    text: &str,
+    scale: f32,
+    options: &LayoutOptions,
+    mode: &str,
+    foreground: &str,
+    background: &str,
+) -> anyhow::Result<DynamicImage> {
+    match mode.to_lowercase().as_str() {
+        "l" => Ok(gram_core::render::render_text(text, scale, options).into()),
+        "rgba" => Ok(gram_core::render::render_text_rgba(
+            text,
+            scale,
+            options,
+            parse_color(foreground)?,
+            parse_color(background)?,
+        )
+        .into()),
+        _ => Err(Error::Render(format!("unknown mode: {mode}")).into()),
+    }
+}
+
+/// 将图片编码为`png`、`webp`、`jpeg`或`bmp`格式
+/// JPEG不支持透明度, RGBA图片的alpha通道会被丢弃; WebP为无损压缩
+fn encode_image(img: &DynamicImage, format: &str) -> anyhow::Result<Vec<u8>> {
+    let format = match format.to_lowercase().as_str() {
+        "png" => ImageFormat::Png,
+        "webp" => ImageFormat::WebP,
+        "jpeg" | "jpg" => ImageFormat::Jpeg,
+        "bmp" => ImageFormat::Bmp,
+        _ => return Err(Error::Render(format!("unknown format: {format}")).into()),
+    };
     let mut ret = Vec::new();
-    img.write_to(&mut Cursor::new(&mut ret), image::ImageFormat::Png)
-        .unwrap();
+    let result = match (format, img) {
+        (ImageFormat::Jpeg, DynamicImage::ImageRgba8(_)) => {
+            DynamicImage::from(img.to_rgb8()).write_to(&mut Cursor::new(&mut ret), format)
+        }
+        _ => img.write_to(&mut Cursor::new(&mut ret), format),
+    };
+    result.map_err(|e| Error::Render(e.to_string()))?;
     Ok(ret)
 }
+
+/// 未编码的图片像素, 按行优先排列, 每像素1字节(`L`)或4字节(`RGBA`)
+/// 实现了缓冲区协议, `numpy.asarray`得到形状为(高, 宽)或(高, 宽, 4)的数组,
+/// `numpy.frombuffer`得到一维数组, 均不复制数据
+#[pyclass(frozen)]
+pub struct RawImage {
+    data: Vec<u8>,
+    #[pyo3(get)]
+    width: u32,
+    #[pyo3(get)]
+    height: u32,
+    /// PIL的模式名, 为`L`或`RGBA`
+    #[pyo3(get)]
+    mode: &'static str,
+    shape: [ffi::Py_ssize_t; 3],
+    strides: [ffi::Py_ssize_t; 3],
+}
+
+impl RawImage {
+    fn new(img: DynamicImage) -> Self {
+        let (width, height) = (img.width(), img.height());
+        let (mode, channels, data) = match img {
+            DynamicImage::ImageLuma8(img) => ("L", 1, img.into_raw()),
+            img => ("RGBA", 4, img.into_rgba8().into_raw()),
+        };
+        let row = width as ffi::Py_ssize_t * channels;
+        Self {
+            data,
+            width,
+            height,
+            mode,
+            shape: [height as _, width as _, channels],
+            strides: [row, channels, 1],
+        }
+    }
+}
+
+#[pymethods]
+impl RawImage {
+    /// 复制像素数据为`bytes`
+    fn tobytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.data)
+    }
+
+    fn __len__(&self) -> usize {
+        self.data.len()
+    }
+
+    unsafe fn __getbuffer__(
+        slf: Bound<'_, Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        if view.is_null() {
+            return Err(PyBufferError::new_err("view is null"));
+        }
+        if flags & ffi::PyBUF_WRITABLE == ffi::PyBUF_WRITABLE {
+            return Err(PyBufferError::new_err("RawImage is read-only"));
+        }
+        let this = slf.get();
+        let ndim = if this.mode == "L" { 2 } else { 3 };
+        (*view).buf = this.data.as_ptr() as *mut c_void;
+        (*view).len = this.data.len() as ffi::Py_ssize_t;
+        (*view).readonly = 1;
+        (*view).itemsize = 1;
+        (*view).format = if flags & ffi::PyBUF_FORMAT == ffi::PyBUF_FORMAT {
+            c"B".as_ptr() as *mut _
+        } else {
+            ptr::null_mut()
+        };
+        // 未请求形状时视为一维的字节数组
+        if flags & ffi::PyBUF_ND == ffi::PyBUF_ND {
+            (*view).ndim = ndim;
+            (*view).shape = this.shape.as_ptr() as *mut _;
+        } else {
+            (*view).ndim = 1;
+            (*view).shape = ptr::null_mut();
+        }
+        (*view).strides = if flags & ffi::PyBUF_STRIDES == ffi::PyBUF_STRIDES {
+            this.strides.as_ptr() as *mut _
+        } else {
+            ptr::null_mut()
+        };
+        (*view).suboffsets = ptr::null_mut();
+        (*view).internal = ptr::null_mut();
+        (*view).obj = slf.into_any().into_ptr();
+        Ok(())
+    }
+
+    unsafe fn __releasebuffer__(&self, _view: *mut ffi::Py_buffer) {}
+}
+
+/// 按entity渲染带格式的消息, 返回PNG格式的RGBA图片
+#[pyfunction]
+#[pyo3(signature = (
+    text, entities_json=None, scale=32.0, max_width=None, line_spacing=1.0,
+    spoiler="blackout", foreground="black", background="white", link_color="#2481cc"
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn render_message(
+    py: Python<'_>,
+    text: String,
+    entities_json: Option<EntitiesArg>,
+    scale: f32,
+    max_width: Option<f32>,
+    line_spacing: f32,
+    spoiler: &str,
+    foreground: &str,
+    background: &str,
+    link_color: &str,
+) -> PyResult<Vec<u8>> {
+    let colors = (foreground, background, link_color);
+    let options = rich_options(scale, max_width, line_spacing, spoiler, colors)?;
+    task::detach(py, render_message_job(text, entities_json, options))
+}
+
+/// `render_message`的awaitable版本, 在线程池中执行
+#[pyfunction]
+#[pyo3(signature = (
+    text, entities_json=None, scale=32.0, max_width=None, line_spacing=1.0,
+    spoiler="blackout", foreground="black", background="white", link_color="#2481cc"
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn render_message_async<'py>(
+    py: Python<'py>,
+    text: String,
+    entities_json: Option<EntitiesArg>,
+    scale: f32,
+    max_width: Option<f32>,
+    line_spacing: f32,
+    spoiler: &str,
+    foreground: &str,
+    background: &str,
+    link_color: &str,
+) -> PyResult<Bound<'py, PyAny>> {
+    let colors = (foreground, background, link_color);
+    let options = rich_options(scale, max_width, line_spacing, spoiler, colors)?;
+    task::spawn(py, render_message_job(text, entities_json, options))
+}
+
+/// `colors`为(前景色, 背景色, 链接颜色)
+fn rich_options(
+    scale: f32,
+    max_width: Option<f32>,
+    line_spacing: f32,
+    spoiler: &str,
+    colors: (&str, &str, &str),
+) -> PyResult<RichOptions> {
+    let color = |s: &str| parse_color(s).map_err(py_err);
+    Ok(RichOptions {
+        scale,
+        max_width,
+        line_spacing,
+        foreground: color(colors.0)?,
+        background: color(colors.1)?,
+        link_color: color(colors.2)?,
+        spoiler: spoiler.parse::<SpoilerMode>().map_err(py_err)?,
+        ..Default::default()
+    })
+}
+
+fn render_message_job(
+    text: String,
+    entities: Option<EntitiesArg>,
+    options: RichOptions,
+) -> impl FnOnce() -> anyhow::Result<Vec<u8>> + Send + 'static {
+    move || {
+        let entities = entities.map(|x| x.0).unwrap_or_default();
+        check_fonts()?;
+        let img = gram_core::render::rich::render_message(&text, &entities, &options)?;
+        encode_image(&img.into(), "png")
+    }
+}
+
+/// 生成验证码, 返回(PNG格式的灰度图, 答案); 未指定答案时随机生成
+#[pyfunction]
+#[pyo3(signature = (
+    answer=None, length=5, scale=48.0, seed=None, mix_fonts=true, confusables=false,
+    max_rotation=25.0, warp=0.06, noise_lines=3, noise_dots=200
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn render_captcha(
+    py: Python<'_>,
+    answer: Option<String>,
+    length: usize,
+    scale: f32,
+    seed: Option<u64>,
+    mix_fonts: bool,
+    confusables: bool,
+    max_rotation: f32,
+    warp: f32,
+    noise_lines: usize,
+    noise_dots: usize,
+) -> PyResult<(Vec<u8>, String)> {
+    let options = CaptchaOptions {
+        scale,
+        length,
+        max_rotation,
+        warp,
+        noise_lines,
+        noise_dots,
+        mix_fonts,
+        confusables,
+        seed,
+        ..Default::default()
+    };
+    task::detach(py, render_captcha_job(answer, options))
+}
+
+/// `render_captcha`的awaitable版本, 在线程池中执行
+#[pyfunction]
+#[pyo3(signature = (
+    answer=None, length=5, scale=48.0, seed=None, mix_fonts=true, confusables=false,
+    max_rotation=25.0, warp=0.06, noise_lines=3, noise_dots=200
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn render_captcha_async<'py>(
+    py: Python<'py>,
+    answer: Option<String>,
+    length: usize,
+    scale: f32,
+    seed: Option<u64>,
+    mix_fonts: bool,
+    confusables: bool,
+    max_rotation: f32,
+    warp: f32,
+    noise_lines: usize,
+    noise_dots: usize,
+) -> PyResult<Bound<'py, PyAny>> {
+    let options = CaptchaOptions {
+        scale,
+        length,
+        max_rotation,
+        warp,
+        noise_lines,
+        noise_dots,
+        mix_fonts,
+        confusables,
+        seed,
+        ..Default::default()
+    };
+    task::spawn(py, render_captcha_job(answer, options))
+}
+
+fn render_captcha_job(
+    answer: Option<String>,
+    options: CaptchaOptions,
+) -> impl FnOnce() -> anyhow::Result<(Vec<u8>, String)> + Send + 'static {
+    move || {
+        check_fonts()?;
+        let captcha = match answer {
+            Some(answer) => gram_core::render::captcha::render_captcha(&answer, &options)?,
+            None => gram_core::render::captcha::generate(&options)?,
+        };
+        let img = encode_image(&captcha.image.into(), "png")?;
+        Ok((img, captcha.answer))
+    }
+}
+
+/// 渲染没有头像的用户的占位头像, 返回PNG等格式的RGBA图片
+#[pyfunction]
+#[pyo3(signature = (name, peer_id, size=160, format="png"))]
+pub fn render_avatar(
+    py: Python<'_>,
+    name: String,
+    peer_id: i64,
+    size: u32,
+    format: &str,
+) -> PyResult<Vec<u8>> {
+    task::detach(py, render_avatar_job(name, peer_id, size, format))
+}
+
+/// `render_avatar`的awaitable版本, 在线程池中执行
+#[pyfunction]
+#[pyo3(signature = (name, peer_id, size=160, format="png"))]
+pub fn render_avatar_async<'py>(
+    py: Python<'py>,
+    name: String,
+    peer_id: i64,
+    size: u32,
+    format: &str,
+) -> PyResult<Bound<'py, PyAny>> {
+    task::spawn(py, render_avatar_job(name, peer_id, size, format))
+}
+
+fn render_avatar_job(
+    name: String,
+    peer_id: i64,
+    size: u32,
+    format: &str,
+) -> impl FnOnce() -> anyhow::Result<Vec<u8>> + Send + 'static {
+    let format = format.to_string();
+    move || {
+        check_fonts()?;
+        let img = gram_core::render::avatar::avatar(&name, peer_id, size);
+        encode_image(&img.into(), &format)
+    }
+}
+
+/// 检查验证码输入, 忽略大小写和空白, 西里尔字母视为外形相同的拉丁字母
+#[pyfunction]
+pub fn check_captcha(answer: &str, input: &str) -> bool {
+    gram_core::render::captcha::check_answer(answer, input)
+}
+
+/// 文本的测量结果
+#[pyclass(frozen, get_all)]
+pub struct TextMetrics {
+    width: f32,
+    height: f32,
+    ascent: f32,
+    descent: f32,
+    line_count: usize,
+    /// (字素簇, 行下标, 起笔点横坐标, 步进宽度)
+    graphemes: Vec<(String, usize, f32, f32)>,
+    missing_glyphs: usize,
+}
+
+/// 测量文本排版后的尺寸, 不绘制图片; 参数与`render_text`一致
+#[pyfunction]
+#[pyo3(signature = (text, scale, max_width=None, align="left", line_spacing=1.0))]
+pub fn measure_text(
+    py: Python<'_>,
+    text: &str,
+    scale: f32,
+    max_width: Option<f32>,
+    align: &str,
+    line_spacing: f32,
+) -> PyResult<TextMetrics> {
+    let options = layout_options(max_width, align, line_spacing)?;
+    let metrics = task::detach(py, || {
+        check_fonts()?;
+        Ok(gram_core::render::measure(text, scale, &options))
+    })?;
+    Ok(TextMetrics {
+        width: metrics.width,
+        height: metrics.height,
+        ascent: metrics.ascent,
+        descent: metrics.descent,
+        line_count: metrics.line_count,
+        graphemes: metrics
+            .graphemes
+            .into_iter()
+            .map(|g| (g.text, g.line, g.x, g.advance))
+            .collect(),
+        missing_glyphs: metrics.missing_glyphs,
+    })
+}
+
+/// 文本中每个字素簇使用的字体, 返回(字素簇, 字体族名, 缺字的码位)列表
+#[pyfunction]
+pub fn font_coverage(py: Python<'_>, text: &str) -> PyResult<Vec<(String, String, Vec<u32>)>> {
+    task::detach(py, || {
+        let ret = gram_core::render::coverage(text)
+            .graphemes
+            .into_iter()
+            .map(|g| {
+                (
+                    g.text,
+                    g.font,
+                    g.missing.into_iter().map(u32::from).collect(),
+                )
+            })
+            .collect();
+        Ok(ret)
+    })
+}
+
+/// 加载字体文件或目录到全局字体注册表, 返回加载的字体数量
+#[pyfunction]
+pub fn load_fonts(py: Python<'_>, path: std::path::PathBuf) -> PyResult<usize> {
+    task::detach(py, || {
+        let mut registry = FontRegistry::write_global();
+        if path.is_dir() {
+            registry.load_dir(&path)
+        } else {
+            registry.load_file(&path)
+        }
+    })
+}
+
+/// 为脚本或语言指定优先使用的字体族
+#[pyfunction]
+pub fn set_font_priority(py: Python<'_>, key: &str, families: Vec<String>) -> PyResult<()> {
+    task::detach(py, || {
+        FontRegistry::write_global().set_priority(key, &families);
+        Ok(())
+    })
+}
+
+/// 设置文本语言, 该语言的优先字体族适用于所有字符
+#[pyfunction]
+#[pyo3(signature = (language=None))]
+pub fn set_font_language(py: Python<'_>, language: Option<&str>) -> PyResult<()> {
+    task::detach(py, || {
+        FontRegistry::write_global().set_language(language);
+        Ok(())
+    })
+}
+
+/// 垃圾消息评分器
+/// 规则配置格式参见`gram_core::spam::SpamConfig`
+#[pyclass]
+pub struct SpamScorer(gram_core::spam::SpamScorer);
+
+#[pymethods]
+impl SpamScorer {
+    #[new]
+    #[pyo3(signature = (config=None))]
+    /// 使用TOML格式的配置创建评分器, 未提供配置时使用默认规则
+    fn new(config: Option<&str>) -> PyResult<Self> {
+        let config = match config {
+            Some(config) => gram_core::spam::SpamConfig::from_toml(config),
+            None => Ok(Default::default()),
+        }
+        .map_err(py_err)?;
+        Self::from_config(config)
+    }
+
+    #[staticmethod]
+    fn from_json(config: &str) -> PyResult<Self> {
+        let config = gram_core::spam::SpamConfig::from_json(config).map_err(py_err)?;
+        Self::from_config(config)
+    }
+
+    #[staticmethod]
+    fn from_path(path: &str) -> PyResult<Self> {
+        let config = gram_core::spam::SpamConfig::from_path(path).map_err(py_err)?;
+        Self::from_config(config)
+    }
+
+    #[pyo3(signature = (message, entities=None))]
+    /// 兼容telethon
+    fn score(
+        &self,
+        py: Python<'_>,
+        message: &str,
+        entities: Option<EntitiesArg>,
+    ) -> PyResult<(f64, bool, Vec<String>)> {
+        task::detach(py, || {
+            let report = self.0.score(message, entities.as_ref().map(|x| &x.0[..]))?;
+            let fired = report.fired.into_iter().map(|x| x.name).collect();
+            Ok((report.score, report.is_spam, fired))
+        })
+    }
+}
+
+impl SpamScorer {
+    fn from_config(config: gram_core::spam::SpamConfig) -> PyResult<Self> {
+        let scorer = gram_core::spam::SpamScorer::new(config).map_err(py_err)?;
+        Ok(Self(scorer))
+    }
+}
+
+/// 在释放GIL后调用
+fn normalize_message(message: &str, entities: Option<EntitiesArg>) -> String {
+    let entities = entities.map(|x| x.0).unwrap_or_default();
+    gram_core::dedup::normalize(message, &entities)
+}
+
+#[pyfunction]
+#[pyo3(signature = (message, entities=None))]
+/// 兼容telethon
+pub fn normalize_text(
+    py: Python<'_>,
+    message: &str,
+    entities: Option<EntitiesArg>,
+) -> PyResult<String> {
+    task::detach(py, || Ok(normalize_message(message, entities)))
+}
+
+#[pyfunction]
+#[pyo3(signature = (message, entities=None))]
+/// 兼容telethon
+pub fn simhash(py: Python<'_>, message: &str, entities: Option<EntitiesArg>) -> PyResult<u64> {
+    task::detach(py, || {
+        let normalized = normalize_message(message, entities);
+        Ok(gram_core::dedup::SimHash::new(&normalized).0)
+    })
+}
+
+#[pyfunction]
+#[pyo3(signature = (message, entities=None))]
+/// 兼容telethon
+pub fn minhash(py: Python<'_>, message: &str, entities: Option<EntitiesArg>) -> PyResult<Vec<u64>> {
+    task::detach(py, || {
+        let normalized = normalize_message(message, entities);
+        Ok(gram_core::dedup::MinHash::new(&normalized).0)
+    })
+}
+
+/// 近似重复消息索引
+#[pyclass]
+pub struct DedupIndex(gram_core::dedup::LshIndex);
+
+#[pymethods]
+impl DedupIndex {
+    #[new]
+    #[pyo3(signature = (threshold=0.9))]
+    fn new(threshold: f64) -> PyResult<Self> {
+        gram_core::dedup::LshIndex::new(threshold)
+            .map(Self)
+            .map_err(py_err)
+    }
+
+    #[pyo3(signature = (id, message, entities=None))]
+    /// 兼容telethon
+    fn insert(
+        &mut self,
+        py: Python<'_>,
+        id: u64,
+        message: &str,
+        entities: Option<EntitiesArg>,
+    ) -> PyResult<()> {
+        task::detach(py, || {
+            let normalized = normalize_message(message, entities);
+            self.0.insert(id, &normalized);
+            Ok(())
+        })
+    }
+
+    fn remove(&mut self, id: u64) -> bool {
+        self.0.remove(id).is_some()
+    }
+
+    #[pyo3(signature = (message, entities=None))]
+    /// 兼容telethon
+    fn query(
+        &self,
+        py: Python<'_>,
+        message: &str,
+        entities: Option<EntitiesArg>,
+    ) -> PyResult<Vec<(u64, f64)>> {
+        task::detach(py, || {
+            let normalized = normalize_message(message, entities);
+            Ok(self.0.query(&normalized))
+        })
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ColorType, GenericImageView, GrayImage, Luma, Rgba, RgbaImage};
+    use pyo3::py_run;
+    use std::sync::mpsc::{self, RecvTimeoutError};
+    use std::time::Duration;
+
+    /// 初始化内嵌的解释器并获取GIL, Python代码中可`import gram_pytools`
+    pub(crate) fn attach<R>(f: impl for<'py> FnOnce(Python<'py>) -> R) -> R {
+        Python::initialize();
+        Python::attach(|py| {
+            let modules = py.import("sys").unwrap().getattr("modules").unwrap();
+            if !modules.contains("gram_pytools").unwrap() {
+                let module = PyModule::new(py, "gram_pytools").unwrap();
+                gram_pytools(&module).unwrap();
+                modules.set_item("gram_pytools", module).unwrap();
+            }
+            f(py)
+        })
+    }
+
+    /// 按`flags`请求缓冲区, 返回(维数, 形状, 步长, 字节数)
+    fn buffer(
+        obj: &Bound<'_, PyAny>,
+        flags: c_int,
+    ) -> PyResult<(c_int, Vec<isize>, Vec<isize>, isize)> {
+        let mut view = std::mem::MaybeUninit::<ffi::Py_buffer>::uninit();
+        unsafe {
+            if ffi::PyObject_GetBuffer(obj.as_ptr(), view.as_mut_ptr(), flags) == -1 {
+                return Err(PyErr::fetch(obj.py()));
+            }
+            let view = view.assume_init_mut();
+            assert_eq!(view.readonly, 1);
+            let read = |p: *mut ffi::Py_ssize_t| match p.is_null() {
+                true => vec![],
+                false => std::slice::from_raw_parts(p, view.ndim as usize).to_vec(),
+            };
+            let ret = (view.ndim, read(view.shape), read(view.strides), view.len);
+            ffi::PyBuffer_Release(view);
+            Ok(ret)
+        }
+    }
+
+    #[test]
+    fn test_py_err() {
+        attach(|py| {
+            let attr = |err: &PyErr, name: &str| -> usize {
+                err.value(py).getattr(name).unwrap().extract().unwrap()
+            };
+
+            // 解析参数中的JSON出错时带有出错的位置
+            let json = PyString::new(py, "[\n  {");
+            let err = json.extract::<EntitiesArg>().err().unwrap();
+            assert!(err.is_instance_of::<EntityDecodeError>(py));
+            assert!(err.is_instance_of::<GramError>(py));
+            assert_eq!((attr(&err, "line"), attr(&err, "column")), (2, 3));
+
+            let err = Error::Offset {
+                offset: 3,
+                length: 5,
+                index: 8,
+                text_length: 5,
+            };
+            // 附加了上下文的错误仍按类型转换, 消息包含上下文
+            let err = py_err(anyhow::Error::from(err).context("entity 0"));
+            assert!(err.is_instance_of::<OffsetError>(py));
+            assert!(err
+                .to_string()
+                .contains("entity 0: invalid utf16 range 3..8"));
+            let attrs = ["offset", "length", "index", "text_length"].map(|x| attr(&err, x));
+            assert_eq!(attrs, [3, 5, 8, 5]);
+
+            let kind = "messageEntityUnknown".to_string();
+            let err = py_err(Error::UnsupportedEntity { kind: kind.clone() }.into());
+            assert!(err.is_instance_of::<UnsupportedEntityError>(py));
+            let value = err.value(py).getattr("kind").unwrap();
+            assert_eq!(value.extract::<String>().unwrap(), kind);
+
+            let err = py_err(Error::Render("unknown mode: cmyk".into()).into());
+            assert!(err.is_instance_of::<RenderError>(py));
+            assert!(err.is_instance_of::<GramError>(py));
+
+            // 未分类的错误为基类
+            let err = py_err(anyhow::anyhow!("boom"));
+            assert!(err.get_type(py).is(py.get_type::<GramError>()));
+        });
+    }
+
+    #[test]
+    fn test_entities_arg() {
+        // 评分和去重直接接受entity对象或dict的列表, 结果与telethon格式的JSON一致
+        attach(|py| {
+            let text = "join now at t.me/casino_bonus";
+            py_run!(
+                py,
+                text,
+                r#"
+                import json
+                import gram_pytools as g
+                entities = [{"type": "url", "offset": 12, "length": 17}]
+                as_json = json.dumps([{"_": "MessageEntityUrl", "offset": 12, "length": 17}])
+                assert g.simhash(text, entities) == g.simhash(text, as_json)
+                assert g.simhash(text, entities) != g.simhash(text)
+                assert g.minhash(text, entities) == g.minhash(text, as_json)
+                assert g.normalize_text(text, entities) == g.normalize_text(text, as_json)
+                scorer = g.SpamScorer()
+                assert scorer.score(text, entities) == scorer.score(text, as_json)
+                index = g.DedupIndex(0.8)
+                index.insert(1, text, entities)
+                assert index.query(text, as_json)[0][0] == 1
+            "#
+            );
+        });
+    }
+
+    #[test]
+    fn test_registry_lock() {
+        // 渲染线程持有注册表的读锁时产生日志, 不应与持有GIL修改注册表的线程死锁
+        crate::logging::tests::install();
+        let (tx, rx) = mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            attach(|py| {
+                py_run!(
+                    py,
+                    *PyDict::new(py),
+                    r#"
+                    async def main():
+                        import asyncio, gram_pytools
+                        # 缺字时产生DEBUG日志
+                        renders = [gram_pytools.render_text_async("hi \ue000", 32) for _ in range(16)]
+                        for _ in range(100):
+                            gram_pytools.set_font_priority("Latn", ["DejaVu Sans"])
+                            gram_pytools.set_font_language(None)
+                            await asyncio.sleep(0)
+                        for png in await asyncio.gather(*renders):
+                            assert png.startswith(b"\x89PNG")
+
+                    import asyncio
+                    asyncio.run(main())
+                "#
+                )
+            });
+            tx.send(()).unwrap();
+        });
+        let result = rx.recv_timeout(Duration::from_secs(60));
+        assert!(
+            !matches!(result, Err(RecvTimeoutError::Timeout)),
+            "deadlock"
+        );
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_raw_image() {
+        attach(|py| {
+            let render = |mode| {
+                let raw =
+                    render_text_buffer(py, "hi", 32., None, "left", 1., mode, "black", "white");
+                Bound::new(py, raw.unwrap()).unwrap().into_any()
+            };
+            let raw = render("L");
+            let (w, h): (isize, isize) = (
+                raw.getattr("width").unwrap().extract().unwrap(),
+                raw.getattr("height").unwrap().extract().unwrap(),
+            );
+            // `numpy.asarray`请求带步长和格式的缓冲区
+            assert_eq!(
+                buffer(&raw, ffi::PyBUF_RECORDS_RO).unwrap(),
+                (2, vec![h, w], vec![w, 1], w * h)
+            );
+            // 不请求形状时为一维的字节数组, 如`numpy.frombuffer`
+            assert_eq!(
+                buffer(&raw, ffi::PyBUF_SIMPLE).unwrap(),
+                (1, vec![], vec![], w * h)
+            );
+            let err = buffer(&raw, ffi::PyBUF_WRITABLE).unwrap_err();
+            assert!(err.is_instance_of::<PyBufferError>(py));
+            py_run!(py, raw w h, r#"
+                view = memoryview(raw)
+                assert view.readonly and view.format == "B"
+                assert view.shape == (h, w) and view.strides == (w, 1)
+                assert view.tobytes() == raw.tobytes() == bytes(raw)
+                assert len(raw) == w * h and raw.mode == "L"
+            "#);
+
+            let raw = render("rgba");
+            let expected = (3, vec![h, w, 4], vec![w * 4, 4, 1], w * h * 4);
+            assert_eq!(buffer(&raw, ffi::PyBUF_RECORDS_RO).unwrap(), expected);
+            assert_eq!(buffer(&raw, ffi::PyBUF_SIMPLE).unwrap().0, 1);
+            py_run!(
+                py,
+                raw,
+                r#"
+                view = memoryview(raw)
+                assert view.shape[2] == 4 and raw.mode == "RGBA"
+                assert view[0, 0, 3] == 255
+            "#
+            );
+        });
+    }
+
+    #[test]
+    fn test_encode_image() {
+        let gray =
+            DynamicImage::ImageLuma8(GrayImage::from_fn(4, 3, |x, y| Luma([(x * 60 + y) as u8])));
+        let rgba = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 3, Rgba([255, 0, 0, 128])));
+        let formats = [
+            ("png", ImageFormat::Png),
+            ("webp", ImageFormat::WebP),
+            ("JPG", ImageFormat::Jpeg),
+            ("bmp", ImageFormat::Bmp),
+        ];
+        for (name, format) in formats {
+            for img in [&gray, &rgba] {
+                let data = encode_image(img, name).unwrap();
+                assert_eq!(image::guess_format(&data).unwrap(), format);
+                assert_eq!(image::load_from_memory(&data).unwrap().dimensions(), (4, 3));
+            }
+        }
+        // JPEG丢弃alpha通道, WebP为无损压缩
+        let jpeg = image::load_from_memory(&encode_image(&rgba, "jpeg").unwrap()).unwrap();
+        assert_eq!(jpeg.color(), ColorType::Rgb8);
+        let webp = image::load_from_memory(&encode_image(&rgba, "webp").unwrap()).unwrap();
+        assert_eq!(webp.to_rgba8(), rgba.to_rgba8());
+        let webp = image::load_from_memory(&encode_image(&gray, "webp").unwrap()).unwrap();
+        assert_eq!(webp.to_luma8(), gray.to_luma8());
+
+        let err = encode_image(&gray, "gif").unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::Render(_))));
+    }
+}
@@ -0,0 +1,399 @@
+use crate::GramError;
+use pyo3::exceptions::PyValueError;
+use pyo3::ffi;
+use pyo3::prelude::*;
+use pyo3::types::{PyInt, PyTuple};
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Condvar, Mutex, Once, OnceLock, PoisonError};
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Python`logging`的日志级别, TRACE不是内置级别, 由`setup_logging`注册
+const TRACE: i32 = 5;
+const DEBUG: i32 = 10;
+const INFO: i32 = 20;
+const WARNING: i32 = 30;
+const ERROR: i32 = 40;
+
+/// 转发的最低级别, 为Python的日志级别
+static LEVEL: AtomicI32 = AtomicI32::new(WARNING);
+
+/// 是否成功安装了全局subscriber
+static INSTALLED: OnceLock<bool> = OnceLock::new();
+
+/// 尚未交给Python的日志记录
+/// 事件可能发生在持有字体注册表等锁的线程中, 若在此等待GIL, 会与持有GIL并等待同一把锁的线程死锁;
+/// 因此只有已持有GIL的线程直接转发, 其余线程的记录由后台线程获取GIL后转发
+static QUEUE: Mutex<VecDeque<LogRecord>> = Mutex::new(VecDeque::new());
+static PENDING: Condvar = Condvar::new();
+static FORWARDER: Once = Once::new();
+
+/// 待转发的日志记录, 文件名和行号为Rust代码中的位置
+struct LogRecord {
+    name: String,
+    level: i32,
+    file: &'static str,
+    line: u32,
+    message: String,
+}
+
+fn python_level(level: &Level) -> i32 {
+    match *level {
+        Level::TRACE => TRACE,
+        Level::DEBUG => DEBUG,
+        Level::INFO => INFO,
+        Level::WARN => WARNING,
+        Level::ERROR => ERROR,
+    }
+}
+
+/// 将target映射为logger名称, 如`gram_core::render::glyph`为`gram_core.render.glyph`
+fn logger_name(target: &str) -> String {
+    target.replace("::", ".")
+}
+
+/// 将tracing的事件转发到Python的`logging`
+/// 事件所在的span及其字段作为消息前缀, 格式与`tracing_subscriber::fmt`一致
+pub struct PyLoggingLayer;
+
+impl<S> Layer<S> for PyLoggingLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: Context<'_, S>) -> bool {
+        python_level(metadata.level()) >= LEVEL.load(Ordering::Relaxed)
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        let level = LEVEL.load(Ordering::Relaxed);
+        let filters = [
+            (TRACE, LevelFilter::TRACE),
+            (DEBUG, LevelFilter::DEBUG),
+            (INFO, LevelFilter::INFO),
+            (WARNING, LevelFilter::WARN),
+            (ERROR, LevelFilter::ERROR),
+        ];
+        let ret = filters
+            .into_iter()
+            .find(|(x, _)| level <= *x)
+            .map_or(LevelFilter::OFF, |(_, filter)| filter);
+        Some(ret)
+    }
+
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(fields);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(fields) = span.extensions_mut().get_mut::<Fields>() {
+                values.record(fields);
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut message = String::new();
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                message.push_str(span.name());
+                match span.extensions().get::<Fields>() {
+                    Some(fields) if !fields.rest.is_empty() => {
+                        write!(message, "{{{}}}", fields.rest).unwrap();
+                    }
+                    _ => {}
+                }
+                message.push_str(": ");
+            }
+        }
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        message.push_str(&fields.message);
+        if !fields.rest.is_empty() {
+            if !fields.message.is_empty() {
+                message.push(' ');
+            }
+            message.push_str(&fields.rest);
+        }
+
+        let metadata = event.metadata();
+        let record = LogRecord {
+            name: logger_name(metadata.target()),
+            level: python_level(metadata.level()),
+            file: metadata.file().unwrap_or("(unknown file)"),
+            line: metadata.line().unwrap_or(0),
+            message,
+        };
+        queue().push_back(record);
+        // SAFETY: 只查询当前线程的状态, 解释器已初始化
+        if unsafe { ffi::Py_IsInitialized() != 0 && ffi::PyGILState_Check() == 1 } {
+            Python::attach(flush);
+        } else {
+            FORWARDER.call_once(|| {
+                std::thread::Builder::new()
+                    .name("gram-logging".into())
+                    .spawn(forward)
+                    .expect("failed to spawn the logging thread");
+            });
+            PENDING.notify_one();
+        }
+    }
+}
+
+fn queue() -> std::sync::MutexGuard<'static, VecDeque<LogRecord>> {
+    QUEUE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// 将队列中的记录交给Python的`logging`, 需持有GIL
+/// 释放GIL执行任务后也会调用, 使同步调用返回前可以看到其中的日志
+pub fn flush(py: Python<'_>) {
+    // 每次只取一条, 转发时不持有队列的锁, Python的handler中可以再产生日志
+    while let Some(record) = queue().pop_front() {
+        if let Err(e) = emit(py, record) {
+            e.write_unraisable(py, None);
+        }
+    }
+}
+
+/// 后台线程, 等待其他线程产生的记录并获取GIL转发
+fn forward() {
+    loop {
+        let mut pending = queue();
+        while pending.is_empty() {
+            pending = PENDING
+                .wait(pending)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        drop(pending);
+        // 解释器正在退出时丢弃
+        if Python::try_attach(flush).is_none() {
+            queue().clear();
+        }
+    }
+}
+
+/// 构造`LogRecord`并交给对应的logger
+fn emit(py: Python<'_>, record: LogRecord) -> PyResult<()> {
+    let logger = py
+        .import("logging")?
+        .call_method1("getLogger", (&record.name,))?;
+    if !logger
+        .call_method1("isEnabledFor", (record.level,))?
+        .is_truthy()?
+    {
+        return Ok(());
+    }
+    let record = logger.call_method1(
+        "makeRecord",
+        (
+            record.name,
+            record.level,
+            record.file,
+            record.line,
+            record.message,
+            PyTuple::empty(py),
+            py.None(),
+        ),
+    )?;
+    logger.call_method1("handle", (record,))?;
+    Ok(())
+}
+
+/// 事件的`message`字段和其他字段, 其他字段格式为`key=value`, 以空格分隔
+#[derive(Default)]
+struct Fields {
+    message: String,
+    rest: String,
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            write!(self.message, "{value:?}").unwrap();
+            return;
+        }
+        if !self.rest.is_empty() {
+            self.rest.push(' ');
+        }
+        write!(self.rest, "{}={value:?}", field.name()).unwrap();
+    }
+}
+
+/// 日志级别参数, 可为整数或级别名
+fn parse_level(level: &Bound<'_, PyAny>) -> PyResult<i32> {
+    if level.cast::<PyInt>().is_ok() {
+        return level.extract();
+    }
+    let name = level.extract::<String>()?;
+    match name.to_ascii_uppercase().as_str() {
+        "TRACE" => Ok(TRACE),
+        "DEBUG" => Ok(DEBUG),
+        "INFO" => Ok(INFO),
+        "WARN" | "WARNING" => Ok(WARNING),
+        "ERROR" => Ok(ERROR),
+        "CRITICAL" | "FATAL" | "OFF" => Ok(ERROR + 10),
+        _ => Err(PyValueError::new_err(format!("unknown log level: {name}"))),
+    }
+}
+
+#[pyfunction]
+#[pyo3(signature = (level=None))]
+/// 将Rust侧的日志转发到Python的`logging`, 可多次调用以修改级别, 默认为INFO
+pub fn setup_logging(py: Python<'_>, level: Option<&Bound<'_, PyAny>>) -> PyResult<()> {
+    let level = level.map(parse_level).transpose()?.unwrap_or(INFO);
+    py.import("logging")?
+        .call_method1("addLevelName", (TRACE, "TRACE"))?;
+    let installed = *INSTALLED.get_or_init(|| {
+        let subscriber = tracing_subscriber::registry().with(PyLoggingLayer);
+        tracing::subscriber::set_global_default(subscriber).is_ok()
+    });
+    if !installed {
+        return Err(GramError::new_err(
+            "a global tracing subscriber is already installed",
+        ));
+    }
+    LEVEL.store(level, Ordering::Relaxed);
+    // 已缓存的callsite需按新级别重新判断
+    tracing::callsite::rebuild_interest_cache();
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::tests::attach;
+    use pyo3::py_run;
+    use pyo3::types::PyString;
+    use std::sync::Once;
+
+    const TARGET: &str = "gram_pytools::logging::tests";
+
+    /// 不经过`setup_logging`安装全局subscriber并转发全部级别
+    /// 测试进程中只能安装一次, 之后`setup_logging`报告已安装其他subscriber
+    pub(crate) fn install() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            LEVEL.store(TRACE, Ordering::Relaxed);
+            let subscriber = tracing_subscriber::registry().with(PyLoggingLayer);
+            tracing::subscriber::set_global_default(subscriber).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_forward() {
+        install();
+        attach(|py| {
+            // 只收集本测试的logger, 不向上传递
+            let logger = py
+                .import("logging")
+                .unwrap()
+                .call_method1("getLogger", (logger_name(TARGET),))
+                .unwrap();
+            let handler = py
+                .import("logging.handlers")
+                .unwrap()
+                .call_method1("BufferingHandler", (100,))
+                .unwrap();
+            py_run!(
+                py,
+                logger handler,
+                r#"
+                logger.addHandler(handler)
+                logger.setLevel(1)
+                logger.propagate = False
+            "#
+            );
+            let subscriber = tracing_subscriber::registry().with(PyLoggingLayer);
+            tracing::subscriber::with_default(subscriber, || {
+                tracing::trace!(target: TARGET, "trace");
+                tracing::debug!(target: TARGET, "debug");
+                tracing::info!(target: TARGET, "info");
+                tracing::warn!(target: TARGET, "warn");
+                tracing::error!(target: TARGET, "error");
+                let outer = tracing::info_span!(target: TARGET, "outer");
+                let _outer = outer.enter();
+                let span = tracing::info_span!(target: TARGET, "render", len = 3, scale = 1.5);
+                let _span = span.enter();
+                tracing::info!(target: TARGET, id = 7, "hello {}", "world");
+                tracing::info!(target: TARGET, id = 8);
+            });
+            py_run!(
+                py,
+                handler,
+                r#"
+                records = handler.buffer
+                assert [r.levelno for r in records[:5]] == [5, 10, 20, 30, 40]
+                assert [r.getMessage() for r in records[:5]] == ["trace", "debug", "info", "warn", "error"]
+                assert all(r.name == "gram_pytools.logging.tests" for r in records)
+                assert records[0].pathname.endswith("logging.rs") and records[0].lineno > 0
+                assert records[5].getMessage() == "outer: render{len=3 scale=1.5}: hello world id=7"
+                assert records[6].getMessage() == "outer: render{len=3 scale=1.5}: id=8"
+                assert len(records) == 7
+            "#
+            );
+
+            // Python侧的logger级别同样生效
+            py_run!(
+                py,
+                logger handler,
+                r#"
+                handler.buffer.clear()
+                logger.setLevel(30)
+            "#
+            );
+            let subscriber = tracing_subscriber::registry().with(PyLoggingLayer);
+            tracing::subscriber::with_default(subscriber, || {
+                tracing::info!(target: TARGET, "info");
+                tracing::warn!(target: TARGET, "warn");
+            });
+            py_run!(
+                py,
+                handler,
+                r#"
+                assert [r.getMessage() for r in handler.buffer] == ["warn"]
+            "#
+            );
+        });
+    }
+
+    #[test]
+    fn test_logger_name() {
+        assert_eq!(
+            logger_name("gram_core::render::glyph"),
+            "gram_core.render.glyph"
+        );
+        assert_eq!(logger_name("gram_core"), "gram_core");
+    }
+
+    #[test]
+    fn test_setup_logging() {
+        install();
+        attach(|py| {
+            let level = |x: &str| parse_level(PyString::new(py, x).as_any());
+            assert_eq!(level("warn").unwrap(), WARNING);
+            assert_eq!(level("Trace").unwrap(), TRACE);
+            assert_eq!(level("off").unwrap(), ERROR + 10);
+            assert!(level("verbose")
+                .unwrap_err()
+                .is_instance_of::<PyValueError>(py));
+            assert_eq!(parse_level(PyInt::new(py, 15).as_any()).unwrap(), 15);
+
+            // 已安装其他全局subscriber时报错
+            let err = setup_logging(py, None).unwrap_err();
+            assert!(err.is_instance_of::<GramError>(py));
+            assert!(err.to_string().contains("already installed"));
+            assert_eq!(LEVEL.load(Ordering::Relaxed), TRACE);
+        });
+    }
+}
@@ -0,0 +1,154 @@
+use crate::py_err;
+use pyo3::prelude::*;
+use pyo3::IntoPyObjectExt;
+
+/// 释放GIL执行`job`, 期间其他Python线程可以运行
+/// 字体注册表等锁须在`job`中获取, 持有GIL等待锁时可能与需要GIL的线程死锁
+pub fn detach<T, F>(py: Python<'_>, job: F) -> PyResult<T>
+where
+    F: FnOnce() -> anyhow::Result<T> + Send,
+    T: Send,
+{
+    let ret = py.detach(job);
+    // 立即转发执行期间的日志, 不等待后台线程
+    crate::logging::flush(py);
+    ret.map_err(py_err)
+}
+
+/// 在rayon线程池中执行`job`, 返回当前事件循环中的`asyncio.Future`
+/// 结果通过`call_soon_threadsafe`在事件循环的线程中设置, 不阻塞事件循环
+pub fn spawn<'py, T, F>(py: Python<'py>, job: F) -> PyResult<Bound<'py, PyAny>>
+where
+    F: FnOnce() -> anyhow::Result<T> + Send + 'static,
+    T: for<'a> IntoPyObject<'a> + Send + 'static,
+{
+    let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+    let future = event_loop.call_method0("create_future")?;
+    let (event_loop, target) = (event_loop.unbind(), future.clone().unbind());
+    rayon::spawn(move || {
+        let result = job();
+        // 解释器正在退出时丢弃结果
+        Python::try_attach(|py| {
+            let event_loop = event_loop.bind(py);
+            if let Err(e) = complete(py, event_loop, target, result) {
+                e.write_unraisable(py, Some(event_loop));
+            }
+        });
+    });
+    Ok(future)
+}
+
+fn complete<T>(
+    py: Python<'_>,
+    event_loop: &Bound<'_, PyAny>,
+    future: Py<PyAny>,
+    result: anyhow::Result<T>,
+) -> PyResult<()>
+where
+    T: for<'a> IntoPyObject<'a>,
+{
+    // 事件循环已关闭时没有等待者
+    if event_loop.call_method0("is_closed")?.is_truthy()? {
+        return Ok(());
+    }
+    let (method, value) = match result.map_err(py_err).and_then(|x| x.into_py_any(py)) {
+        Ok(value) => ("set_result", value),
+        Err(e) => ("set_exception", e.into_value(py).into_any()),
+    };
+    let resolve = wrap_pyfunction!(resolve, py)?;
+    event_loop.call_method1("call_soon_threadsafe", (resolve, future, method, value))?;
+    Ok(())
+}
+
+/// 设置Future的结果, Future已被取消时忽略
+#[pyfunction]
+fn resolve(future: &Bound<'_, PyAny>, method: &str, value: &Bound<'_, PyAny>) -> PyResult<()> {
+    if !future.call_method0("done")?.is_truthy()? {
+        future.call_method1(method, (value,))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::attach;
+    use pyo3::py_run;
+    use std::time::Duration;
+
+    /// 在线程池中等待`ms`毫秒后返回`ms`
+    #[pyfunction]
+    fn sleep_async(py: Python<'_>, ms: u64) -> PyResult<Bound<'_, PyAny>> {
+        spawn(py, move || {
+            std::thread::sleep(Duration::from_millis(ms));
+            Ok(ms)
+        })
+    }
+
+    #[test]
+    fn test_spawn() {
+        attach(|py| {
+            let sleep_async = wrap_pyfunction!(sleep_async, py).unwrap();
+            py_run!(
+                py,
+                sleep_async,
+                r#"
+                async def main(sleep_async):
+                    import asyncio, gram_pytools
+                    png, extractions = await asyncio.gather(
+                        gram_pytools.render_text_async("hi", 32),
+                        gram_pytools.extract_async("see t.me/durov"),
+                    )
+                    assert png.startswith(b"\x89PNG")
+                    assert [(x.kind, x.value) for x in extractions] == [("username", "durov")]
+                    # 任务中的错误在await时抛出
+                    try:
+                        await gram_pytools.render_text_async("hi", 32, mode="cmyk")
+                        assert False
+                    except gram_pytools.RenderError as e:
+                        assert "cmyk" in str(e)
+
+                    # 已取消的Future在任务完成时被忽略, 事件循环中没有异常
+                    errors = []
+                    asyncio.get_running_loop().set_exception_handler(lambda _, x: errors.append(x))
+                    future = sleep_async(10)
+                    future.cancel()
+                    assert await sleep_async(50) == 50
+                    assert future.cancelled() and errors == []
+
+                import asyncio
+                asyncio.run(main(sleep_async))
+            "#
+            );
+        });
+    }
+
+    #[test]
+    fn test_closed_loop() {
+        attach(|py| {
+            let sleep_async = wrap_pyfunction!(sleep_async, py).unwrap();
+            // 事件循环关闭后任务才完成, 结果被丢弃而不是报告无法设置
+            py_run!(
+                py,
+                sleep_async,
+                r#"
+                import asyncio, sys, time
+
+                async def start(sleep_async):
+                    return sleep_async(20)
+
+                unraisable = []
+                sys.unraisablehook = unraisable.append
+                try:
+                    loop = asyncio.new_event_loop()
+                    future = loop.run_until_complete(start(sleep_async))
+                    loop.close()
+                    time.sleep(1)
+                finally:
+                    sys.unraisablehook = sys.__unraisablehook__
+                assert not future.done() and unraisable == []
+            "#
+            );
+        });
+    }
+}